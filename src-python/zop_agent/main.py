@@ -5,13 +5,15 @@ This service provides HTTP endpoints for the AI agent functionality,
 designed to run as a Tauri sidecar.
 """
 
+import json
 import os
 import sys
 from contextlib import asynccontextmanager
-from typing import Optional
+from typing import Iterator, Optional
 
 from fastapi import FastAPI, HTTPException
 from fastapi.middleware.cors import CORSMiddleware
+from fastapi.responses import StreamingResponse
 from pydantic import BaseModel, Field
 
 from .sop_agent import SopGeneratorAgent, SopGeneratorOutputSchema, SopStep
@@ -99,6 +101,65 @@ async def generate_sop(request: GenerateSopRequest):
         )
 
 
+@app.post("/generate-sop/stream")
+async def generate_sop_stream(request: GenerateSopRequest):
+    """
+    Generate an SOP, streaming it as newline-delimited JSON events.
+
+    Events are {"event": "title", "title": ...} once the title is known,
+    {"event": "step", "step": {...}} for every completed step, and then either
+    {"event": "done", "sop": {...}} or {"event": "error", "message": ...,
+    "status": <provider HTTP status or null>}. Closing the connection stops
+    the generation.
+    """
+    agent = SopGeneratorAgent(
+        base_url=request.config.base_url,
+        api_key=request.config.api_key,
+        model_name=request.config.model_name,
+    )
+    return StreamingResponse(
+        _stream_events(agent, request.prompt),
+        media_type="application/x-ndjson",
+    )
+
+
+def _stream_events(agent: SopGeneratorAgent, prompt: str) -> Iterator[str]:
+    def event(**fields) -> str:
+        return json.dumps(fields) + "\n"
+
+    title_sent = False
+    steps_sent = 0
+    latest = None
+    try:
+        for partial in agent.generate_partial(prompt):
+            latest = partial
+            steps = partial.steps or []
+            # The title is complete once the steps have started
+            if not title_sent and partial.title and steps:
+                title_sent = True
+                yield event(event="title", title=partial.title)
+            # A step is complete once the next one has started
+            while steps_sent < len(steps) - 1:
+                yield event(event="step", step=steps[steps_sent].model_dump())
+                steps_sent += 1
+
+        if latest is None:
+            raise ValueError("The model returned no output")
+        sop = SopGeneratorOutputSchema.model_validate(latest.model_dump())
+        if not title_sent:
+            yield event(event="title", title=sop.title)
+        for step in sop.steps[steps_sent:]:
+            yield event(event="step", step=step.model_dump())
+        yield event(event="done", sop=sop.model_dump())
+
+    except Exception as e:
+        yield event(
+            event="error",
+            message=f"Failed to generate SOP: {str(e)}",
+            status=getattr(e, "status_code", None),
+        )
+
+
 def main():
     """Entry point for the sidecar."""
     import uvicorn
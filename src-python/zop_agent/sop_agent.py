@@ -6,7 +6,7 @@ with steps that can be converted into a flowchart.
 """
 
 from pydantic import Field, BaseModel
-from typing import Optional
+from typing import Iterator, Optional
 import instructor
 from instructor import Mode
 import openai
@@ -116,3 +116,23 @@ You must respond with valid JSON matching this schema:
         )
 
         return response
+
+    def generate_partial(self, prompt: str) -> Iterator[SopGeneratorOutputSchema]:
+        """
+        Generate an SOP from a natural language prompt, streaming as it goes.
+
+        Args:
+            prompt: User's description of the process
+
+        Yields:
+            Partial SopGeneratorOutputSchema objects, each extending the last;
+            fields that are not complete yet are None
+        """
+        return self.client.chat.completions.create_partial(
+            model=self.model_name,
+            response_model=SopGeneratorOutputSchema,
+            messages=[
+                {"role": "system", "content": self.system_prompt},
+                {"role": "user", "content": prompt}
+            ],
+        )
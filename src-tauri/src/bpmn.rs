@@ -0,0 +1,613 @@
+//! Flowchart SOPs as BPMN 2.0 XML, for round trips through BPMN modelers.
+//!
+//! Steps map to flow elements (start: `startEvent`, read: `task`, form:
+//! `userTask`, decision: `exclusiveGateway`, end: `endEvent`) and edges to
+//! `sequenceFlow`s. Node positions become the DI bounds of each shape, so a
+//! diagram keeps its layout in both directions. Read/form content is written
+//! as `documentation`; form fields and edge conditions have no BPMN
+//! equivalent and travel as JSON in `zop:` extension elements, which modelers
+//! carry along unchanged.
+//!
+//! On import, other tasks, sub-processes and intermediate events become read
+//! steps, and every kind of gateway becomes a decision. Pools, lanes, data
+//! objects and annotations are dropped.
+
+use quick_xml::escape::{escape, unescape};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::name::ResolveResult;
+use quick_xml::NsReader;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+
+use crate::bundle::{BundledFlow, BundledSop, SopBundle};
+use crate::engine::EdgeCondition;
+use crate::error::{ZopError, ZopResult};
+use crate::flow::{FlowEdge, FlowNode, FlowPosition, NodeConfig, NodeShape};
+use crate::form::FormField;
+
+pub const FILE_EXTENSION: &str = "bpmn";
+
+const ZOP_NAMESPACE: &str = "urn:zop:bpmn:1";
+const PROCESS_ID: &str = "Process_1";
+/// Icon of SOPs created from a BPMN file
+const IMPORTED_ICON: &str = "file-text";
+/// Spacing of nodes imported from a file without diagram information
+const FALLBACK_SPACING: f64 = 220.0;
+const ORIGIN: f64 = 100.0;
+
+/// Suffixes BPMN files are saved with
+const FILE_SUFFIXES: &[&str] = &[".bpmn", ".bpmn20.xml", ".bpmn.xml"];
+
+/// Whether `path` names a BPMN file
+pub fn is_bpmn(path: &Path) -> bool {
+    file_stem(path).is_some()
+}
+
+/// File name of `path` without its BPMN suffix
+fn file_stem(path: &Path) -> Option<&str> {
+    let name = path.file_name()?.to_str()?;
+    FILE_SUFFIXES.iter().find_map(|suffix| {
+        let split = name.len().checked_sub(suffix.len())?;
+        let (stem, end) = (name.get(..split)?, &name[split..]);
+        end.eq_ignore_ascii_case(suffix).then_some(stem)
+    })
+}
+
+/// The flow of a flowchart SOP as a BPMN definitions document with one process
+pub fn to_xml(bundle: &SopBundle) -> ZopResult<String> {
+    if bundle.sop.item_type != "flowchart" {
+        return Err(ZopError::validation("Only flowchart SOPs can be exported as BPMN"));
+    }
+    let empty = BundledFlow {
+        nodes: Vec::new(),
+        edges: Vec::new(),
+    };
+    let flow = bundle.flow.as_ref().unwrap_or(&empty);
+    let ids = export_ids(flow);
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<bpmn:definitions xmlns:bpmn=\"http://www.omg.org/spec/BPMN/20100524/MODEL\" \
+         xmlns:bpmndi=\"http://www.omg.org/spec/BPMN/20100524/DI\" \
+         xmlns:dc=\"http://www.omg.org/spec/DD/20100524/DC\" \
+         xmlns:di=\"http://www.omg.org/spec/DD/20100524/DI\" \
+         xmlns:zop=\"{}\" id=\"Definitions_1\" targetNamespace=\"{}\" exporter=\"zop\">\n",
+        ZOP_NAMESPACE, ZOP_NAMESPACE
+    ));
+    xml.push_str(&format!(
+        "  <bpmn:process id=\"{}\" name=\"{}\" isExecutable=\"false\">\n",
+        PROCESS_ID,
+        escape(bundle.sop.name.as_str())
+    ));
+
+    for node in &flow.nodes {
+        let id = &ids.nodes[node.id.as_str()];
+        let tag = element(node.shape());
+        xml.push_str(&format!("    <bpmn:{} id=\"{}\" name=\"{}\">\n", tag, id, escape(node.label())));
+        if let Some(content) = node.content().filter(|c| !c.is_empty()) {
+            xml.push_str(&format!("      <bpmn:documentation>{}</bpmn:documentation>\n", escape(content)));
+        }
+        if !node.fields().is_empty() {
+            xml.push_str("      <bpmn:extensionElements>\n");
+            xml.push_str(&format!(
+                "        <zop:fields>{}</zop:fields>\n",
+                escape(serde_json::to_string(node.fields())?)
+            ));
+            xml.push_str("      </bpmn:extensionElements>\n");
+        }
+        for edge in &flow.edges {
+            if edge.target == node.id && ids.edges.contains_key(edge.id.as_str()) {
+                xml.push_str(&format!("      <bpmn:incoming>{}</bpmn:incoming>\n", ids.edges[edge.id.as_str()]));
+            }
+        }
+        for edge in &flow.edges {
+            if edge.source == node.id && ids.edges.contains_key(edge.id.as_str()) {
+                xml.push_str(&format!("      <bpmn:outgoing>{}</bpmn:outgoing>\n", ids.edges[edge.id.as_str()]));
+            }
+        }
+        xml.push_str(&format!("    </bpmn:{}>\n", tag));
+    }
+
+    for edge in &flow.edges {
+        let Some(id) = ids.edges.get(edge.id.as_str()) else {
+            continue;
+        };
+        let name = edge.label.as_deref().map(|label| format!(" name=\"{}\"", escape(label))).unwrap_or_default();
+        let refs = format!(
+            "id=\"{}\"{} sourceRef=\"{}\" targetRef=\"{}\"",
+            id, name, ids.nodes[edge.source.as_str()], ids.nodes[edge.target.as_str()]
+        );
+        match &edge.condition {
+            Some(condition) => {
+                xml.push_str(&format!("    <bpmn:sequenceFlow {}>\n", refs));
+                xml.push_str("      <bpmn:extensionElements>\n");
+                xml.push_str(&format!(
+                    "        <zop:condition>{}</zop:condition>\n",
+                    escape(serde_json::to_string(condition)?)
+                ));
+                xml.push_str("      </bpmn:extensionElements>\n");
+                xml.push_str("    </bpmn:sequenceFlow>\n");
+            }
+            None => xml.push_str(&format!("    <bpmn:sequenceFlow {} />\n", refs)),
+        }
+    }
+    xml.push_str("  </bpmn:process>\n");
+
+    xml.push_str("  <bpmndi:BPMNDiagram id=\"BPMNDiagram_1\">\n");
+    xml.push_str(&format!("    <bpmndi:BPMNPlane id=\"BPMNPlane_1\" bpmnElement=\"{}\">\n", PROCESS_ID));
+    let nodes: HashMap<&str, &FlowNode> = flow.nodes.iter().map(|n| (n.id.as_str(), n)).collect();
+    for node in &flow.nodes {
+        let id = &ids.nodes[node.id.as_str()];
+        let (width, height) = size(node.shape());
+        xml.push_str(&format!("      <bpmndi:BPMNShape id=\"{}_di\" bpmnElement=\"{}\">\n", id, id));
+        xml.push_str(&format!(
+            "        <dc:Bounds x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" />\n",
+            node.position.x, node.position.y, width, height
+        ));
+        xml.push_str("      </bpmndi:BPMNShape>\n");
+    }
+    for edge in &flow.edges {
+        let Some(id) = ids.edges.get(edge.id.as_str()) else {
+            continue;
+        };
+        // From the middle of the source's right side to the middle of the target's left side
+        let (source, target) = (nodes[edge.source.as_str()], nodes[edge.target.as_str()]);
+        let (source_width, source_height) = size(source.shape());
+        let (_, target_height) = size(target.shape());
+        xml.push_str(&format!("      <bpmndi:BPMNEdge id=\"{}_di\" bpmnElement=\"{}\">\n", id, id));
+        xml.push_str(&format!(
+            "        <di:waypoint x=\"{}\" y=\"{}\" />\n",
+            source.position.x + source_width,
+            source.position.y + source_height / 2.0
+        ));
+        xml.push_str(&format!(
+            "        <di:waypoint x=\"{}\" y=\"{}\" />\n",
+            target.position.x,
+            target.position.y + target_height / 2.0
+        ));
+        xml.push_str("      </bpmndi:BPMNEdge>\n");
+    }
+    xml.push_str("    </bpmndi:BPMNPlane>\n");
+    xml.push_str("  </bpmndi:BPMNDiagram>\n");
+    xml.push_str("</bpmn:definitions>\n");
+    Ok(xml)
+}
+
+pub fn write(bundle: &SopBundle, path: &Path) -> ZopResult<()> {
+    fs::write(path, to_xml(bundle)?)?;
+    Ok(())
+}
+
+/// A flowchart SOP from a BPMN file, named after its process or else the file
+pub fn read(path: &Path) -> ZopResult<SopBundle> {
+    let name = file_stem(path).unwrap_or("Imported process");
+    parse(&fs::read_to_string(path)?, name)
+}
+
+pub fn parse(xml: &str, fallback_name: &str) -> ZopResult<SopBundle> {
+    let mut reader = NsReader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut process_name: Option<String> = None;
+    let mut nodes: Vec<FlowNode> = Vec::new();
+    let mut edges: Vec<FlowEdge> = Vec::new();
+    let mut bounds: HashMap<String, FlowPosition> = HashMap::new();
+    let mut saw_definitions = false;
+    // The node or sequence flow whose children are being read
+    let mut current: Option<Current> = None;
+    // `bpmnElement` of the DI shape whose children are being read
+    let mut shape_of: Option<String> = None;
+
+    loop {
+        let (namespace, event) = reader.read_resolved_event().map_err(invalid)?;
+        let (start, is_empty) = match event {
+            Event::Start(start) => (start, false),
+            Event::Empty(start) => (start, true),
+            Event::End(end) => {
+                match end.local_name().as_ref() {
+                    b"BPMNShape" => shape_of = None,
+                    name if current.as_ref().is_some_and(|c| c.tag.as_bytes() == name) => {
+                        finish(current.take(), &mut nodes, &mut edges);
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+        let local = String::from_utf8_lossy(start.local_name().as_ref()).into_owned();
+        let is_zop = matches!(namespace, ResolveResult::Bound(ns) if ns.as_ref() == ZOP_NAMESPACE.as_bytes());
+
+        match local.as_str() {
+            "definitions" => saw_definitions = true,
+            "process" => {
+                if process_name.is_none() {
+                    process_name = attribute(&start, "name")?;
+                }
+            }
+            "sequenceFlow" => {
+                finish(current.take(), &mut nodes, &mut edges);
+                let id = required_attribute(&start, "id")?;
+                let edge = FlowEdge::new(
+                    id,
+                    required_attribute(&start, "sourceRef")?,
+                    required_attribute(&start, "targetRef")?,
+                    attribute(&start, "name")?.filter(|name| !name.is_empty()),
+                );
+                current = Some(Current::edge(edge));
+            }
+            "documentation" if !is_empty => {
+                let text = read_text(&mut reader, &start)?;
+                if let Some(Current { node: Some(node), .. }) = &mut current {
+                    if !text.is_empty() {
+                        node.data.config.get_or_insert_with(NodeConfig::default).content = Some(text);
+                    }
+                }
+            }
+            "fields" if is_zop && !is_empty => {
+                let text = read_text(&mut reader, &start)?;
+                if let Some(Current { node: Some(node), .. }) = &mut current {
+                    let fields: Vec<FormField> = serde_json::from_str(&text).map_err(invalid)?;
+                    node.data.config.get_or_insert_with(NodeConfig::default).fields = fields;
+                }
+            }
+            "condition" if is_zop && !is_empty => {
+                let text = read_text(&mut reader, &start)?;
+                if let Some(Current { edge: Some(edge), .. }) = &mut current {
+                    let condition: EdgeCondition = serde_json::from_str(&text).map_err(invalid)?;
+                    edge.condition = Some(condition);
+                }
+            }
+            "BPMNShape" => shape_of = attribute(&start, "bpmnElement")?,
+            "Bounds" => {
+                if let Some(element) = &shape_of {
+                    bounds.insert(
+                        element.clone(),
+                        FlowPosition {
+                            x: number_attribute(&start, "x")?,
+                            y: number_attribute(&start, "y")?,
+                        },
+                    );
+                }
+            }
+            other => {
+                if let Some(shape) = step_shape(other) {
+                    finish(current.take(), &mut nodes, &mut edges);
+                    let node = FlowNode::new(
+                        required_attribute(&start, "id")?,
+                        shape,
+                        attribute(&start, "name")?.unwrap_or_default(),
+                        FlowPosition::default(),
+                    );
+                    current = Some(Current::node(node, other));
+                }
+            }
+        }
+        if is_empty && current.as_ref().is_some_and(|c| c.tag == local) {
+            finish(current.take(), &mut nodes, &mut edges);
+        }
+    }
+
+    if !saw_definitions {
+        return Err(ZopError::validation("Not a BPMN file"));
+    }
+    if nodes.is_empty() {
+        return Err(ZopError::validation("The BPMN file has no process steps"));
+    }
+
+    for (i, node) in nodes.iter_mut().enumerate() {
+        node.position = bounds.remove(&node.id).unwrap_or(FlowPosition {
+            x: ORIGIN + i as f64 * FALLBACK_SPACING,
+            y: ORIGIN,
+        });
+    }
+    // Flows to or from dropped elements (data objects, pools) can't be drawn
+    let ids: Vec<String> = nodes.iter().map(|n| n.id.clone()).collect();
+    edges.retain(|edge| ids.contains(&edge.source) && ids.contains(&edge.target));
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let name = process_name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| fallback_name.trim().to_string());
+    let mut bundle = SopBundle::new(BundledSop {
+        name,
+        icon: IMPORTED_ICON.to_string(),
+        item_type: "flowchart".to_string(),
+        tags: Vec::new(),
+        created_at: now.clone(),
+        updated_at: now,
+    });
+    bundle.flow = Some(BundledFlow { nodes, edges });
+    Ok(bundle)
+}
+
+/// BPMN ids of the nodes and edges, which must be XML names
+struct ExportIds<'a> {
+    nodes: HashMap<&'a str, String>,
+    /// Only edges whose endpoints both exist
+    edges: HashMap<&'a str, String>,
+}
+
+fn export_ids(flow: &BundledFlow) -> ExportIds<'_> {
+    let node_ids: Vec<&str> = flow.nodes.iter().map(|node| node.id.as_str()).collect();
+    let edges: Vec<&FlowEdge> = flow
+        .edges
+        .iter()
+        .filter(|edge| node_ids.contains(&edge.source.as_str()) && node_ids.contains(&edge.target.as_str()))
+        .collect();
+    let edge_ids: Vec<&str> = edges.iter().map(|edge| edge.id.as_str()).collect();
+
+    // Ids that are already valid claim their names before any are generated
+    let mut taken = XmlIds::default();
+    let kept_nodes = taken.keep(&node_ids);
+    let kept_edges = taken.keep(&edge_ids);
+    let nodes = taken.generate(kept_nodes, "Node");
+    let edges = taken.generate(kept_edges, "Flow");
+    ExportIds {
+        nodes: node_ids.into_iter().zip(nodes).collect(),
+        edges: edge_ids.into_iter().zip(edges).collect(),
+    }
+}
+
+/// XML ids in use in an exported document. Every element also gets a `{id}_di`
+/// diagram shape, so that name is claimed along with it.
+struct XmlIds(HashSet<String>);
+
+impl Default for XmlIds {
+    fn default() -> Self {
+        let fixed = ["Definitions_1", PROCESS_ID, "BPMNDiagram_1", "BPMNPlane_1"];
+        Self(fixed.iter().map(|id| id.to_string()).collect())
+    }
+}
+
+impl XmlIds {
+    fn claim(&mut self, id: &str) -> bool {
+        let di = format!("{}_di", id);
+        if self.0.contains(id) || self.0.contains(&di) {
+            return false;
+        }
+        self.0.insert(id.to_string());
+        self.0.insert(di);
+        true
+    }
+
+    /// Each of `ids` that is a valid XML name not already in use
+    fn keep(&mut self, ids: &[&str]) -> Vec<Option<String>> {
+        ids.iter()
+            .map(|id| (is_xml_name(id) && self.claim(id)).then(|| id.to_string()))
+            .collect()
+    }
+
+    /// The ids `keep` left out replaced by `{prefix}_{n}`, with the first `n` not in use
+    fn generate(&mut self, kept: Vec<Option<String>>, prefix: &str) -> Vec<String> {
+        let mut next = 0;
+        kept.into_iter()
+            .map(|kept| {
+                if let Some(id) = kept {
+                    return id;
+                }
+                loop {
+                    let generated = format!("{}_{}", prefix, next);
+                    next += 1;
+                    if self.claim(&generated) {
+                        return generated;
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+fn is_xml_name(id: &str) -> bool {
+    let mut chars = id.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+fn element(shape: NodeShape) -> &'static str {
+    match shape {
+        NodeShape::Start => "startEvent",
+        NodeShape::Read => "task",
+        NodeShape::Form => "userTask",
+        NodeShape::Decision => "exclusiveGateway",
+        NodeShape::End => "endEvent",
+    }
+}
+
+/// Step kind of a BPMN flow element, or `None` for elements that aren't steps
+fn step_shape(element: &str) -> Option<NodeShape> {
+    match element {
+        "startEvent" => Some(NodeShape::Start),
+        "endEvent" => Some(NodeShape::End),
+        "userTask" => Some(NodeShape::Form),
+        "exclusiveGateway" | "inclusiveGateway" | "parallelGateway" | "eventBasedGateway" | "complexGateway" => {
+            Some(NodeShape::Decision)
+        }
+        "task" | "manualTask" | "serviceTask" | "scriptTask" | "sendTask" | "receiveTask" | "businessRuleTask"
+        | "callActivity" | "subProcess" | "intermediateCatchEvent" | "intermediateThrowEvent" | "boundaryEvent" => {
+            Some(NodeShape::Read)
+        }
+        _ => None,
+    }
+}
+
+/// Default size of each shape in BPMN modelers
+fn size(shape: NodeShape) -> (f64, f64) {
+    match shape {
+        NodeShape::Start | NodeShape::End => (36.0, 36.0),
+        NodeShape::Read | NodeShape::Form => (100.0, 80.0),
+        NodeShape::Decision => (50.0, 50.0),
+    }
+}
+
+struct Current {
+    /// Local name of the element, to recognize its end tag
+    tag: String,
+    node: Option<FlowNode>,
+    edge: Option<FlowEdge>,
+}
+
+impl Current {
+    fn node(node: FlowNode, tag: &str) -> Self {
+        Self {
+            tag: tag.to_string(),
+            node: Some(node),
+            edge: None,
+        }
+    }
+
+    fn edge(edge: FlowEdge) -> Self {
+        Self {
+            tag: "sequenceFlow".to_string(),
+            node: None,
+            edge: Some(edge),
+        }
+    }
+}
+
+fn finish(current: Option<Current>, nodes: &mut Vec<FlowNode>, edges: &mut Vec<FlowEdge>) {
+    if let Some(current) = current {
+        nodes.extend(current.node);
+        edges.extend(current.edge);
+    }
+}
+
+fn read_text(reader: &mut NsReader<&[u8]>, start: &BytesStart) -> ZopResult<String> {
+    let raw = reader.read_text(start.name()).map_err(invalid)?;
+    let raw = raw.trim();
+    if let Some(cdata) = raw.strip_prefix("<![CDATA[").and_then(|r| r.strip_suffix("]]>")) {
+        return Ok(cdata.trim().to_string());
+    }
+    Ok(unescape(raw).map_err(invalid)?.trim().to_string())
+}
+
+fn attribute(start: &BytesStart, name: &str) -> ZopResult<Option<String>> {
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(invalid)?;
+        if attribute.key.local_name().as_ref() == name.as_bytes() {
+            return Ok(Some(attribute.unescape_value().map_err(invalid)?.into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+fn required_attribute(start: &BytesStart, name: &str) -> ZopResult<String> {
+    attribute(start, name)?.ok_or_else(|| {
+        ZopError::validation(format!(
+            "Not a valid BPMN file: <{}> has no {}",
+            String::from_utf8_lossy(start.local_name().as_ref()),
+            name
+        ))
+    })
+}
+
+fn number_attribute(start: &BytesStart, name: &str) -> ZopResult<f64> {
+    let value = attribute(start, name)?.unwrap_or_default();
+    value.trim().parse().map_err(invalid)
+}
+
+fn invalid(e: impl Display) -> ZopError {
+    ZopError::validation(format!("Not a valid BPMN file: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn node(id: &str, shape: NodeShape, label: &str, x: f64) -> FlowNode {
+        FlowNode::new(id.to_string(), shape, label.to_string(), FlowPosition { x, y: 40.0 })
+    }
+
+    fn edge(id: &str, source: &str, target: &str) -> FlowEdge {
+        FlowEdge::new(id.to_string(), source.to_string(), target.to_string(), None)
+    }
+
+    fn bundle(nodes: Vec<FlowNode>, edges: Vec<FlowEdge>) -> SopBundle {
+        let mut bundle = SopBundle::new(BundledSop {
+            name: "Expenses & refunds".to_string(),
+            icon: "zap".to_string(),
+            item_type: "flowchart".to_string(),
+            tags: Vec::new(),
+            created_at: String::new(),
+            updated_at: String::new(),
+        });
+        bundle.flow = Some(BundledFlow { nodes, edges });
+        bundle
+    }
+
+    #[test]
+    fn round_trips_zop_extensions() {
+        let mut read = node("read", NodeShape::Read, "Read the <policy>", 200.0);
+        read.data.config = Some(NodeConfig {
+            content: Some("Receipts over \"50\" & up need a manager".to_string()),
+            ..NodeConfig::default()
+        });
+        let mut form = node("form", NodeShape::Form, "Claim", 400.0);
+        form.data.config = Some(serde_json::from_value(json!({
+            "fields": [
+                {"id": "amount", "label": "Amount", "type": "number", "required": true},
+                {"id": "kind", "label": "Kind", "type": "select", "options": ["travel", "food"]}
+            ]
+        }))
+        .unwrap());
+        let mut over = FlowEdge::new("over".into(), "check".into(), "manager".into(), Some("> 50".into()));
+        over.condition = Some(serde_json::from_value(json!({"field": "amount", "op": "gt", "value": 50})).unwrap());
+        let nodes = vec![
+            node("start", NodeShape::Start, "Start", 0.0),
+            read,
+            form,
+            node("check", NodeShape::Decision, "Over 50?", 600.0),
+            node("manager", NodeShape::Read, "Manager approves", 800.0),
+            node("end", NodeShape::End, "Done", 1000.0),
+        ];
+        let edges = vec![
+            edge("e1", "start", "read"),
+            edge("e2", "read", "form"),
+            edge("e3", "form", "check"),
+            over,
+            edge("e4", "check", "end"),
+            edge("e5", "manager", "end"),
+        ];
+        let original = bundle(nodes, edges);
+
+        let imported = parse(&to_xml(&original).unwrap(), "file").unwrap();
+        assert_eq!(imported.sop.name, original.sop.name);
+        let (flow, original) = (imported.flow.unwrap(), original.flow.unwrap());
+        assert_eq!(flow.nodes, original.nodes);
+        assert_eq!(flow.edges, original.edges);
+    }
+
+    #[test]
+    fn generated_ids_do_not_collide() {
+        let nodes = vec![
+            node("1st", NodeShape::Start, "a", 0.0),
+            node("Node_0", NodeShape::Read, "b", 1.0),
+            node("Process_1", NodeShape::Read, "c", 2.0),
+            node("Node_1_di", NodeShape::Read, "d", 3.0),
+            node("has space", NodeShape::End, "e", 4.0),
+        ];
+        let edges = vec![edge("Node_2", "1st", "Node_0"), edge("e 2", "Node_0", "has space")];
+        let bundle = bundle(nodes, edges);
+        let ids = export_ids(bundle.flow.as_ref().unwrap());
+        let mut all: Vec<&String> = ids.nodes.values().chain(ids.edges.values()).collect();
+        all.sort();
+        assert_eq!(
+            all,
+            ["Flow_0", "Node_0", "Node_1_di", "Node_2", "Node_3", "Node_4", "Node_5"]
+        );
+        assert_eq!(ids.nodes["Node_0"], "Node_0");
+        assert_eq!(ids.edges["Node_2"], "Node_2");
+    }
+
+    #[test]
+    fn rejects_files_without_steps() {
+        assert!(parse("<root/>", "file").is_err());
+        let empty = "<bpmn:definitions xmlns:bpmn=\"http://www.omg.org/spec/BPMN/20100524/MODEL\"><bpmn:process id=\"p\"/></bpmn:definitions>";
+        assert!(parse(empty, "file").is_err());
+    }
+}
@@ -0,0 +1,176 @@
+//! Portable `.zop.json` bundles for moving an SOP between machines.
+//!
+//! A bundle holds everything that belongs to one SOP but none of its database
+//! ids: todos are listed in order, flow nodes keep their own string ids, and
+//! revisions are identified by their number. Importing assigns fresh ids.
+//! Folders are local to a workspace and are not part of a bundle; tags travel
+//! by name.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+use crate::error::{ZopError, ZopResult};
+use crate::flow::{FlowEdge, FlowNode};
+
+pub const FILE_EXTENSION: &str = "zop.json";
+
+const FORMAT: &str = "zop-sop";
+/// Bumped whenever a change would make older versions misread a bundle
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SopBundle {
+    /// Always `zop-sop`; tells a bundle apart from any other JSON file
+    pub format: String,
+    pub version: u32,
+    pub exported_at: String,
+    pub sop: BundledSop,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub todos: Vec<BundledTodo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flow: Option<BundledFlow>,
+    /// Earlier versions of the flow, oldest first; only present when requested on export
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub revisions: Vec<BundledRevision>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundledSop {
+    pub name: String,
+    pub icon: String,
+    pub item_type: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundledTodo {
+    pub content: String,
+    #[serde(default)]
+    pub completed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundledFlow {
+    pub nodes: Vec<FlowNode>,
+    pub edges: Vec<FlowEdge>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundledRevision {
+    pub revision: i64,
+    pub nodes: Vec<FlowNode>,
+    pub edges: Vec<FlowEdge>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restored_from: Option<i64>,
+    pub created_at: String,
+}
+
+impl SopBundle {
+    pub fn new(sop: BundledSop) -> Self {
+        Self {
+            format: FORMAT.to_string(),
+            version: VERSION,
+            exported_at: chrono::Utc::now().to_rfc3339(),
+            sop,
+            todos: Vec::new(),
+            flow: None,
+            revisions: Vec::new(),
+        }
+    }
+
+    /// Parse a bundle, rejecting other JSON files and bundles from a newer version of the app
+    pub fn from_json(text: &str) -> ZopResult<Self> {
+        let value: Value = serde_json::from_str(text)
+            .map_err(|e| ZopError::validation(format!("Not a valid SOP bundle: {}", e)))?;
+
+        if value.get("format").and_then(Value::as_str) != Some(FORMAT) {
+            return Err(ZopError::validation("Not an SOP bundle"));
+        }
+        let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+        if version > VERSION as u64 {
+            return Err(ZopError::validation(format!(
+                "This bundle was written by a newer version of the app (format version {}, supported up to {})",
+                version, VERSION
+            )));
+        }
+
+        let bundle: SopBundle = serde_json::from_value(value)
+            .map_err(|e| ZopError::validation(format!("Not a valid SOP bundle: {}", e)))?;
+        match bundle.sop.item_type.as_str() {
+            "todo" | "flowchart" => Ok(bundle),
+            other => Err(ZopError::validation(format!("Unknown SOP type {}", other))),
+        }
+    }
+
+    pub fn read(path: &Path) -> ZopResult<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn write(&self, path: &Path) -> ZopResult<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+
+    fn bundle(item_type: &str) -> Value {
+        serde_json::to_value(SopBundle::new(BundledSop {
+            name: "Checklist".to_string(),
+            icon: "list".to_string(),
+            item_type: item_type.to_string(),
+            tags: vec![],
+            created_at: String::new(),
+            updated_at: String::new(),
+        }))
+        .unwrap()
+    }
+
+    fn rejection(value: &Value) -> String {
+        let error = SopBundle::from_json(&value.to_string()).unwrap_err();
+        assert_eq!(error.code, ErrorCode::Validation);
+        error.message
+    }
+
+    #[test]
+    fn reads_its_own_bundles() {
+        let parsed = SopBundle::from_json(&bundle("flowchart").to_string()).unwrap();
+        assert_eq!(parsed.sop.item_type, "flowchart");
+        assert_eq!(parsed.version, VERSION);
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        let error = SopBundle::from_json("{\"format\":").unwrap_err();
+        assert!(error.message.starts_with("Not a valid SOP bundle"), "{}", error.message);
+    }
+
+    #[test]
+    fn rejects_another_format() {
+        let mut value = bundle("todo");
+        value["format"] = "something-else".into();
+        assert_eq!(rejection(&value), "Not an SOP bundle");
+        value.as_object_mut().unwrap().remove("format");
+        assert_eq!(rejection(&value), "Not an SOP bundle");
+    }
+
+    #[test]
+    fn rejects_a_newer_version() {
+        let mut value = bundle("todo");
+        value["version"] = (VERSION + 1).into();
+        assert!(rejection(&value).contains("newer version"));
+    }
+
+    #[test]
+    fn rejects_an_unknown_sop_type() {
+        assert_eq!(rejection(&bundle("kanban")), "Unknown SOP type kanban");
+    }
+}
@@ -1,351 +1,668 @@
-use rusqlite::{Connection, Result as SqliteResult};
-use std::fs;
-use std::path::PathBuf;
+use rusqlite::{Connection, Result as SqliteResult, Transaction, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
 
-use crate::{CreateSopItem, CreateTodoItem, FlowData, SopItem, TodoItem, AiConfig, SaveAiConfig};
+use crate::bundle::{BundledFlow, BundledRevision, BundledSop, BundledTodo, SopBundle};
+use crate::flow::{FlowEdge, FlowNode, NodeShape};
+use crate::error::{ZopError, ZopResult};
+use crate::migrations;
+use crate::search;
+use crate::{ActiveFlowRun, CreateSopItem, CreateTodoItem, FlowData, Folder, FlowRevision, FlowRevisionSummary, FlowRun, FlowRunInput, FlowRunStep, SearchHit, SopItem, SopItemFilter, Tag, TodoItem, TodoListState, AiConfig, SaveAiConfig};
 
+/// Idle connections kept open for reuse; more are opened while all of them are busy
+const MAX_IDLE_CONNECTIONS: usize = 4;
+
+/// How long a write waits for another connection's write to finish before failing as locked
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A small pool of connections to one database file in WAL mode, so reads
+/// don't wait on each other or on a write in progress. Writers still take
+/// turns, waiting up to `BUSY_TIMEOUT` for the file lock.
 pub struct Database {
-    conn: Connection,
+    path: PathBuf,
+    idle: Mutex<Vec<Connection>>,
 }
 
-impl Database {
-    pub fn new() -> SqliteResult<Self> {
-        let db_path = Self::get_db_path()?;
-        let conn = Connection::open(&db_path)?;
-        let db = Self { conn };
-        db.init_tables()?;
-        Ok(db)
-    }
-
-    fn get_db_path() -> SqliteResult<PathBuf> {
-        let home_dir = dirs::home_dir().expect("Could not find home directory");
-        let zop_dir = home_dir.join(".zop");
+/// A connection checked out of the pool, returned to it when dropped
+struct PooledConnection<'a> {
+    db: &'a Database,
+    conn: Option<Connection>,
+}
 
-        if !zop_dir.exists() {
-            fs::create_dir_all(&zop_dir).expect("Could not create .zop directory");
-        }
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
 
-        Ok(zop_dir.join("zop.db"))
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("connection is present until drop")
     }
+}
 
-    fn init_tables(&self) -> SqliteResult<()> {
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS sop_items (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                name TEXT NOT NULL,
-                icon TEXT NOT NULL,
-                item_type TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL,
-                deleted_at TEXT
-            )",
-            [],
-        )?;
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            let mut idle = self.db.idle.lock().unwrap_or_else(|e| e.into_inner());
+            if idle.len() < MAX_IDLE_CONNECTIONS {
+                idle.push(conn);
+            }
+        }
+    }
+}
 
-        // Add deleted_at column if not exists (for migration)
-        let _ = self.conn.execute(
-            "ALTER TABLE sop_items ADD COLUMN deleted_at TEXT",
-            [],
-        );
+impl Database {
+    /// Open the database at `path` and apply any pending schema migrations.
+    /// `:memory:` opens a private in-memory database shared by the pool's connections.
+    pub fn open(path: &Path) -> ZopResult<Self> {
+        let path = if path == Path::new(":memory:") {
+            static NEXT_MEMORY_DB: AtomicUsize = AtomicUsize::new(0);
+            let n = NEXT_MEMORY_DB.fetch_add(1, Ordering::Relaxed);
+            PathBuf::from(format!("file:zop-memory-{}?mode=memory&cache=shared", n))
+        } else {
+            path.to_path_buf()
+        };
 
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS todo_items (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                sop_id INTEGER NOT NULL,
-                content TEXT NOT NULL,
-                completed INTEGER NOT NULL DEFAULT 0,
-                sort_order INTEGER NOT NULL DEFAULT 0,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL,
-                FOREIGN KEY (sop_id) REFERENCES sop_items(id) ON DELETE CASCADE
-            )",
-            [],
-        )?;
+        let mut conn = connect(&path)?;
+        // Persisted in the file, so only needs setting once; in-memory databases stay in "memory" mode
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        migrations::migrate(&mut conn)?;
 
-        // Add sort_order column if not exists (for migration)
-        let _ = self.conn.execute(
-            "ALTER TABLE todo_items ADD COLUMN sort_order INTEGER NOT NULL DEFAULT 0",
-            [],
-        );
+        Ok(Self {
+            path,
+            idle: Mutex::new(vec![conn]),
+        })
+    }
 
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS flow_data (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                sop_id INTEGER NOT NULL UNIQUE,
-                nodes TEXT NOT NULL,
-                edges TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL,
-                FOREIGN KEY (sop_id) REFERENCES sop_items(id) ON DELETE CASCADE
-            )",
-            [],
-        )?;
+    /// Check a connection out of the pool, opening a new one if none is idle
+    fn conn(&self) -> SqliteResult<PooledConnection<'_>> {
+        let idle = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => connect(&self.path)?,
+        };
+        Ok(PooledConnection { db: self, conn: Some(conn) })
+    }
 
-        // Migration: Drop old ai_config table if it has the old schema (with provider column)
-        let has_old_schema: bool = self.conn
-            .prepare("SELECT provider FROM ai_config LIMIT 1")
-            .is_ok();
+    /// Run `f` in a transaction: its writes are committed together, or not at all if it fails.
+    /// The write lock is taken up front, so a concurrent writer makes this wait rather than fail.
+    pub fn transaction<T>(&self, f: impl FnOnce(&Transaction) -> SqliteResult<T>) -> SqliteResult<T> {
+        let conn = self.conn()?;
+        let tx = Transaction::new_unchecked(&conn, TransactionBehavior::Immediate)?;
+        let value = f(&tx)?;
+        tx.commit()?;
+        Ok(value)
+    }
 
-        if has_old_schema {
-            let _ = self.conn.execute("DROP TABLE ai_config", []);
-        }
+    pub fn create_sop_item(&self, item: &CreateSopItem) -> SqliteResult<SopItem> {
+        let conn = self.conn()?;
+        insert_sop_item(&conn, item)
+    }
 
-        self.conn.execute(
-            "CREATE TABLE IF NOT EXISTS ai_config (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                base_url TEXT NOT NULL,
-                api_key TEXT NOT NULL,
-                model_name TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                updated_at TEXT NOT NULL
-            )",
-            [],
-        )?;
+    /// Create a flowchart SOP together with its graph
+    pub fn create_sop_with_flow(&self, item: &CreateSopItem, nodes: &[FlowNode], edges: &[FlowEdge]) -> SqliteResult<SopItem> {
+        let nodes = to_json_column(nodes)?;
+        let edges = to_json_column(edges)?;
 
-        Ok(())
+        self.transaction(|tx| {
+            let sop = insert_sop_item(tx, item)?;
+            write_flow_data(tx, sop.id, &nodes, &edges, None)?;
+            Ok(sop)
+        })
     }
 
-    pub fn create_sop_item(&self, item: &CreateSopItem) -> SqliteResult<SopItem> {
-        let now = chrono::Utc::now().to_rfc3339();
-        self.conn.execute(
-            "INSERT INTO sop_items (name, icon, item_type, created_at, updated_at, deleted_at) VALUES (?1, ?2, ?3, ?4, ?5, NULL)",
-            (&item.name, &item.icon, &item.item_type, &now, &now),
-        )?;
-
-        let id = self.conn.last_insert_rowid();
-        Ok(SopItem {
-            id,
-            name: item.name.clone(),
-            icon: item.icon.clone(),
-            item_type: item.item_type.clone(),
-            created_at: now.clone(),
-            updated_at: now,
-            deleted_at: None,
+    /// Create a todo SOP together with its items, in the given order
+    pub fn create_sop_with_todos(&self, item: &CreateSopItem, contents: &[String]) -> SqliteResult<SopItem> {
+        self.transaction(|tx| {
+            let sop = insert_sop_item(tx, item)?;
+            for content in contents {
+                insert_todo_item_at_end(tx, sop.id, content)?;
+            }
+            Ok(sop)
         })
     }
 
-    pub fn get_all_sop_items(&self) -> SqliteResult<Vec<SopItem>> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT id, name, icon, item_type, created_at, updated_at, deleted_at FROM sop_items WHERE deleted_at IS NULL ORDER BY created_at DESC")?;
+    /// SOPs not in the trash, newest first, narrowed down by `filter`
+    pub fn get_all_sop_items(&self, filter: &SopItemFilter) -> SqliteResult<Vec<SopItem>> {
+        let conn = self.conn()?;
+        let mut sql = format!("SELECT {} FROM sop_items WHERE deleted_at IS NULL", SOP_ITEM_COLUMNS);
+        let mut params: Vec<i64> = Vec::new();
 
-        let items = stmt.query_map([], |row| {
-            Ok(SopItem {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                icon: row.get(2)?,
-                item_type: row.get(3)?,
-                created_at: row.get(4)?,
-                updated_at: row.get(5)?,
-                deleted_at: row.get(6)?,
-            })
-        })?;
+        if let Some(folder_id) = filter.folder_id {
+            params.push(folder_id);
+            if filter.recursive {
+                sql.push_str(&format!(
+                    " AND folder_id IN (
+                        WITH RECURSIVE subtree(id) AS (
+                            SELECT ?{n} UNION SELECT folders.id FROM folders JOIN subtree ON folders.parent_id = subtree.id
+                        )
+                        SELECT id FROM subtree
+                    )",
+                    n = params.len()
+                ));
+            } else {
+                sql.push_str(&format!(" AND folder_id = ?{}", params.len()));
+            }
+        } else if filter.unfiled {
+            sql.push_str(" AND folder_id IS NULL");
+        }
+
+        if !filter.tag_ids.is_empty() {
+            let placeholders: Vec<String> = filter
+                .tag_ids
+                .iter()
+                .map(|tag_id| {
+                    params.push(*tag_id);
+                    format!("?{}", params.len())
+                })
+                .collect();
+            sql.push_str(&format!(
+                " AND id IN (SELECT sop_id FROM sop_item_tags WHERE tag_id IN ({}) GROUP BY sop_id HAVING COUNT(*) = {})",
+                placeholders.join(", "),
+                filter.tag_ids.len()
+            ));
+        }
+        sql.push_str(" ORDER BY created_at DESC");
+
+        let mut stmt = conn.prepare(&sql)?;
+        let items = stmt.query_map(rusqlite::params_from_iter(&params), sop_item_from_row)?;
 
         items.collect()
     }
 
     pub fn soft_delete_sop_item(&self, id: i64) -> SqliteResult<()> {
+        let conn = self.conn()?;
         let now = chrono::Utc::now().to_rfc3339();
-        self.conn
+        conn
             .execute("UPDATE sop_items SET deleted_at = ?1, updated_at = ?1 WHERE id = ?2", (&now, id))?;
         Ok(())
     }
 
     pub fn rename_sop_item(&self, id: i64, name: &str) -> SqliteResult<SopItem> {
+        let conn = self.conn()?;
         let now = chrono::Utc::now().to_rfc3339();
-        self.conn.execute(
+        conn.execute(
             "UPDATE sop_items SET name = ?1, updated_at = ?2 WHERE id = ?3",
             (name, &now, id),
         )?;
 
-        let mut stmt = self.conn.prepare(
-            "SELECT id, name, icon, item_type, created_at, updated_at, deleted_at FROM sop_items WHERE id = ?1"
-        )?;
+        get_sop_item(&conn, id)
+    }
 
-        stmt.query_row([id], |row| {
-            Ok(SopItem {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                icon: row.get(2)?,
-                item_type: row.get(3)?,
-                created_at: row.get(4)?,
-                updated_at: row.get(5)?,
-                deleted_at: row.get(6)?,
-            })
-        })
+    /// Search SOP names, todo contents and flow node labels and contents. Every term
+    /// of `query` must occur in a hit; SOPs in the trash are left out.
+    pub fn search(&self, query: &str, limit: u32) -> SqliteResult<Vec<SearchHit>> {
+        let terms = search::terms(query);
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+        let conn = self.conn()?;
+
+        if let Some(fts_query) = search::fts_query(&terms) {
+            let mut stmt = conn.prepare(
+                "SELECT search_index.sop_id, sop_items.name, sop_items.item_type, kind, target_id,
+                        snippet(search_index, -1, ?2, ?3, '…', 12), bm25(search_index, 4.0, 1.0) AS rank
+                 FROM search_index JOIN sop_items ON sop_items.id = search_index.sop_id
+                 WHERE search_index MATCH ?1 AND sop_items.deleted_at IS NULL
+                 ORDER BY rank LIMIT ?4",
+            )?;
+            let hits = stmt.query_map((&fts_query, search::MARK_OPEN, search::MARK_CLOSE, limit), |row| {
+                let rank: f64 = row.get(6)?;
+                search_hit_from_row(row, row.get(5)?, -rank)
+            })?;
+            return hits.collect();
+        }
+
+        // Too short for the trigram index: scan, and list SOP names before their contents
+        let mut sql = String::from(
+            "SELECT search_index.sop_id, sop_items.name, sop_items.item_type, kind, target_id, title, body
+             FROM search_index JOIN sop_items ON sop_items.id = search_index.sop_id
+             WHERE sop_items.deleted_at IS NULL",
+        );
+        for i in 1..=terms.len() {
+            sql.push_str(&format!(" AND (title LIKE ?{i} ESCAPE '\\' OR body LIKE ?{i} ESCAPE '\\')"));
+        }
+        sql.push_str(&format!(" ORDER BY kind != 'sop', sop_items.name LIMIT {}", limit));
+
+        let patterns: Vec<String> = terms.iter().map(|term| search::like_pattern(term)).collect();
+        let mut stmt = conn.prepare(&sql)?;
+        let hits = stmt.query_map(rusqlite::params_from_iter(&patterns), |row| {
+            let title: String = row.get(5)?;
+            let body: String = row.get(6)?;
+            let title_matches = terms.iter().any(|term| title.to_lowercase().contains(&term.to_lowercase()));
+            let text = if title_matches || body.is_empty() { &title } else { &body };
+            search_hit_from_row(row, search::highlight(text, &terms), 0.0)
+        })?;
+        hits.collect()
     }
 
     pub fn get_deleted_sop_items(&self) -> SqliteResult<Vec<SopItem>> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT id, name, icon, item_type, created_at, updated_at, deleted_at FROM sop_items WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC")?;
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM sop_items WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC",
+            SOP_ITEM_COLUMNS
+        ))?;
 
-        let items = stmt.query_map([], |row| {
-            Ok(SopItem {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                icon: row.get(2)?,
-                item_type: row.get(3)?,
-                created_at: row.get(4)?,
-                updated_at: row.get(5)?,
-                deleted_at: row.get(6)?,
-            })
-        })?;
+        let items = stmt.query_map([], sop_item_from_row)?;
 
         items.collect()
     }
 
     pub fn restore_sop_item(&self, id: i64) -> SqliteResult<SopItem> {
+        let conn = self.conn()?;
         let now = chrono::Utc::now().to_rfc3339();
-        self.conn
+        conn
             .execute("UPDATE sop_items SET deleted_at = NULL, updated_at = ?1 WHERE id = ?2", (&now, id))?;
 
-        let mut stmt = self.conn.prepare(
-            "SELECT id, name, icon, item_type, created_at, updated_at, deleted_at FROM sop_items WHERE id = ?1"
-        )?;
-
-        stmt.query_row([id], |row| {
-            Ok(SopItem {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                icon: row.get(2)?,
-                item_type: row.get(3)?,
-                created_at: row.get(4)?,
-                updated_at: row.get(5)?,
-                deleted_at: row.get(6)?,
-            })
-        })
+        get_sop_item(&conn, id)
     }
 
+    /// Delete an SOP for good. Its todos, undo history, flow, revisions, runs
+    /// and tag assignments go with it through the schema's cascades.
     pub fn permanently_delete_sop_item(&self, id: i64) -> SqliteResult<()> {
-        self.conn
-            .execute("DELETE FROM sop_items WHERE id = ?1", [id])?;
+        let conn = self.conn()?;
+        conn.execute("DELETE FROM sop_items WHERE id = ?1", [id])?;
         Ok(())
     }
 
-    pub fn delete_sop_item(&self, id: i64) -> SqliteResult<()> {
-        self.conn
-            .execute("DELETE FROM sop_items WHERE id = ?1", [id])?;
-        Ok(())
+    /// Everything belonging to an SOP, ready to be written to a bundle
+    pub fn export_sop_bundle(&self, id: i64, include_revisions: bool) -> SqliteResult<SopBundle> {
+        let conn = self.conn()?;
+        let item = get_sop_item(&conn, id)?;
+
+        let mut stmt = conn.prepare(
+            "SELECT tags.name FROM tags JOIN sop_item_tags ON sop_item_tags.tag_id = tags.id
+             WHERE sop_item_tags.sop_id = ?1 ORDER BY tags.name COLLATE NOCASE",
+        )?;
+        let tags = stmt.query_map([id], |row| row.get(0))?.collect::<SqliteResult<Vec<String>>>()?;
+
+        let mut bundle = SopBundle::new(BundledSop {
+            name: item.name,
+            icon: item.icon,
+            item_type: item.item_type,
+            tags,
+            created_at: item.created_at,
+            updated_at: item.updated_at,
+        });
+        bundle.todos = self
+            .get_todo_items(id)?
+            .into_iter()
+            .map(|todo| BundledTodo {
+                content: todo.content,
+                completed: todo.completed,
+            })
+            .collect();
+        bundle.flow = self.get_flow_data(id)?.map(|flow| BundledFlow {
+            nodes: flow.nodes,
+            edges: flow.edges,
+        });
+
+        if include_revisions {
+            let mut stmt = conn.prepare(
+                "SELECT revision, nodes, edges, restored_from, created_at FROM flow_revisions WHERE sop_id = ?1 ORDER BY revision"
+            )?;
+            let revisions = stmt.query_map([id], |row| {
+                Ok(BundledRevision {
+                    revision: row.get(0)?,
+                    nodes: from_json_column(row, 1)?,
+                    edges: from_json_column(row, 2)?,
+                    restored_from: row.get(3)?,
+                    created_at: row.get(4)?,
+                })
+            })?;
+            bundle.revisions = revisions.collect::<SqliteResult<_>>()?;
+        }
+
+        Ok(bundle)
     }
 
-    pub fn create_todo_item(&self, item: &CreateTodoItem) -> SqliteResult<TodoItem> {
-        let now = chrono::Utc::now().to_rfc3339();
+    /// Create a new SOP from a bundle, with fresh ids. If another SOP already has its
+    /// name, " (2)", " (3)", ... is appended. Tags are matched by name and created if missing.
+    pub fn import_sop_bundle(&self, bundle: &SopBundle, folder_id: Option<i64>) -> SqliteResult<SopItem> {
+        self.transaction(|tx| {
+            let item = CreateSopItem {
+                name: unique_sop_name(tx, &bundle.sop.name)?,
+                icon: bundle.sop.icon.clone(),
+                item_type: bundle.sop.item_type.clone(),
+                folder_id,
+            };
+            let sop = insert_sop_item(tx, &item)?;
 
-        // Get the max sort_order for this sop_id
-        let max_order: i64 = self.conn
-            .query_row(
-                "SELECT COALESCE(MAX(sort_order), -1) FROM todo_items WHERE sop_id = ?1",
-                [item.sop_id],
-                |row| row.get(0),
-            )
-            .unwrap_or(-1);
+            for todo in &bundle.todos {
+                let created = insert_todo_item_at_end(tx, sop.id, &todo.content)?;
+                if todo.completed {
+                    set_todo_completed(tx, created.id, true)?;
+                }
+            }
 
-        let sort_order = max_order + 1;
+            // History first, so the current flow only becomes a new revision if it differs from the last one
+            for revision in &bundle.revisions {
+                tx.execute(
+                    "INSERT INTO flow_revisions (sop_id, revision, nodes, edges, restored_from, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    (
+                        sop.id,
+                        revision.revision,
+                        to_json_column(&revision.nodes)?,
+                        to_json_column(&revision.edges)?,
+                        revision.restored_from,
+                        &revision.created_at,
+                    ),
+                )?;
+            }
+            if let Some(flow) = &bundle.flow {
+                write_flow_data(tx, sop.id, &to_json_column(&flow.nodes)?, &to_json_column(&flow.edges)?, None)?;
+            }
 
-        self.conn.execute(
-            "INSERT INTO todo_items (sop_id, content, completed, sort_order, created_at, updated_at) VALUES (?1, ?2, 0, ?3, ?4, ?5)",
-            (&item.sop_id, &item.content, &sort_order, &now, &now),
+            let now = chrono::Utc::now().to_rfc3339();
+            for tag in &bundle.sop.tags {
+                tx.execute("INSERT OR IGNORE INTO tags (name, color, created_at) VALUES (?1, NULL, ?2)", (tag, &now))?;
+                tx.execute(
+                    "INSERT OR IGNORE INTO sop_item_tags (sop_id, tag_id) SELECT ?1, id FROM tags WHERE name = ?2",
+                    (sop.id, tag),
+                )?;
+            }
+
+            get_sop_item(tx, sop.id)
+        })
+    }
+
+    /// Put an SOP in `folder_id`, or at the top level with `None`. The item keeps its
+    /// id, so its todos, flow revisions, runs and undo history move along with it.
+    pub fn move_sop_item(&self, id: i64, folder_id: Option<i64>) -> SqliteResult<SopItem> {
+        let conn = self.conn()?;
+        let now = chrono::Utc::now().to_rfc3339();
+        conn.execute(
+            "UPDATE sop_items SET folder_id = ?1, updated_at = ?2 WHERE id = ?3",
+            (folder_id, &now, id),
         )?;
+        get_sop_item(&conn, id)
+    }
 
-        let id = self.conn.last_insert_rowid();
-        Ok(TodoItem {
-            id,
-            sop_id: item.sop_id,
-            content: item.content.clone(),
-            completed: false,
-            sort_order,
-            created_at: now.clone(),
-            updated_at: now,
+    /// Replace the tags of an SOP
+    pub fn set_sop_item_tags(&self, sop_id: i64, tag_ids: &[i64]) -> SqliteResult<SopItem> {
+        self.transaction(|tx| {
+            tx.execute("DELETE FROM sop_item_tags WHERE sop_id = ?1", [sop_id])?;
+            for tag_id in tag_ids {
+                tx.execute(
+                    "INSERT OR IGNORE INTO sop_item_tags (sop_id, tag_id) VALUES (?1, ?2)",
+                    (sop_id, tag_id),
+                )?;
+            }
+            get_sop_item(tx, sop_id)
         })
     }
 
-    pub fn get_todo_items(&self, sop_id: i64) -> SqliteResult<Vec<TodoItem>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, sop_id, content, completed, sort_order, created_at, updated_at FROM todo_items WHERE sop_id = ?1 ORDER BY sort_order ASC"
+    /// All folders, by name; the frontend assembles the tree from `parent_id`
+    pub fn get_folders(&self) -> SqliteResult<Vec<Folder>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, name, parent_id, created_at, updated_at FROM folders ORDER BY name COLLATE NOCASE"
         )?;
 
-        let items = stmt.query_map([sop_id], |row| {
-            Ok(TodoItem {
-                id: row.get(0)?,
-                sop_id: row.get(1)?,
-                content: row.get(2)?,
-                completed: row.get::<_, i32>(3)? != 0,
-                sort_order: row.get(4)?,
-                created_at: row.get(5)?,
-                updated_at: row.get(6)?,
-            })
-        })?;
+        let folders = stmt.query_map([], folder_from_row)?;
+        folders.collect()
+    }
 
-        items.collect()
+    pub fn get_folder(&self, id: i64) -> SqliteResult<Option<Folder>> {
+        let conn = self.conn()?;
+        match get_folder(&conn, id) {
+            Ok(folder) => Ok(Some(folder)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
     }
 
-    pub fn toggle_todo_item(&self, id: i64) -> SqliteResult<TodoItem> {
+    pub fn create_folder(&self, name: &str, parent_id: Option<i64>) -> SqliteResult<Folder> {
+        let conn = self.conn()?;
         let now = chrono::Utc::now().to_rfc3339();
-        self.conn.execute(
-            "UPDATE todo_items SET completed = NOT completed, updated_at = ?1 WHERE id = ?2",
-            (&now, &id),
+        conn.execute(
+            "INSERT INTO folders (name, parent_id, created_at, updated_at) VALUES (?1, ?2, ?3, ?3)",
+            (name, parent_id, &now),
         )?;
+        get_folder(&conn, conn.last_insert_rowid())
+    }
 
-        let mut stmt = self.conn.prepare(
-            "SELECT id, sop_id, content, completed, sort_order, created_at, updated_at FROM todo_items WHERE id = ?1"
+    pub fn rename_folder(&self, id: i64, name: &str) -> SqliteResult<Folder> {
+        let conn = self.conn()?;
+        let now = chrono::Utc::now().to_rfc3339();
+        conn.execute("UPDATE folders SET name = ?1, updated_at = ?2 WHERE id = ?3", (name, &now, id))?;
+        get_folder(&conn, id)
+    }
+
+    /// Put a folder, with everything in it, below `parent_id` or at the top level
+    pub fn move_folder(&self, id: i64, parent_id: Option<i64>) -> SqliteResult<Folder> {
+        let conn = self.conn()?;
+        let now = chrono::Utc::now().to_rfc3339();
+        conn.execute(
+            "UPDATE folders SET parent_id = ?1, updated_at = ?2 WHERE id = ?3",
+            (parent_id, &now, id),
         )?;
+        get_folder(&conn, id)
+    }
 
-        stmt.query_row([id], |row| {
-            Ok(TodoItem {
-                id: row.get(0)?,
-                sop_id: row.get(1)?,
-                content: row.get(2)?,
-                completed: row.get::<_, i32>(3)? != 0,
-                sort_order: row.get(4)?,
-                created_at: row.get(5)?,
-                updated_at: row.get(6)?,
-            })
+    /// Whether `folder_id` is `ancestor_id` itself or one of the folders below it
+    pub fn is_folder_within(&self, folder_id: i64, ancestor_id: i64) -> SqliteResult<bool> {
+        let conn = self.conn()?;
+        conn.query_row(
+            "WITH RECURSIVE subtree(id) AS (
+                SELECT ?2 UNION SELECT folders.id FROM folders JOIN subtree ON folders.parent_id = subtree.id
+            )
+            SELECT EXISTS (SELECT 1 FROM subtree WHERE id = ?1)",
+            (folder_id, ancestor_id),
+            |row| row.get(0),
+        )
+    }
+
+    /// Delete a folder. Its SOPs and subfolders move up to its parent, so nothing else is lost.
+    pub fn delete_folder(&self, id: i64) -> SqliteResult<()> {
+        self.transaction(|tx| {
+            let folder = get_folder(tx, id)?;
+            tx.execute("UPDATE sop_items SET folder_id = ?1 WHERE folder_id = ?2", (folder.parent_id, id))?;
+            tx.execute("UPDATE folders SET parent_id = ?1 WHERE parent_id = ?2", (folder.parent_id, id))?;
+            tx.execute("DELETE FROM folders WHERE id = ?1", [id])?;
+            Ok(())
         })
     }
 
-    pub fn delete_todo_item(&self, id: i64) -> SqliteResult<()> {
-        self.conn.execute("DELETE FROM todo_items WHERE id = ?1", [id])?;
-        Ok(())
+    pub fn get_tags(&self) -> SqliteResult<Vec<Tag>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT id, name, color, created_at FROM tags ORDER BY name COLLATE NOCASE")?;
+
+        let tags = stmt.query_map([], tag_from_row)?;
+        tags.collect()
     }
 
-    pub fn update_todo_item(&self, id: i64, content: &str) -> SqliteResult<TodoItem> {
+    pub fn create_tag(&self, name: &str, color: Option<&str>) -> SqliteResult<Tag> {
+        let conn = self.conn()?;
         let now = chrono::Utc::now().to_rfc3339();
-        self.conn.execute(
-            "UPDATE todo_items SET content = ?1, updated_at = ?2 WHERE id = ?3",
-            (content, &now, &id),
+        conn.execute(
+            "INSERT INTO tags (name, color, created_at) VALUES (?1, ?2, ?3)",
+            (name, color, &now),
         )?;
+        get_tag(&conn, conn.last_insert_rowid())
+    }
 
-        let mut stmt = self.conn.prepare(
-            "SELECT id, sop_id, content, completed, sort_order, created_at, updated_at FROM todo_items WHERE id = ?1"
+    pub fn update_tag(&self, id: i64, name: &str, color: Option<&str>) -> SqliteResult<Tag> {
+        let conn = self.conn()?;
+        conn.execute("UPDATE tags SET name = ?1, color = ?2 WHERE id = ?3", (name, color, id))?;
+        get_tag(&conn, id)
+    }
+
+    /// Delete a tag and take it off every SOP carrying it
+    pub fn delete_tag(&self, id: i64) -> SqliteResult<()> {
+        self.transaction(|tx| {
+            tx.execute("DELETE FROM sop_item_tags WHERE tag_id = ?1", [id])?;
+            tx.execute("DELETE FROM tags WHERE id = ?1", [id])?;
+            Ok(())
+        })
+    }
+
+    pub fn delete_sop_item(&self, id: i64) -> SqliteResult<()> {
+        let conn = self.conn()?;
+        conn
+            .execute("DELETE FROM sop_items WHERE id = ?1", [id])?;
+        Ok(())
+    }
+
+    pub fn create_todo_item(&self, item: &CreateTodoItem) -> SqliteResult<TodoItem> {
+        self.transaction(|tx| {
+            let created = insert_todo_item_at_end(tx, item.sop_id, &item.content)?;
+            record_todo_operation(tx, item.sop_id, &TodoOperation::Create { item: created.clone() })?;
+            Ok(created)
+        })
+    }
+
+    pub fn get_todo_items(&self, sop_id: i64) -> SqliteResult<Vec<TodoItem>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, sop_id, content, completed, sort_order, created_at, updated_at FROM todo_items WHERE sop_id = ?1 ORDER BY sort_order ASC"
         )?;
 
-        stmt.query_row([id], |row| {
-            Ok(TodoItem {
-                id: row.get(0)?,
-                sop_id: row.get(1)?,
-                content: row.get(2)?,
-                completed: row.get::<_, i32>(3)? != 0,
-                sort_order: row.get(4)?,
-                created_at: row.get(5)?,
-                updated_at: row.get(6)?,
-            })
+        let items = stmt.query_map([sop_id], todo_item_from_row)?;
+        items.collect()
+    }
+
+    pub fn toggle_todo_item(&self, id: i64) -> SqliteResult<TodoItem> {
+        self.transaction(|tx| {
+            let before = get_todo_item(tx, id)?;
+            set_todo_completed(tx, id, !before.completed)?;
+            record_todo_operation(tx, before.sop_id, &TodoOperation::Toggle { id, completed: !before.completed })?;
+            get_todo_item(tx, id)
         })
     }
 
-    pub fn reorder_todo_items(&self, item_ids: &[i64]) -> SqliteResult<()> {
-        let now = chrono::Utc::now().to_rfc3339();
-        for (index, id) in item_ids.iter().enumerate() {
-            self.conn.execute(
-                "UPDATE todo_items SET sort_order = ?1, updated_at = ?2 WHERE id = ?3",
-                (index as i64, &now, id),
-            )?;
+    pub fn delete_todo_item(&self, id: i64) -> SqliteResult<()> {
+        self.transaction(|tx| {
+            let item = get_todo_item(tx, id)?;
+            tx.execute("DELETE FROM todo_items WHERE id = ?1", [id])?;
+            record_todo_operation(tx, item.sop_id, &TodoOperation::Delete { item })
+        })
+    }
+
+    pub fn update_todo_item(&self, id: i64, content: &str) -> SqliteResult<TodoItem> {
+        self.transaction(|tx| {
+            let before = get_todo_item(tx, id)?;
+            set_todo_content(tx, id, content)?;
+            if before.content != content {
+                record_todo_operation(
+                    tx,
+                    before.sop_id,
+                    &TodoOperation::Edit {
+                        id,
+                        before: before.content.clone(),
+                        after: content.to_string(),
+                    },
+                )?;
+            }
+            get_todo_item(tx, id)
+        })
+    }
+
+    /// Put the todos of one SOP in the order of `item_ids`, which must list each of them exactly once
+    pub fn reorder_todo_items(&self, item_ids: &[i64]) -> ZopResult<()> {
+        let Some(&first) = item_ids.first() else {
+            return Ok(());
+        };
+        // Checked inside the transaction, so the todos cannot change in between
+        let reordered = self.transaction(|tx| {
+            let sop_id = get_todo_item(tx, first)?.sop_id;
+            let before = todo_order(tx, sop_id)?;
+            let mut expected = before.clone();
+            let mut given = item_ids.to_vec();
+            expected.sort_unstable();
+            given.sort_unstable();
+            if given != expected {
+                return Ok(false);
+            }
+            set_todo_order(tx, item_ids)?;
+            if before != item_ids {
+                record_todo_operation(
+                    tx,
+                    sop_id,
+                    &TodoOperation::Reorder {
+                        before,
+                        after: item_ids.to_vec(),
+                    },
+                )?;
+            }
+            Ok(true)
+        })?;
+        if !reordered {
+            return Err(ZopError::validation("Reordering must list every todo of the SOP exactly once"));
         }
         Ok(())
     }
 
+    pub fn get_todo_list_state(&self, sop_id: i64) -> SqliteResult<TodoListState> {
+        let conn = self.conn()?;
+        let count = |undone: bool| -> SqliteResult<i64> {
+            conn.query_row(
+                "SELECT COUNT(*) FROM todo_operations WHERE sop_id = ?1 AND (undone_at IS NOT NULL) = ?2",
+                (sop_id, undone),
+                |row| row.get(0),
+            )
+        };
+
+        Ok(TodoListState {
+            items: self.get_todo_items(sop_id)?,
+            can_undo: count(false)? > 0,
+            can_redo: count(true)? > 0,
+        })
+    }
+
+    /// Revert the most recent todo change of an SOP that hasn't been undone yet
+    pub fn undo_todo_operation(&self, sop_id: i64) -> SqliteResult<TodoListState> {
+        self.transaction(|tx| {
+            let latest = tx.query_row(
+                "SELECT id, operation FROM todo_operations WHERE sop_id = ?1 AND undone_at IS NULL ORDER BY id DESC LIMIT 1",
+                [sop_id],
+                |row| Ok((row.get::<_, i64>(0)?, from_json_column::<TodoOperation>(row, 1)?)),
+            );
+
+            match latest {
+                Ok((id, operation)) => {
+                    operation.revert(tx)?;
+                    tx.execute(
+                        "UPDATE todo_operations SET undone_at = ?1 WHERE id = ?2",
+                        (chrono::Utc::now().to_rfc3339(), id),
+                    )?;
+                    Ok(())
+                }
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(()),
+                Err(e) => Err(e),
+            }
+        })?;
+
+        self.get_todo_list_state(sop_id)
+    }
+
+    /// Re-apply the earliest undone todo change of an SOP
+    pub fn redo_todo_operation(&self, sop_id: i64) -> SqliteResult<TodoListState> {
+        self.transaction(|tx| {
+            let next = tx.query_row(
+                "SELECT id, operation FROM todo_operations WHERE sop_id = ?1 AND undone_at IS NOT NULL ORDER BY id ASC LIMIT 1",
+                [sop_id],
+                |row| Ok((row.get::<_, i64>(0)?, from_json_column::<TodoOperation>(row, 1)?)),
+            );
+
+            match next {
+                Ok((id, operation)) => {
+                    operation.apply(tx)?;
+                    tx.execute("UPDATE todo_operations SET undone_at = NULL WHERE id = ?1", [id])?;
+                    Ok(())
+                }
+                Err(rusqlite::Error::QueryReturnedNoRows) => Ok(()),
+                Err(e) => Err(e),
+            }
+        })?;
+
+        self.get_todo_list_state(sop_id)
+    }
+
     pub fn get_flow_data(&self, sop_id: i64) -> SqliteResult<Option<FlowData>> {
-        let mut stmt = self.conn.prepare(
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
             "SELECT id, sop_id, nodes, edges, created_at, updated_at FROM flow_data WHERE sop_id = ?1"
         )?;
 
@@ -353,8 +670,8 @@ impl Database {
             Ok(FlowData {
                 id: row.get(0)?,
                 sop_id: row.get(1)?,
-                nodes: row.get(2)?,
-                edges: row.get(3)?,
+                nodes: from_json_column(row, 2)?,
+                edges: from_json_column(row, 3)?,
                 created_at: row.get(4)?,
                 updated_at: row.get(5)?,
             })
@@ -367,42 +684,325 @@ impl Database {
         }
     }
 
-    pub fn save_flow_data(&self, sop_id: i64, nodes: &str, edges: &str) -> SqliteResult<FlowData> {
-        let now = chrono::Utc::now().to_rfc3339();
+    /// Save the flow and record it as a new revision if it differs from the latest one
+    pub fn save_flow_data(&self, sop_id: i64, nodes: &[FlowNode], edges: &[FlowEdge]) -> SqliteResult<FlowData> {
+        let nodes = to_json_column(nodes)?;
+        let edges = to_json_column(edges)?;
+
+        self.transaction(|tx| write_flow_data(tx, sop_id, &nodes, &edges, None))?;
+
+        self.get_flow_data(sop_id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)
+    }
+
+    pub fn list_flow_revisions(&self, sop_id: i64) -> SqliteResult<Vec<FlowRevisionSummary>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, sop_id, revision, json_array_length(nodes), json_array_length(edges), restored_from, created_at
+             FROM flow_revisions WHERE sop_id = ?1 ORDER BY revision DESC"
+        )?;
+
+        let revisions = stmt.query_map([sop_id], |row| {
+            Ok(FlowRevisionSummary {
+                id: row.get(0)?,
+                sop_id: row.get(1)?,
+                revision: row.get(2)?,
+                node_count: row.get(3)?,
+                edge_count: row.get(4)?,
+                restored_from: row.get(5)?,
+                created_at: row.get(6)?,
+            })
+        })?;
+
+        revisions.collect()
+    }
+
+    pub fn get_flow_revision(&self, id: i64) -> SqliteResult<Option<FlowRevision>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, sop_id, revision, nodes, edges, restored_from, created_at FROM flow_revisions WHERE id = ?1"
+        )?;
+
+        let result = stmt.query_row([id], |row| {
+            Ok(FlowRevision {
+                id: row.get(0)?,
+                sop_id: row.get(1)?,
+                revision: row.get(2)?,
+                nodes: from_json_column(row, 3)?,
+                edges: from_json_column(row, 4)?,
+                restored_from: row.get(5)?,
+                created_at: row.get(6)?,
+            })
+        });
 
-        // Try to update existing record, or insert new one
-        let existing = self.get_flow_data(sop_id)?;
+        match result {
+            Ok(revision) => Ok(Some(revision)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Make an earlier revision the current flow again. The rollback is recorded as a
+    /// new revision, so the versions saved after it stay in the history.
+    pub fn restore_flow_revision(&self, id: i64) -> SqliteResult<FlowData> {
+        let revision = self.get_flow_revision(id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+        let nodes = to_json_column(&revision.nodes)?;
+        let edges = to_json_column(&revision.edges)?;
+
+        self.transaction(|tx| write_flow_data(tx, revision.sop_id, &nodes, &edges, Some(revision.revision)))?;
 
-        if existing.is_some() {
-            self.conn.execute(
-                "UPDATE flow_data SET nodes = ?1, edges = ?2, updated_at = ?3 WHERE sop_id = ?4",
-                (nodes, edges, &now, sop_id),
+        self.get_flow_data(revision.sop_id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)
+    }
+
+    pub fn create_flow_run(&self, sop_id: i64, operator: Option<&str>, start: &FlowNode) -> SqliteResult<FlowRun> {
+        let now = chrono::Utc::now().to_rfc3339();
+        let run_id = self.transaction(|tx| {
+            tx.execute(
+                "INSERT INTO flow_runs (sop_id, revision_id, operator, status, current_node_id, started_at, updated_at)
+                 VALUES (?1, (SELECT id FROM flow_revisions WHERE sop_id = ?1 ORDER BY revision DESC LIMIT 1), ?2, 'active', ?3, ?4, ?4)",
+                (sop_id, operator, &start.id, &now),
             )?;
-        } else {
-            self.conn.execute(
-                "INSERT INTO flow_data (sop_id, nodes, edges, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)",
-                (sop_id, nodes, edges, &now, &now),
+            let run_id = tx.last_insert_rowid();
+            tx.execute(
+                "INSERT INTO flow_run_steps (run_id, seq, node_id, node_label, node_shape, entered_at) VALUES (?1, 0, ?2, ?3, ?4, ?5)",
+                (run_id, &start.id, start.label(), start.shape().as_str(), &now),
             )?;
+            Ok(run_id)
+        })?;
+
+        self.get_flow_run(run_id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)
+    }
+
+    pub fn get_flow_run(&self, id: i64) -> SqliteResult<Option<FlowRun>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, sop_id, operator, status, current_node_id, draft_values, started_at, updated_at, finished_at, revision_id FROM flow_runs WHERE id = ?1"
+        )?;
+
+        match stmt.query_row([id], flow_run_from_row) {
+            Ok(run) => Ok(Some(run)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
         }
+    }
 
-        self.get_flow_data(sop_id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)
+    pub fn list_flow_runs(&self, sop_id: i64) -> SqliteResult<Vec<FlowRun>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, sop_id, operator, status, current_node_id, draft_values, started_at, updated_at, finished_at, revision_id FROM flow_runs WHERE sop_id = ?1 ORDER BY started_at DESC, id DESC"
+        )?;
+
+        let runs = stmt.query_map([sop_id], flow_run_from_row)?;
+        runs.collect()
     }
 
-    pub fn get_ai_config(&self) -> SqliteResult<Option<AiConfig>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, base_url, api_key, model_name, created_at, updated_at FROM ai_config ORDER BY id DESC LIMIT 1"
+    pub fn get_active_flow_runs(&self) -> SqliteResult<Vec<ActiveFlowRun>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT r.id, r.sop_id, r.operator, r.status, r.current_node_id, r.draft_values, r.started_at, r.updated_at, r.finished_at, r.revision_id,
+                    s.name,
+                    (SELECT node_label FROM flow_run_steps WHERE run_id = r.id AND reverted_at IS NULL ORDER BY seq DESC LIMIT 1)
+             FROM flow_runs r
+             JOIN sop_items s ON s.id = r.sop_id
+             WHERE r.status = 'active' AND s.deleted_at IS NULL
+               AND r.id = (SELECT id FROM flow_runs WHERE sop_id = r.sop_id AND status = 'active' ORDER BY updated_at DESC, id DESC LIMIT 1)
+             ORDER BY r.updated_at DESC"
         )?;
 
-        let result = stmt.query_row([], |row| {
-            Ok(AiConfig {
-                id: row.get(0)?,
-                base_url: row.get(1)?,
-                api_key: row.get(2)?,
-                model_name: row.get(3)?,
-                created_at: row.get(4)?,
-                updated_at: row.get(5)?,
+        let runs = stmt.query_map([], |row| {
+            Ok(ActiveFlowRun {
+                run: flow_run_from_row(row)?,
+                sop_name: row.get(10)?,
+                current_node_label: row.get(11)?,
             })
-        });
+        })?;
+
+        runs.collect()
+    }
+
+    pub fn save_flow_run_draft(
+        &self,
+        run_id: i64,
+        values: &serde_json::Map<String, serde_json::Value>,
+    ) -> SqliteResult<FlowRun> {
+        let conn = self.conn()?;
+        let now = chrono::Utc::now().to_rfc3339();
+        conn.execute(
+            "UPDATE flow_runs SET draft_values = ?1, updated_at = ?2 WHERE id = ?3",
+            (to_json_column(values)?, &now, run_id),
+        )?;
+
+        self.get_flow_run(run_id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)
+    }
+
+    /// All steps of a run in the order they were entered, including reverted ones
+    pub fn get_flow_run_steps(&self, run_id: i64) -> SqliteResult<Vec<FlowRunStep>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, run_id, seq, node_id, node_label, node_shape, entered_at, reverted_at FROM flow_run_steps WHERE run_id = ?1 ORDER BY seq ASC"
+        )?;
+
+        let steps = stmt.query_map([run_id], flow_run_step_from_row)?;
+        steps.collect()
+    }
+
+    /// The latest step of a run that has not been reverted
+    pub fn get_current_flow_run_step(&self, run_id: i64) -> SqliteResult<Option<FlowRunStep>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, run_id, seq, node_id, node_label, node_shape, entered_at, reverted_at FROM flow_run_steps WHERE run_id = ?1 AND reverted_at IS NULL ORDER BY seq DESC LIMIT 1"
+        )?;
+
+        match stmt.query_row([run_id], flow_run_step_from_row) {
+            Ok(step) => Ok(Some(step)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Insert or replace the values entered on a form step
+    pub fn save_flow_run_input(
+        &self,
+        run_id: i64,
+        step: &FlowRunStep,
+        values: &serde_json::Map<String, serde_json::Value>,
+    ) -> SqliteResult<FlowRunInput> {
+        let conn = self.conn()?;
+        let now = chrono::Utc::now().to_rfc3339();
+        let json = to_json_column(values)?;
+        conn.execute(
+            "INSERT INTO flow_run_inputs (run_id, step_id, node_id, input_values, submitted_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+             ON CONFLICT(step_id) DO UPDATE SET input_values = excluded.input_values, updated_at = excluded.updated_at",
+            (run_id, step.id, &step.node_id, &json, &now),
+        )?;
+
+        conn.query_row(
+            "SELECT id, run_id, step_id, node_id, input_values, submitted_at, updated_at FROM flow_run_inputs WHERE step_id = ?1",
+            [step.id],
+            flow_run_input_from_row,
+        )
+    }
+
+    /// Values stored for one step, empty if none were submitted
+    pub fn get_flow_run_step_values(&self, step_id: i64) -> SqliteResult<serde_json::Map<String, serde_json::Value>> {
+        let conn = self.conn()?;
+        match conn.query_row(
+            "SELECT input_values FROM flow_run_inputs WHERE step_id = ?1",
+            [step_id],
+            |row| from_json_column(row, 0),
+        ) {
+            Ok(values) => Ok(values),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(serde_json::Map::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Form values entered on the steps a run has not gone back over, later steps winning
+    pub fn get_flow_run_values(&self, run_id: i64) -> SqliteResult<serde_json::Map<String, serde_json::Value>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT i.input_values FROM flow_run_inputs i
+             JOIN flow_run_steps s ON s.id = i.step_id
+             WHERE i.run_id = ?1 AND s.reverted_at IS NULL
+             ORDER BY s.seq ASC"
+        )?;
+
+        let rows = stmt.query_map([run_id], |row| {
+            from_json_column::<serde_json::Map<String, serde_json::Value>>(row, 0)
+        })?;
+
+        let mut values = serde_json::Map::new();
+        for row in rows {
+            values.extend(row?);
+        }
+        Ok(values)
+    }
+
+    pub fn get_flow_run_inputs(&self, run_id: i64) -> SqliteResult<Vec<FlowRunInput>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, run_id, step_id, node_id, input_values, submitted_at, updated_at FROM flow_run_inputs WHERE run_id = ?1 ORDER BY id ASC"
+        )?;
+
+        let inputs = stmt.query_map([run_id], flow_run_input_from_row)?;
+        inputs.collect()
+    }
+
+    /// Record that the run moved on through `nodes`, in order; the last one becomes current
+    pub fn push_flow_run_steps(&self, run_id: i64, nodes: &[&FlowNode]) -> SqliteResult<FlowRun> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.transaction(|tx| {
+            for node in nodes {
+                tx.execute(
+                    "INSERT INTO flow_run_steps (run_id, seq, node_id, node_label, node_shape, entered_at)
+                     SELECT ?1, COALESCE(MAX(seq), -1) + 1, ?2, ?3, ?4, ?5 FROM flow_run_steps WHERE run_id = ?1",
+                    (run_id, &node.id, node.label(), node.shape().as_str(), &now),
+                )?;
+                tx.execute(
+                    "UPDATE flow_runs SET current_node_id = ?1, draft_values = NULL, updated_at = ?2 WHERE id = ?3",
+                    (&node.id, &now, run_id),
+                )?;
+            }
+            Ok(())
+        })?;
+
+        self.get_flow_run(run_id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)
+    }
+
+    /// Return a run to its previous step, keeping the steps left in the audit trail as
+    /// reverted. Decision nodes passed through on the way are reverted as well, all in
+    /// one transaction. The first step of a run is never reverted.
+    pub fn go_back_flow_run(&self, run_id: i64) -> SqliteResult<FlowRun> {
+        let now = chrono::Utc::now().to_rfc3339();
+        self.transaction(|tx| {
+            loop {
+                let active: Vec<(i64, String, String, i64)> = {
+                    let mut stmt = tx.prepare(
+                        "SELECT id, node_id, node_shape, seq FROM flow_run_steps WHERE run_id = ?1 AND reverted_at IS NULL ORDER BY seq DESC LIMIT 2"
+                    )?;
+                    let rows = stmt.query_map([run_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?;
+                    rows.collect::<SqliteResult<_>>()?
+                };
+
+                let [(latest_id, ..), (_, previous_node_id, previous_shape, previous_seq)] = active.as_slice() else {
+                    return Ok(());
+                };
+                tx.execute(
+                    "UPDATE flow_run_steps SET reverted_at = ?1 WHERE id = ?2",
+                    (&now, latest_id),
+                )?;
+                tx.execute(
+                    "UPDATE flow_runs SET current_node_id = ?1, draft_values = NULL, updated_at = ?2 WHERE id = ?3",
+                    (previous_node_id, &now, run_id),
+                )?;
+                if previous_shape != NodeShape::Decision.as_str() || *previous_seq == 0 {
+                    return Ok(());
+                }
+            }
+        })?;
+
+        self.get_flow_run(run_id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)
+    }
+
+    /// Close a run with the given final status ("completed" or "abandoned")
+    pub fn finish_flow_run(&self, run_id: i64, status: &str) -> SqliteResult<FlowRun> {
+        let conn = self.conn()?;
+        let now = chrono::Utc::now().to_rfc3339();
+        conn.execute(
+            "UPDATE flow_runs SET status = ?1, updated_at = ?2, finished_at = ?2 WHERE id = ?3",
+            (status, &now, run_id),
+        )?;
+
+        self.get_flow_run(run_id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)
+    }
+
+    /// The default AI provider profile, used when no other one is picked
+    pub fn get_ai_config(&self) -> SqliteResult<Option<AiConfig>> {
+        let conn = self.conn()?;
+        let result = conn.query_row(
+            &format!("SELECT {} FROM ai_config WHERE is_default = 1", AI_CONFIG_COLUMNS),
+            [],
+            ai_config_from_row,
+        );
 
         match result {
             Ok(config) => Ok(Some(config)),
@@ -411,24 +1011,839 @@ impl Database {
         }
     }
 
-    pub fn save_ai_config(&self, config: &SaveAiConfig) -> SqliteResult<AiConfig> {
+    /// Save the default AI profile, creating it if there is none yet;
+    /// `api_key_secret` is the id of the key in the secret store
+    pub fn save_ai_config(&self, config: &SaveAiConfig, api_key_secret: &str) -> SqliteResult<AiConfig> {
+        match self.get_ai_config()? {
+            Some(existing) => self.update_ai_profile(existing.id, &existing.name, config, api_key_secret),
+            None => self.create_ai_profile("Default", config, api_key_secret),
+        }
+    }
+
+    /// All AI provider profiles, the default one first
+    pub fn list_ai_profiles(&self) -> SqliteResult<Vec<AiConfig>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM ai_config ORDER BY is_default DESC, name COLLATE NOCASE",
+            AI_CONFIG_COLUMNS
+        ))?;
+        let profiles = stmt.query_map([], ai_config_from_row)?;
+        profiles.collect()
+    }
+
+    pub fn get_ai_profile(&self, id: i64) -> SqliteResult<AiConfig> {
+        let conn = self.conn()?;
+        get_ai_profile(&conn, id)
+    }
+
+    /// Add an AI provider profile; the first one becomes the default
+    pub fn create_ai_profile(&self, name: &str, config: &SaveAiConfig, api_key_secret: &str) -> SqliteResult<AiConfig> {
+        self.transaction(|tx| {
+            let now = chrono::Utc::now().to_rfc3339();
+            let has_default: bool =
+                tx.query_row("SELECT EXISTS(SELECT 1 FROM ai_config WHERE is_default = 1)", [], |row| row.get(0))?;
+            tx.execute(
+                "INSERT INTO ai_config (name, base_url, api_key, api_key_secret, model_name, is_default, created_at, updated_at)
+                 VALUES (?1, ?2, '', ?3, ?4, ?5, ?6, ?6)",
+                (name, &config.base_url, api_key_secret, &config.model_name, !has_default, &now),
+            )?;
+            get_ai_profile(tx, tx.last_insert_rowid())
+        })
+    }
+
+    pub fn update_ai_profile(
+        &self,
+        id: i64,
+        name: &str,
+        config: &SaveAiConfig,
+        api_key_secret: &str,
+    ) -> SqliteResult<AiConfig> {
+        let conn = self.conn()?;
         let now = chrono::Utc::now().to_rfc3339();
+        conn.execute(
+            "UPDATE ai_config SET name = ?1, base_url = ?2, api_key = '', api_key_secret = ?3, model_name = ?4, updated_at = ?5
+             WHERE id = ?6",
+            (name, &config.base_url, api_key_secret, &config.model_name, &now, id),
+        )?;
+        get_ai_profile(&conn, id)
+    }
+
+    pub fn set_default_ai_profile(&self, id: i64) -> SqliteResult<AiConfig> {
+        self.transaction(|tx| {
+            get_ai_profile(tx, id)?;
+            tx.execute("UPDATE ai_config SET is_default = 0 WHERE is_default = 1 AND id != ?1", [id])?;
+            tx.execute("UPDATE ai_config SET is_default = 1 WHERE id = ?1", [id])?;
+            get_ai_profile(tx, id)
+        })
+    }
+
+    /// Delete an AI provider profile and return the id of its API key, so the
+    /// caller can drop it from the secret store. When the default profile goes,
+    /// the newest remaining one takes its place.
+    pub fn delete_ai_profile(&self, id: i64) -> SqliteResult<Option<String>> {
+        self.transaction(|tx| {
+            let profile = get_ai_profile(tx, id)?;
+            tx.execute("DELETE FROM ai_config WHERE id = ?1", [id])?;
+            if profile.is_default {
+                tx.execute(
+                    "UPDATE ai_config SET is_default = 1 WHERE id = (SELECT MAX(id) FROM ai_config)",
+                    [],
+                )?;
+            }
+            Ok(profile.api_key_secret)
+        })
+    }
 
-        // Check if config exists
-        let existing = self.get_ai_config()?;
+    /// Rewrite the database file and empty the WAL, so deleted or overwritten values
+    /// such as plaintext API keys can't be recovered from free pages or old frames
+    pub fn erase_freed_data(&self) -> SqliteResult<()> {
+        let conn = self.conn()?;
+        conn.execute_batch("VACUUM;")?;
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+    }
+
+    /// API keys still stored in plaintext, by AI config id
+    pub fn plaintext_api_keys(&self) -> SqliteResult<Vec<(i64, String)>> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare("SELECT id, api_key FROM ai_config WHERE api_key != ''")?;
+        let keys = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        keys.collect()
+    }
 
-        if let Some(existing_config) = existing {
-            self.conn.execute(
-                "UPDATE ai_config SET base_url = ?1, api_key = ?2, model_name = ?3, updated_at = ?4 WHERE id = ?5",
-                (&config.base_url, &config.api_key, &config.model_name, &now, existing_config.id),
+    /// Point an AI config at its key in the secret store and erase the plaintext
+    /// copy, including the one kept in `ai_config_legacy` by an earlier migration.
+    /// The old value stays in free pages and the WAL until `erase_freed_data`.
+    pub fn seal_api_key(&self, id: i64, api_key_secret: &str) -> SqliteResult<()> {
+        self.transaction(|tx| {
+            tx.execute(
+                "UPDATE ai_config SET api_key = '', api_key_secret = ?1 WHERE id = ?2",
+                (api_key_secret, id),
             )?;
-        } else {
-            self.conn.execute(
-                "INSERT INTO ai_config (base_url, api_key, model_name, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)",
-                (&config.base_url, &config.api_key, &config.model_name, &now, &now),
+            let has_legacy: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'ai_config_legacy')",
+                [],
+                |row| row.get(0),
             )?;
+            if has_legacy {
+                tx.execute("UPDATE ai_config_legacy SET api_key = '' WHERE id = ?1", [id])?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// A recorded change to a todo list, with enough state to apply it again or revert it
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum TodoOperation {
+    Create { item: TodoItem },
+    Toggle { id: i64, completed: bool },
+    Edit { id: i64, before: String, after: String },
+    Delete { item: TodoItem },
+    Reorder { before: Vec<i64>, after: Vec<i64> },
+}
+
+impl TodoOperation {
+    fn apply(&self, conn: &Connection) -> SqliteResult<()> {
+        match self {
+            TodoOperation::Create { item } => insert_todo_item(conn, item),
+            TodoOperation::Toggle { id, completed } => set_todo_completed(conn, *id, *completed),
+            TodoOperation::Edit { id, after, .. } => set_todo_content(conn, *id, after),
+            TodoOperation::Delete { item } => {
+                conn.execute("DELETE FROM todo_items WHERE id = ?1", [item.id])?;
+                Ok(())
+            }
+            TodoOperation::Reorder { after, .. } => set_todo_order(conn, after),
+        }
+    }
+
+    fn revert(&self, conn: &Connection) -> SqliteResult<()> {
+        match self {
+            TodoOperation::Create { item } => {
+                conn.execute("DELETE FROM todo_items WHERE id = ?1", [item.id])?;
+                Ok(())
+            }
+            TodoOperation::Toggle { id, completed } => set_todo_completed(conn, *id, !*completed),
+            TodoOperation::Edit { id, before, .. } => set_todo_content(conn, *id, before),
+            TodoOperation::Delete { item } => insert_todo_item(conn, item),
+            TodoOperation::Reorder { before, .. } => set_todo_order(conn, before),
+        }
+    }
+}
+
+/// Log a todo change; a new change discards whatever could still be redone
+fn record_todo_operation(conn: &Connection, sop_id: i64, operation: &TodoOperation) -> SqliteResult<()> {
+    conn.execute(
+        "DELETE FROM todo_operations WHERE sop_id = ?1 AND undone_at IS NOT NULL",
+        [sop_id],
+    )?;
+    conn.execute(
+        "INSERT INTO todo_operations (sop_id, operation, created_at) VALUES (?1, ?2, ?3)",
+        (sop_id, to_json_column(operation)?, chrono::Utc::now().to_rfc3339()),
+    )?;
+    Ok(())
+}
+
+/// Maps the leading `sop_id, name, item_type, kind, target_id` columns of a search query
+fn search_hit_from_row(row: &rusqlite::Row, snippet: String, score: f64) -> SqliteResult<SearchHit> {
+    let kind: String = row.get(3)?;
+    let target: rusqlite::types::Value = row.get(4)?;
+    let (todo_id, node_id) = match target {
+        rusqlite::types::Value::Integer(id) if kind == "todo" => (Some(id), None),
+        rusqlite::types::Value::Text(id) if kind == "node" => (None, Some(id)),
+        _ => (None, None),
+    };
+
+    Ok(SearchHit {
+        sop_id: row.get(0)?,
+        sop_name: row.get(1)?,
+        item_type: row.get(2)?,
+        kind,
+        todo_id,
+        node_id,
+        snippet,
+        score,
+    })
+}
+
+fn connect(path: &Path) -> SqliteResult<Connection> {
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    // Off by default unless SQLite was built otherwise; the schema's ON DELETE clauses depend on it
+    conn.pragma_update(None, "foreign_keys", "ON")?;
+    Ok(conn)
+}
+
+fn insert_sop_item(conn: &Connection, item: &CreateSopItem) -> SqliteResult<SopItem> {
+    let now = chrono::Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO sop_items (name, icon, item_type, folder_id, created_at, updated_at, deleted_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, NULL)",
+        (&item.name, &item.icon, &item.item_type, item.folder_id, &now, &now),
+    )?;
+
+    let id = conn.last_insert_rowid();
+    Ok(SopItem {
+        id,
+        name: item.name.clone(),
+        icon: item.icon.clone(),
+        item_type: item.item_type.clone(),
+        folder_id: item.folder_id,
+        tag_ids: Vec::new(),
+        created_at: now.clone(),
+        updated_at: now,
+        deleted_at: None,
+    })
+}
+
+/// `name`, or `name (n)` with the smallest n not used by an SOP outside the trash
+fn unique_sop_name(conn: &Connection, name: &str) -> SqliteResult<String> {
+    let taken = |candidate: &str| -> SqliteResult<bool> {
+        conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sop_items WHERE name = ?1 AND deleted_at IS NULL)",
+            [candidate],
+            |row| row.get(0),
+        )
+    };
+
+    let mut candidate = name.to_string();
+    let mut n = 2;
+    while taken(&candidate)? {
+        candidate = format!("{} ({})", name, n);
+        n += 1;
+    }
+    Ok(candidate)
+}
+
+/// Columns read by `sop_item_from_row`; the last one collects the item's tag ids
+const SOP_ITEM_COLUMNS: &str = "id, name, icon, item_type, created_at, updated_at, deleted_at, folder_id,
+    (SELECT json_group_array(tag_id) FROM sop_item_tags WHERE sop_id = sop_items.id)";
+
+fn get_sop_item(conn: &Connection, id: i64) -> SqliteResult<SopItem> {
+    conn.query_row(
+        &format!("SELECT {} FROM sop_items WHERE id = ?1", SOP_ITEM_COLUMNS),
+        [id],
+        sop_item_from_row,
+    )
+}
+
+fn get_folder(conn: &Connection, id: i64) -> SqliteResult<Folder> {
+    conn.query_row(
+        "SELECT id, name, parent_id, created_at, updated_at FROM folders WHERE id = ?1",
+        [id],
+        folder_from_row,
+    )
+}
+
+fn folder_from_row(row: &rusqlite::Row) -> SqliteResult<Folder> {
+    Ok(Folder {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        parent_id: row.get(2)?,
+        created_at: row.get(3)?,
+        updated_at: row.get(4)?,
+    })
+}
+
+const AI_CONFIG_COLUMNS: &str = "id, name, base_url, api_key_secret, model_name, is_default, created_at, updated_at";
+
+fn get_ai_profile(conn: &Connection, id: i64) -> SqliteResult<AiConfig> {
+    conn.query_row(
+        &format!("SELECT {} FROM ai_config WHERE id = ?1", AI_CONFIG_COLUMNS),
+        [id],
+        ai_config_from_row,
+    )
+}
+
+fn ai_config_from_row(row: &rusqlite::Row) -> SqliteResult<AiConfig> {
+    Ok(AiConfig {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        base_url: row.get(2)?,
+        api_key_secret: row.get(3)?,
+        api_key_masked: None,
+        api_key_error: None,
+        model_name: row.get(4)?,
+        is_default: row.get(5)?,
+        created_at: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
+}
+
+fn get_tag(conn: &Connection, id: i64) -> SqliteResult<Tag> {
+    conn.query_row("SELECT id, name, color, created_at FROM tags WHERE id = ?1", [id], tag_from_row)
+}
+
+fn tag_from_row(row: &rusqlite::Row) -> SqliteResult<Tag> {
+    Ok(Tag {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        color: row.get(2)?,
+        created_at: row.get(3)?,
+    })
+}
+
+fn sop_item_from_row(row: &rusqlite::Row) -> SqliteResult<SopItem> {
+    Ok(SopItem {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        icon: row.get(2)?,
+        item_type: row.get(3)?,
+        folder_id: row.get(7)?,
+        tag_ids: from_json_column(row, 8)?,
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+        deleted_at: row.get(6)?,
+    })
+}
+
+/// Add a new, unchecked item after the existing ones of the SOP
+fn insert_todo_item_at_end(conn: &Connection, sop_id: i64, content: &str) -> SqliteResult<TodoItem> {
+    let now = chrono::Utc::now().to_rfc3339();
+
+    // Get the max sort_order for this sop_id
+    let max_order: i64 = conn
+        .query_row(
+            "SELECT COALESCE(MAX(sort_order), -1) FROM todo_items WHERE sop_id = ?1",
+            [sop_id],
+            |row| row.get(0),
+        )
+        .unwrap_or(-1);
+
+    let sort_order = max_order + 1;
+
+    conn.execute(
+        "INSERT INTO todo_items (sop_id, content, completed, sort_order, created_at, updated_at) VALUES (?1, ?2, 0, ?3, ?4, ?5)",
+        (sop_id, content, &sort_order, &now, &now),
+    )?;
+
+    Ok(TodoItem {
+        id: conn.last_insert_rowid(),
+        sop_id,
+        content: content.to_string(),
+        completed: false,
+        sort_order,
+        created_at: now.clone(),
+        updated_at: now,
+    })
+}
+
+fn get_todo_item(conn: &Connection, id: i64) -> SqliteResult<TodoItem> {
+    conn.query_row(
+        "SELECT id, sop_id, content, completed, sort_order, created_at, updated_at FROM todo_items WHERE id = ?1",
+        [id],
+        todo_item_from_row,
+    )
+}
+
+/// Put back an item exactly as it was, keeping its id and position
+fn insert_todo_item(conn: &Connection, item: &TodoItem) -> SqliteResult<()> {
+    conn.execute(
+        "INSERT INTO todo_items (id, sop_id, content, completed, sort_order, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        (item.id, item.sop_id, &item.content, item.completed, item.sort_order, &item.created_at, &item.updated_at),
+    )?;
+    Ok(())
+}
+
+fn set_todo_completed(conn: &Connection, id: i64, completed: bool) -> SqliteResult<()> {
+    conn.execute(
+        "UPDATE todo_items SET completed = ?1, updated_at = ?2 WHERE id = ?3",
+        (completed, chrono::Utc::now().to_rfc3339(), id),
+    )?;
+    Ok(())
+}
+
+fn set_todo_content(conn: &Connection, id: i64, content: &str) -> SqliteResult<()> {
+    conn.execute(
+        "UPDATE todo_items SET content = ?1, updated_at = ?2 WHERE id = ?3",
+        (content, chrono::Utc::now().to_rfc3339(), id),
+    )?;
+    Ok(())
+}
+
+fn todo_order(conn: &Connection, sop_id: i64) -> SqliteResult<Vec<i64>> {
+    let mut stmt = conn.prepare("SELECT id FROM todo_items WHERE sop_id = ?1 ORDER BY sort_order ASC, id ASC")?;
+    let ids = stmt.query_map([sop_id], |row| row.get(0))?;
+    ids.collect()
+}
+
+fn set_todo_order(conn: &Connection, item_ids: &[i64]) -> SqliteResult<()> {
+    let now = chrono::Utc::now().to_rfc3339();
+    for (index, id) in item_ids.iter().enumerate() {
+        conn.execute(
+            "UPDATE todo_items SET sort_order = ?1, updated_at = ?2 WHERE id = ?3",
+            (index as i64, &now, id),
+        )?;
+    }
+    Ok(())
+}
+
+fn todo_item_from_row(row: &rusqlite::Row) -> SqliteResult<TodoItem> {
+    Ok(TodoItem {
+        id: row.get(0)?,
+        sop_id: row.get(1)?,
+        content: row.get(2)?,
+        completed: row.get::<_, i32>(3)? != 0,
+        sort_order: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+    })
+}
+
+/// Upsert the flow_data row and append a revision unless the graph is unchanged
+fn write_flow_data(
+    conn: &Connection,
+    sop_id: i64,
+    nodes: &str,
+    edges: &str,
+    restored_from: Option<i64>,
+) -> SqliteResult<()> {
+    let now = chrono::Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO flow_data (sop_id, nodes, edges, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?4)
+         ON CONFLICT(sop_id) DO UPDATE SET nodes = excluded.nodes, edges = excluded.edges, updated_at = excluded.updated_at",
+        (sop_id, nodes, edges, &now),
+    )?;
+
+    let latest = conn.query_row(
+        "SELECT revision, nodes, edges FROM flow_revisions WHERE sop_id = ?1 ORDER BY revision DESC LIMIT 1",
+        [sop_id],
+        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)),
+    );
+    let next = match latest {
+        Ok((_, latest_nodes, latest_edges)) if latest_nodes == nodes && latest_edges == edges && restored_from.is_none() => {
+            return Ok(());
         }
+        Ok((revision, _, _)) => revision + 1,
+        Err(rusqlite::Error::QueryReturnedNoRows) => 1,
+        Err(e) => return Err(e),
+    };
+
+    conn.execute(
+        "INSERT INTO flow_revisions (sop_id, revision, nodes, edges, restored_from, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        (sop_id, next, nodes, edges, restored_from, &now),
+    )?;
+    Ok(())
+}
+
+fn flow_run_from_row(row: &rusqlite::Row) -> SqliteResult<FlowRun> {
+    Ok(FlowRun {
+        id: row.get(0)?,
+        sop_id: row.get(1)?,
+        revision_id: row.get(9)?,
+        operator: row.get(2)?,
+        status: row.get(3)?,
+        current_node_id: row.get(4)?,
+        draft_values: from_optional_json_column(row, 5)?,
+        started_at: row.get(6)?,
+        updated_at: row.get(7)?,
+        finished_at: row.get(8)?,
+    })
+}
+
+fn flow_run_step_from_row(row: &rusqlite::Row) -> SqliteResult<FlowRunStep> {
+    Ok(FlowRunStep {
+        id: row.get(0)?,
+        run_id: row.get(1)?,
+        seq: row.get(2)?,
+        node_id: row.get(3)?,
+        node_label: row.get(4)?,
+        node_shape: row.get(5)?,
+        entered_at: row.get(6)?,
+        reverted_at: row.get(7)?,
+    })
+}
+
+fn flow_run_input_from_row(row: &rusqlite::Row) -> SqliteResult<FlowRunInput> {
+    Ok(FlowRunInput {
+        id: row.get(0)?,
+        run_id: row.get(1)?,
+        step_id: row.get(2)?,
+        node_id: row.get(3)?,
+        values: from_json_column(row, 4)?,
+        submitted_at: row.get(5)?,
+        updated_at: row.get(6)?,
+    })
+}
+
+/// Deserialize a JSON text column into a typed value
+fn from_json_column<T: serde::de::DeserializeOwned>(row: &rusqlite::Row, idx: usize) -> SqliteResult<T> {
+    let text: String = row.get(idx)?;
+    serde_json::from_str(&text).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+/// Like `from_json_column`, with NULL mapping to `None`
+fn from_optional_json_column<T: serde::de::DeserializeOwned>(row: &rusqlite::Row, idx: usize) -> SqliteResult<Option<T>> {
+    let text: Option<String> = row.get(idx)?;
+    text.map(|text| {
+        serde_json::from_str(&text).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e))
+        })
+    })
+    .transpose()
+}
+
+/// Serialize a typed value into JSON text for storage
+fn to_json_column<T: serde::Serialize + ?Sized>(value: &T) -> SqliteResult<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow::fixtures::{edge, node};
+
+    fn open() -> Database {
+        Database::open(Path::new(":memory:")).unwrap()
+    }
+
+    fn create_sop(db: &Database, item_type: &str, folder_id: Option<i64>) -> i64 {
+        let item = CreateSopItem {
+            name: "Checklist".to_string(),
+            icon: "list".to_string(),
+            item_type: item_type.to_string(),
+            folder_id,
+        };
+        db.create_sop_item(&item).unwrap().id
+    }
+
+    fn count(db: &Database, sql: &str, id: i64) -> i64 {
+        db.conn().unwrap().query_row(sql, [id], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn deleting_a_parent_cascades() {
+        let db = open();
+        let folder = db.create_folder("Ops", None).unwrap();
+        let sop_id = create_sop(&db, "todo", Some(folder.id));
+        db.create_todo_item(&CreateTodoItem {
+            sop_id,
+            content: "Check".to_string(),
+        })
+        .unwrap();
+
+        db.conn().unwrap().execute("DELETE FROM folders WHERE id = ?1", [folder.id]).unwrap();
+        assert_eq!(get_sop_item(&db.conn().unwrap(), sop_id).unwrap().folder_id, None);
+
+        db.delete_sop_item(sop_id).unwrap();
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM todo_items WHERE sop_id = ?1", sop_id), 0);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM todo_operations WHERE sop_id = ?1", sop_id), 0);
+    }
+
+    #[test]
+    fn permanently_deleting_an_sop_leaves_nothing_behind() {
+        let db = open();
+        let sop_id = create_sop(&db, "flowchart", None);
+        let nodes = vec![node("start", "start"), node("form", "form"), node("end", "end")];
+        db.save_flow_data(sop_id, &nodes, &[edge("start", "form"), edge("form", "end")])
+            .unwrap();
+        let run = db.create_flow_run(sop_id, None, &nodes[0]).unwrap();
+        db.push_flow_run_steps(run.id, &[&nodes[1]]).unwrap();
+        let step = db.get_current_flow_run_step(run.id).unwrap().unwrap();
+        db.save_flow_run_input(run.id, &step, &serde_json::Map::new()).unwrap();
+        db.create_todo_item(&CreateTodoItem {
+            sop_id,
+            content: "Check".to_string(),
+        })
+        .unwrap();
+        let tag = db.create_tag("Weekly", None).unwrap();
+        db.set_sop_item_tags(sop_id, &[tag.id]).unwrap();
+
+        db.soft_delete_sop_item(sop_id).unwrap();
+        db.permanently_delete_sop_item(sop_id).unwrap();
+
+        for table in ["flow_data", "flow_revisions", "flow_runs", "todo_items", "todo_operations", "sop_item_tags"] {
+            let sql = format!("SELECT COUNT(*) FROM {} WHERE sop_id = ?1", table);
+            assert_eq!(count(&db, &sql, sop_id), 0, "{} rows left", table);
+        }
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM flow_run_steps WHERE run_id = ?1", run.id), 0);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM flow_run_inputs WHERE run_id = ?1", run.id), 0);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM search_index WHERE sop_id = ?1", sop_id), 0);
+        // The tag itself stays
+        assert_eq!(db.get_tags().unwrap().len(), 1);
+    }
+
+    fn todo_contents(state: &TodoListState) -> Vec<(&str, bool)> {
+        state.items.iter().map(|i| (i.content.as_str(), i.completed)).collect()
+    }
+
+    #[test]
+    fn undo_then_redo_todo_changes() {
+        let db = open();
+        let sop_id = create_sop(&db, "todo", None);
+        let add = |content: &str| {
+            db.create_todo_item(&CreateTodoItem {
+                sop_id,
+                content: content.to_string(),
+            })
+            .unwrap()
+        };
+        let first = add("Pack");
+        let second = add("Ship");
+        db.toggle_todo_item(first.id).unwrap();
+        db.update_todo_item(second.id, "Ship it").unwrap();
+        db.reorder_todo_items(&[second.id, first.id]).unwrap();
+        db.delete_todo_item(first.id).unwrap();
+        assert_eq!(todo_contents(&db.get_todo_list_state(sop_id).unwrap()), vec![("Ship it", false)]);
+
+        // Undo everything, newest change first
+        let mut states = Vec::new();
+        for _ in 0..6 {
+            states.push(db.undo_todo_operation(sop_id).unwrap());
+        }
+        assert_eq!(todo_contents(&states[0]), vec![("Ship it", false), ("Pack", true)]);
+        assert_eq!(todo_contents(&states[1]), vec![("Pack", true), ("Ship it", false)]);
+        assert_eq!(todo_contents(&states[2]), vec![("Pack", true), ("Ship", false)]);
+        assert_eq!(todo_contents(&states[3]), vec![("Pack", false), ("Ship", false)]);
+        assert_eq!(todo_contents(&states[5]), vec![]);
+        assert!(!states[5].can_undo);
+        assert!(states[5].can_redo);
+        // Nothing left to undo is not an error
+        assert_eq!(todo_contents(&db.undo_todo_operation(sop_id).unwrap()), vec![]);
+
+        // Redo replays them oldest first, with the original ids
+        let state = db.redo_todo_operation(sop_id).unwrap();
+        assert_eq!(state.items[0].id, first.id);
+        for _ in 0..5 {
+            db.redo_todo_operation(sop_id).unwrap();
+        }
+        let state = db.get_todo_list_state(sop_id).unwrap();
+        assert_eq!(todo_contents(&state), vec![("Ship it", false)]);
+        assert!(state.can_undo);
+        assert!(!state.can_redo);
+    }
+
+    #[test]
+    fn a_new_todo_change_clears_redo() {
+        let db = open();
+        let sop_id = create_sop(&db, "todo", None);
+        let item = db
+            .create_todo_item(&CreateTodoItem {
+                sop_id,
+                content: "Pack".to_string(),
+            })
+            .unwrap();
+        db.toggle_todo_item(item.id).unwrap();
+        db.update_todo_item(item.id, "Pack boxes").unwrap();
+
+        db.undo_todo_operation(sop_id).unwrap();
+        let state = db.undo_todo_operation(sop_id).unwrap();
+        assert!(state.can_redo);
+
+        db.update_todo_item(item.id, "Pack crates").unwrap();
+        let state = db.get_todo_list_state(sop_id).unwrap();
+        assert!(!state.can_redo);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM todo_operations WHERE sop_id = ?1", sop_id), 2);
+
+        // Redo has nothing to bring back, and undo reverts the new change
+        let state = db.redo_todo_operation(sop_id).unwrap();
+        assert_eq!(todo_contents(&state), vec![("Pack crates", false)]);
+        let state = db.undo_todo_operation(sop_id).unwrap();
+        assert_eq!(todo_contents(&state), vec![("Pack", false)]);
+        // Undo history is per SOP
+        let other = create_sop(&db, "todo", None);
+        assert!(!db.get_todo_list_state(other).unwrap().can_undo);
+    }
+
+    #[test]
+    fn reordering_takes_exactly_the_todos_of_one_sop() {
+        let db = open();
+        let sop_id = create_sop(&db, "todo", None);
+        let ids: Vec<i64> = ["a", "b", "c"]
+            .iter()
+            .map(|content| db.create_todo_item(&CreateTodoItem { sop_id, content: content.to_string() }).unwrap().id)
+            .collect();
+        let other_sop = create_sop(&db, "todo", None);
+        let stranger = db.create_todo_item(&CreateTodoItem { sop_id: other_sop, content: "x".to_string() }).unwrap().id;
+
+        let operations = count(&db, "SELECT COUNT(*) FROM todo_operations WHERE sop_id = ?1", sop_id);
+        for invalid in [
+            vec![ids[2], ids[0]],
+            vec![ids[2], ids[0], ids[1], ids[1]],
+            vec![ids[2], ids[0], ids[0]],
+            vec![ids[2], ids[0], ids[1], stranger],
+            vec![ids[2], ids[0], stranger],
+        ] {
+            let error = db.reorder_todo_items(&invalid).unwrap_err();
+            assert_eq!(error.code, crate::error::ErrorCode::Validation, "{:?}", invalid);
+        }
+        let order = |id| db.get_todo_items(id).unwrap().into_iter().map(|t| t.id).collect::<Vec<_>>();
+        assert_eq!(order(sop_id), ids);
+        assert_eq!(order(other_sop), [stranger]);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM todo_operations WHERE sop_id = ?1", sop_id), operations);
+
+        db.reorder_todo_items(&[ids[2], ids[0], ids[1]]).unwrap();
+        assert_eq!(order(sop_id), [ids[2], ids[0], ids[1]]);
+    }
+
+    #[test]
+    fn going_back_skips_decisions_in_one_step() {
+        let db = open();
+        let sop_id = create_sop(&db, "flowchart", None);
+        let nodes = [node("start", "start"), node("form", "form"), node("decide", "decision"), node("read", "read")];
+        let run = db.create_flow_run(sop_id, None, &nodes[0]).unwrap();
+        db.push_flow_run_steps(run.id, &[&nodes[1]]).unwrap();
+        db.push_flow_run_steps(run.id, &[&nodes[2], &nodes[3]]).unwrap();
+
+        let run = db.go_back_flow_run(run.id).unwrap();
+        assert_eq!(run.current_node_id.as_deref(), Some("form"));
+        let reverted: Vec<(String, bool)> = db
+            .get_flow_run_steps(run.id)
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.node_id, s.reverted_at.is_some()))
+            .collect();
+        assert_eq!(
+            reverted,
+            vec![
+                ("start".to_string(), false),
+                ("form".to_string(), false),
+                ("decide".to_string(), true),
+                ("read".to_string(), true),
+            ]
+        );
+
+        // The first step stays
+        db.go_back_flow_run(run.id).unwrap();
+        let run = db.go_back_flow_run(run.id).unwrap();
+        assert_eq!(run.current_node_id.as_deref(), Some("start"));
+    }
+
+    #[test]
+    fn sealing_leaves_no_plaintext_key_on_disk() {
+        let path = std::env::temp_dir().join(format!("zop-seal-{}.db", std::process::id()));
+        let key = "sk-plaintext-4f7c2a9e1b";
+        {
+            let db = Database::open(&path).unwrap();
+            db.conn()
+                .unwrap()
+                .execute(
+                    "INSERT INTO ai_config (base_url, api_key, model_name, created_at, updated_at)
+                     VALUES ('https://api.openai.com/v1', ?1, 'gpt-4o', '', '')",
+                    [key],
+                )
+                .unwrap();
+            let (id, _) = db.plaintext_api_keys().unwrap().remove(0);
+            db.seal_api_key(id, "sealed").unwrap();
+            db.erase_freed_data().unwrap();
+
+            for file in [path.clone(), path.with_extension("db-wal")] {
+                let bytes = std::fs::read(&file).unwrap_or_default();
+                assert!(!bytes.windows(key.len()).any(|w| w == key.as_bytes()), "{:?}", file);
+            }
+        }
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    fn round_trip(from: &Database, to: &Database, id: i64, include_revisions: bool) -> SopItem {
+        let json = serde_json::to_string(&from.export_sop_bundle(id, include_revisions).unwrap()).unwrap();
+        to.import_sop_bundle(&SopBundle::from_json(&json).unwrap(), None).unwrap()
+    }
+
+    fn revisions(db: &Database, sop_id: i64) -> Vec<(i64, i64)> {
+        let revisions = db.list_flow_revisions(sop_id).unwrap();
+        let mut revisions: Vec<_> = revisions.into_iter().map(|r| (r.revision, r.node_count)).collect();
+        revisions.sort();
+        revisions
+    }
+
+    #[test]
+    fn todo_bundles_round_trip_under_a_free_name() {
+        let db = open();
+        let sop_id = create_sop(&db, "todo", None);
+        for content in ["first", "second", "third"] {
+            db.create_todo_item(&CreateTodoItem { sop_id, content: content.to_string() }).unwrap();
+        }
+        let second = db.get_todo_items(sop_id).unwrap()[1].id;
+        db.toggle_todo_item(second).unwrap();
+        let tag = db.create_tag("daily", None).unwrap();
+        db.set_sop_item_tags(sop_id, &[tag.id]).unwrap();
+
+        let copy = round_trip(&db, &db, sop_id, false);
+        assert_ne!(copy.id, sop_id);
+        assert_eq!(copy.name, "Checklist (2)");
+        assert_eq!(copy.item_type, "todo");
+        assert_eq!(copy.tag_ids, vec![tag.id]);
+        let todos: Vec<_> = db.get_todo_items(copy.id).unwrap().into_iter().map(|t| (t.content, t.completed)).collect();
+        assert_eq!(todos, [("first".to_string(), false), ("second".to_string(), true), ("third".to_string(), false)]);
+        assert_eq!(db.get_todo_items(sop_id).unwrap().len(), 3);
+
+        assert_eq!(round_trip(&db, &db, sop_id, false).name, "Checklist (3)");
+
+        let other = open();
+        let copy = round_trip(&db, &other, sop_id, false);
+        assert_eq!(copy.name, "Checklist");
+        assert_eq!(other.get_tags().unwrap()[0].name, "daily");
+    }
+
+    #[test]
+    fn flow_bundles_round_trip_with_and_without_revisions() {
+        let db = open();
+        let sop_id = create_sop(&db, "flowchart", None);
+        db.save_flow_data(sop_id, &[node("start", "start")], &[]).unwrap();
+        let nodes = [node("start", "start"), node("step", "read")];
+        let edges = [edge("start", "step")];
+        db.save_flow_data(sop_id, &nodes, &edges).unwrap();
+        let flow = |db: &Database, id: i64| {
+            let flow = db.get_flow_data(id).unwrap().unwrap();
+            serde_json::to_value((flow.nodes, flow.edges)).unwrap()
+        };
+
+        let other = open();
+        let with_history = round_trip(&db, &other, sop_id, true);
+        assert_eq!(with_history.item_type, "flowchart");
+        assert_eq!(flow(&other, with_history.id), flow(&db, sop_id));
+        assert_eq!(revisions(&other, with_history.id), [(1, 1), (2, 2)]);
 
-        self.get_ai_config()?.ok_or(rusqlite::Error::QueryReturnedNoRows)
+        let without_history = round_trip(&db, &other, sop_id, false);
+        assert_eq!(without_history.name, "Checklist (2)");
+        assert_ne!(without_history.id, with_history.id);
+        assert_eq!(flow(&other, without_history.id), flow(&db, sop_id));
+        assert_eq!(revisions(&other, without_history.id), [(1, 2)]);
+        assert_eq!(revisions(&other, with_history.id), [(1, 1), (2, 2)]);
     }
 }
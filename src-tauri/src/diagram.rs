@@ -0,0 +1,566 @@
+//! Flowchart SOPs as Mermaid and Graphviz DOT diagrams, for embedding in wikis
+//! and design docs.
+//!
+//! Step kinds map to node shapes: start is a stadium (DOT: oval), read a
+//! rectangle, form a parallelogram, decision a rhombus and end a double circle.
+//! Exported node ids are `n0`, `n1`, ... because React Flow ids can contain
+//! characters, or be words like `end`, that Mermaid would misread.
+//!
+//! Mermaid import covers the common subset of `flowchart`/`graph` syntax:
+//! node shapes, chained links, `&` groups and link labels. Node ids may contain
+//! `-`, and circle (`--o`) and cross (`--x`) links import as plain links, since
+//! steps have no link markers. Styling, classes, click handlers and subgraph
+//! boundaries are ignored. Node kinds come from the shapes above, with any
+//! other shape becoming a read step, and nodes are laid out left to right by
+//! their distance from the nodes nothing links to.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+
+use crate::bundle::{BundledFlow, BundledSop, SopBundle};
+use crate::error::{ZopError, ZopResult};
+use crate::flow::{FlowEdge, FlowNode, FlowPosition, NodeShape};
+
+pub const MERMAID_EXTENSION: &str = "mmd";
+pub const DOT_EXTENSION: &str = "dot";
+
+/// Icon of SOPs created from a Mermaid file
+const IMPORTED_ICON: &str = "file-text";
+/// Spacing of imported nodes, matching the editor's left-to-right handles
+const LAYER_SPACING: f64 = 220.0;
+const ROW_SPACING: f64 = 120.0;
+const ORIGIN: f64 = 100.0;
+
+/// Whether `path` names a Mermaid file
+pub fn is_mermaid(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mmd") || ext.eq_ignore_ascii_case("mermaid"))
+}
+
+/// The flow of a bundle as a Mermaid `flowchart LR`
+pub fn mermaid(flow: &BundledFlow) -> String {
+    let ids = export_ids(&flow.nodes);
+    let mut text = String::from("flowchart LR\n");
+
+    for node in &flow.nodes {
+        let (open, close) = mermaid_brackets(node.shape());
+        text.push_str(&format!(
+            "    {}{}\"{}\"{}\n",
+            ids[node.id.as_str()],
+            open,
+            mermaid_text(node.label()),
+            close
+        ));
+    }
+    for edge in &flow.edges {
+        // Dangling edges would make Mermaid invent a node
+        let (Some(source), Some(target)) = (ids.get(edge.source.as_str()), ids.get(edge.target.as_str())) else {
+            continue;
+        };
+        match edge.label.as_deref().map(str::trim).filter(|l| !l.is_empty()) {
+            Some(label) => text.push_str(&format!("    {} -->|\"{}\"| {}\n", source, mermaid_text(label), target)),
+            None => text.push_str(&format!("    {} --> {}\n", source, target)),
+        }
+    }
+    text
+}
+
+/// The flow of a bundle as a DOT digraph named after the SOP
+pub fn dot(name: &str, flow: &BundledFlow) -> String {
+    let ids = export_ids(&flow.nodes);
+    let mut text = format!("digraph \"{}\" {{\n    rankdir=LR;\n", dot_text(name));
+
+    for node in &flow.nodes {
+        text.push_str(&format!(
+            "    {} [label=\"{}\", shape={}];\n",
+            ids[node.id.as_str()],
+            dot_text(node.label()),
+            dot_shape(node.shape())
+        ));
+    }
+    for edge in &flow.edges {
+        let (Some(source), Some(target)) = (ids.get(edge.source.as_str()), ids.get(edge.target.as_str())) else {
+            continue;
+        };
+        match edge.label.as_deref().map(str::trim).filter(|l| !l.is_empty()) {
+            Some(label) => text.push_str(&format!("    {} -> {} [label=\"{}\"];\n", source, target, dot_text(label))),
+            None => text.push_str(&format!("    {} -> {};\n", source, target)),
+        }
+    }
+    text.push_str("}\n");
+    text
+}
+
+pub fn write_mermaid(bundle: &SopBundle, path: &Path) -> ZopResult<()> {
+    fs::write(path, mermaid(flow_of(bundle)?))?;
+    Ok(())
+}
+
+pub fn write_dot(bundle: &SopBundle, path: &Path) -> ZopResult<()> {
+    fs::write(path, dot(&bundle.sop.name, flow_of(bundle)?))?;
+    Ok(())
+}
+
+/// A flowchart SOP named after the file, from a Mermaid flowchart
+pub fn read_mermaid(path: &Path) -> ZopResult<SopBundle> {
+    let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("Imported flowchart");
+    parse_mermaid(&fs::read_to_string(path)?, name)
+}
+
+pub fn parse_mermaid(text: &str, name: &str) -> ZopResult<SopBundle> {
+    let mut statements = text
+        .lines()
+        .flat_map(split_statements)
+        .map(str::trim)
+        .filter(|s| !s.is_empty() && !s.starts_with("%%"));
+
+    let header = statements.next().unwrap_or("");
+    let keyword = header.split_whitespace().next().unwrap_or("");
+    if keyword != "flowchart" && keyword != "graph" {
+        return Err(ZopError::validation("Not a Mermaid flowchart; the first line must start with `flowchart` or `graph`"));
+    }
+
+    let mut graph = MermaidGraph::default();
+    for statement in statements {
+        let first = statement.split_whitespace().next().unwrap_or("");
+        if IGNORED_STATEMENTS.contains(&first) {
+            continue;
+        }
+        graph.statement(statement).map_err(|message| {
+            ZopError::validation(format!("Could not read Mermaid statement `{}`: {}", statement, message))
+        })?;
+    }
+    if graph.nodes.is_empty() {
+        return Err(ZopError::validation("The Mermaid flowchart has no nodes"));
+    }
+
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut bundle = SopBundle::new(BundledSop {
+        name: name.trim().to_string(),
+        icon: IMPORTED_ICON.to_string(),
+        item_type: "flowchart".to_string(),
+        tags: Vec::new(),
+        created_at: now.clone(),
+        updated_at: now,
+    });
+    bundle.flow = Some(graph.into_flow());
+    Ok(bundle)
+}
+
+fn flow_of(bundle: &SopBundle) -> ZopResult<&BundledFlow> {
+    if bundle.sop.item_type != "flowchart" {
+        return Err(ZopError::validation("Only flowchart SOPs can be exported as a diagram"));
+    }
+    bundle
+        .flow
+        .as_ref()
+        .ok_or_else(|| ZopError::validation("This flowchart has no steps yet"))
+}
+
+fn export_ids(nodes: &[FlowNode]) -> HashMap<&str, String> {
+    nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.id.as_str(), format!("n{}", i)))
+        .collect()
+}
+
+fn mermaid_brackets(shape: NodeShape) -> (&'static str, &'static str) {
+    match shape {
+        NodeShape::Start => ("([", "])"),
+        NodeShape::Read => ("[", "]"),
+        NodeShape::Form => ("[/", "/]"),
+        NodeShape::Decision => ("{", "}"),
+        NodeShape::End => ("(((", ")))"),
+    }
+}
+
+fn dot_shape(shape: NodeShape) -> &'static str {
+    match shape {
+        NodeShape::Start => "oval",
+        NodeShape::Read => "box",
+        NodeShape::Form => "parallelogram",
+        NodeShape::Decision => "diamond",
+        NodeShape::End => "doublecircle",
+    }
+}
+
+/// Text for inside a quoted Mermaid label
+fn mermaid_text(text: &str) -> String {
+    text.trim().replace('"', "#quot;").replace('\n', "<br>")
+}
+
+/// Text for inside a quoted DOT string
+fn dot_text(text: &str) -> String {
+    text.trim().replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Statements that only style or group nodes
+const IGNORED_STATEMENTS: &[&str] = &["classDef", "class", "style", "linkStyle", "click", "subgraph", "end", "direction"];
+
+/// Openers of Mermaid node shapes with their closers, longest first so `((` wins over `(`
+const MERMAID_SHAPES: &[(&str, &str, NodeShape)] = &[
+    ("(((", ")))", NodeShape::End),
+    ("((", "))", NodeShape::End),
+    ("([", "])", NodeShape::Start),
+    ("[[", "]]", NodeShape::Read),
+    ("[(", ")]", NodeShape::Read),
+    ("[/", "/]", NodeShape::Form),
+    ("[\\", "\\]", NodeShape::Form),
+    ("[/", "\\]", NodeShape::Form),
+    ("[\\", "/]", NodeShape::Form),
+    ("{{", "}}", NodeShape::Read),
+    ("[", "]", NodeShape::Read),
+    ("{", "}", NodeShape::Decision),
+    ("(", ")", NodeShape::Read),
+    (">", "]", NodeShape::Read),
+];
+
+#[derive(Default)]
+struct MermaidGraph {
+    /// Nodes in order of first mention
+    nodes: Vec<(String, String, NodeShape)>,
+    index: HashMap<String, usize>,
+    edges: Vec<(String, String, Option<String>)>,
+}
+
+impl MermaidGraph {
+    /// Read `a --> b -->|label| c & d`: groups of nodes joined by links
+    fn statement(&mut self, statement: &str) -> Result<(), String> {
+        let (mut sources, mut rest) = self.node_group(statement)?;
+        while !rest.trim().is_empty() {
+            let (label, after_link) = link(rest)?;
+            let (targets, after_targets) = self.node_group(after_link)?;
+            for source in &sources {
+                for target in &targets {
+                    self.edges.push((source.clone(), target.clone(), label.clone()));
+                }
+            }
+            sources = targets;
+            rest = after_targets;
+        }
+        Ok(())
+    }
+
+    fn node_group<'a>(&mut self, text: &'a str) -> Result<(Vec<String>, &'a str), String> {
+        let mut ids = Vec::new();
+        let mut rest = text;
+        loop {
+            let (id, after) = self.node(rest)?;
+            ids.push(id);
+            match after.trim_start().strip_prefix('&') {
+                Some(next) => rest = next,
+                None => return Ok((ids, after)),
+            }
+        }
+    }
+
+    /// A node reference with an optional shape, registering it on first mention
+    fn node<'a>(&mut self, text: &'a str) -> Result<(String, &'a str), String> {
+        let text = text.trim_start();
+        let end = id_len(text);
+        if end == 0 {
+            return Err("expected a node id".to_string());
+        }
+        let id = &text[..end];
+        let mut rest = &text[end..];
+
+        let mut shape = None;
+        for (open, close, kind) in MERMAID_SHAPES {
+            let Some(inner) = rest.strip_prefix(open) else {
+                continue;
+            };
+            let Some(close_at) = closing(inner, close) else {
+                continue;
+            };
+            shape = Some((label_text(&inner[..close_at]), *kind));
+            rest = &inner[close_at + close.len()..];
+            break;
+        }
+
+        match (self.index.get(id), shape) {
+            (Some(&i), Some((label, kind))) => self.nodes[i] = (id.to_string(), label, kind),
+            (Some(_), None) => {}
+            (None, shape) => {
+                let (label, kind) = shape.unwrap_or_else(|| (id.to_string(), NodeShape::Read));
+                self.index.insert(id.to_string(), self.nodes.len());
+                self.nodes.push((id.to_string(), label, kind));
+            }
+        }
+        Ok((id.to_string(), rest))
+    }
+
+    fn into_flow(self) -> BundledFlow {
+        let layers = layers(&self.nodes, &self.edges, &self.index);
+        let mut rows: HashMap<usize, usize> = HashMap::new();
+
+        let nodes = self
+            .nodes
+            .iter()
+            .zip(&layers)
+            .map(|((id, label, shape), &layer)| {
+                let row = rows.entry(layer).or_insert(0);
+                let position = FlowPosition {
+                    x: ORIGIN + layer as f64 * LAYER_SPACING,
+                    y: ORIGIN + *row as f64 * ROW_SPACING,
+                };
+                *row += 1;
+                FlowNode::new(id.clone(), *shape, label.clone(), position)
+            })
+            .collect();
+
+        let edges = self
+            .edges
+            .into_iter()
+            .enumerate()
+            .map(|(i, (source, target, label))| {
+                FlowEdge::new(format!("edge-{}-{}-{}", source, target, i), source, target, label)
+            })
+            .collect();
+
+        BundledFlow { nodes, edges }
+    }
+}
+
+/// Column of each node: its distance from the nodes nothing links to, or from
+/// the first node when every node has an incoming link
+fn layers(
+    nodes: &[(String, String, NodeShape)],
+    edges: &[(String, String, Option<String>)],
+    index: &HashMap<String, usize>,
+) -> Vec<usize> {
+    let mut outgoing: Vec<Vec<usize>> = vec![Vec::new(); nodes.len()];
+    let mut has_incoming = vec![false; nodes.len()];
+    for (source, target, _) in edges {
+        outgoing[index[source]].push(index[target]);
+        has_incoming[index[target]] = true;
+    }
+
+    let mut layer: Vec<Option<usize>> = vec![None; nodes.len()];
+    let mut queue: VecDeque<usize> = (0..nodes.len()).filter(|&i| !has_incoming[i]).collect();
+    if queue.is_empty() {
+        queue.push_back(0);
+    }
+    for &i in &queue {
+        layer[i] = Some(0);
+    }
+    while let Some(i) = queue.pop_front() {
+        let next = layer[i].unwrap_or(0) + 1;
+        for &target in &outgoing[i] {
+            if layer[target].is_none() {
+                layer[target] = Some(next);
+                queue.push_back(target);
+            }
+        }
+    }
+    // Nodes only reachable through a cycle start a column of their own
+    layer.into_iter().map(|l| l.unwrap_or(0)).collect()
+}
+
+/// Statements of a line, which `;` separates unless it is inside a quoted label
+fn split_statements(line: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                statements.push(&line[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    statements.push(&line[start..]);
+    statements
+}
+
+/// Position of `close` in `text`, skipping over a quoted label
+fn closing(text: &str, close: &str) -> Option<usize> {
+    if let Some(quoted) = text.strip_prefix('"') {
+        let end = quoted.find('"')? + 1;
+        return text[end + 1..].find(close).map(|i| i + end + 1);
+    }
+    text.find(close)
+}
+
+/// Label of a node or link, unquoted and with Mermaid's escapes undone
+fn label_text(raw: &str) -> String {
+    let raw = raw.trim();
+    let raw = raw
+        .strip_prefix('"')
+        .and_then(|r| r.strip_suffix('"'))
+        .unwrap_or(raw);
+    raw.replace("#quot;", "\"")
+        .replace("<br>", "\n")
+        .replace("<br/>", "\n")
+        .replace("<br />", "\n")
+        .trim()
+        .to_string()
+}
+
+/// Length of the node id at the start of `text`. Ids can contain `-`, but not
+/// next to another `-` or a `.`, since those start a link: `step-1-->b`.
+fn id_len(text: &str) -> usize {
+    let is_id_char = |c: char| c.is_alphanumeric() || c == '_';
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let joins_id = c == '-' && i > 0 && chars.peek().is_some_and(|&(_, next)| is_id_char(next));
+        if !is_id_char(c) && !joins_id {
+            return i;
+        }
+    }
+    text.len()
+}
+
+/// Ends of the arrow after a `-- label` link's text
+const LABEL_CLOSERS: &[&str] = &["-->", "---", "==>", "===", ".->", "--o", "--x", "==o", "==x"];
+
+/// Length of the arrow at the start of `text`, including the `o` and `x` of
+/// circle and cross links (`--o`, `o--o`, `x--x`)
+fn arrow_len(text: &str) -> usize {
+    let is_arrow_char = |c: char| matches!(c, '-' | '=' | '.' | '<' | '>');
+    let start = match text.strip_prefix(['o', 'x']) {
+        Some(rest) if rest.starts_with(['-', '=']) => 1,
+        _ => 0,
+    };
+    let body = text[start..].find(|c| !is_arrow_char(c)).unwrap_or(text.len() - start);
+    let end = start + body;
+    if body >= 2 && text[start..end].ends_with(['-', '=']) && text[end..].starts_with(['o', 'x']) {
+        end + 1
+    } else {
+        end
+    }
+}
+
+/// A link such as `-->`, `---`, `-.->`, `==>`, `--o`, `-->|label|` or `-- label -->`,
+/// returning its label and the text after it
+fn link(text: &str) -> Result<(Option<String>, &str), String> {
+    let text = text.trim_start();
+    let arrow = &text[..arrow_len(text)];
+    if arrow.trim_matches(['o', 'x']).len() < 2 {
+        return Err("expected a link such as `-->`".to_string());
+    }
+    let mut rest = &text[arrow.len()..];
+
+    // `-- label -->`: the opening dashes are followed by text and the rest of the arrow
+    if arrow.len() == 2 && !arrow.ends_with('>') {
+        let close = LABEL_CLOSERS
+            .iter()
+            .filter_map(|closer| rest.find(closer))
+            .min()
+            .ok_or_else(|| "unterminated link label".to_string())?;
+        let label = label_text(&rest[..close]);
+        rest = &rest[close + arrow_len(&rest[close..])..];
+        return Ok((Some(label).filter(|l| !l.is_empty()), rest));
+    }
+
+    let rest = rest.trim_start();
+    if let Some(labelled) = rest.strip_prefix('|') {
+        let close = closing(labelled, "|").ok_or_else(|| "unterminated link label".to_string())?;
+        let label = label_text(&labelled[..close]);
+        return Ok((Some(label).filter(|l| !l.is_empty()), &labelled[close + 1..]));
+    }
+    Ok((None, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> BundledFlow {
+        parse_mermaid(text, "Imported").unwrap().flow.unwrap()
+    }
+
+    fn links(flow: &BundledFlow) -> Vec<(&str, &str, Option<&str>)> {
+        flow.edges
+            .iter()
+            .map(|e| (e.source.as_str(), e.target.as_str(), e.label.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_shapes_chains_and_groups() {
+        let flow = parse(
+            "flowchart LR
+            %% a comment
+            start([Begin]) --> ask{Approved?} -->|yes| a & b
+            ask -- no --> stop((Done)); a[/Fill in/] --> stop
+            style a fill:#f9f",
+        );
+        let nodes: Vec<_> = flow.nodes.iter().map(|n| (n.id.as_str(), n.data.label.as_str(), n.data.shape)).collect();
+        assert_eq!(
+            nodes,
+            [
+                ("start", "Begin", NodeShape::Start),
+                ("ask", "Approved?", NodeShape::Decision),
+                ("a", "Fill in", NodeShape::Form),
+                ("b", "b", NodeShape::Read),
+                ("stop", "Done", NodeShape::End),
+            ]
+        );
+        assert_eq!(
+            links(&flow),
+            [
+                ("start", "ask", None),
+                ("ask", "a", Some("yes")),
+                ("ask", "b", Some("yes")),
+                ("ask", "stop", Some("no")),
+                ("a", "stop", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_ids_with_dashes() {
+        let flow = parse("graph TD\nstep-1[First]-->step-2\nstep-2 -.-> last-step-3");
+        let ids: Vec<_> = flow.nodes.iter().map(|n| n.id.as_str()).collect();
+        assert_eq!(ids, ["step-1", "step-2", "last-step-3"]);
+        assert_eq!(links(&flow), [("step-1", "step-2", None), ("step-2", "last-step-3", None)]);
+    }
+
+    #[test]
+    fn reads_circle_and_cross_links() {
+        let flow = parse("flowchart LR\na --o b\nb --x c\nc o--o d\nd x--x|stop| e\ne -- why --o f\nf--xg");
+        assert_eq!(
+            links(&flow),
+            [
+                ("a", "b", None),
+                ("b", "c", None),
+                ("c", "d", None),
+                ("d", "e", Some("stop")),
+                ("e", "f", Some("why")),
+                ("f", "g", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_read() {
+        let error = parse_mermaid("sequenceDiagram\nA->>B: hi", "x").unwrap_err();
+        assert!(error.message.contains("Not a Mermaid flowchart"));
+
+        let error = parse_mermaid("flowchart LR\na - b", "x").unwrap_err();
+        assert!(error.message.contains("`a - b`"), "{}", error.message);
+
+        assert!(parse_mermaid("flowchart LR\na -- unterminated b", "x").is_err());
+        assert!(parse_mermaid("flowchart LR\n%% nothing", "x").is_err());
+    }
+
+    #[test]
+    fn exported_flows_read_back() {
+        let flow = parse("flowchart LR\ns([Start]) --> q{\"Is it ok?\"} -->|yes| e(((End)))\nq -->|no| s");
+        let again = parse(&mermaid(&flow));
+        let shapes = |flow: &BundledFlow| -> Vec<(String, NodeShape)> {
+            flow.nodes.iter().map(|n| (n.data.label.clone(), n.data.shape)).collect()
+        };
+        assert_eq!(shapes(&again), shapes(&flow));
+        assert_eq!(again.edges.len(), flow.edges.len());
+    }
+}
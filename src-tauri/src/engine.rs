@@ -0,0 +1,402 @@
+//! Execution engine for flowchart SOPs.
+//!
+//! Given the typed graph and the state of a run (its current node and the form
+//! values entered so far), the engine decides which node comes next. Edges may
+//! carry a condition over earlier form inputs: the first outgoing edge whose
+//! condition holds is taken, and an edge without a condition acts as the
+//! default branch. `decision` nodes are passed through automatically.
+//!
+//! A run is a single path, so a node may have at most one default branch;
+//! `flow::validate` rejects graphs that fan out into parallel branches.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::flow::{FlowEdge, FlowNode, NodeShape};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConditionOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Contains,
+    IsEmpty,
+    IsNotEmpty,
+}
+
+/// A test of one form field entered earlier in the run
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct EdgeCondition {
+    pub field: String,
+    pub op: ConditionOp,
+    #[serde(default)]
+    pub value: Value,
+}
+
+impl EdgeCondition {
+    pub fn matches(&self, inputs: &Map<String, Value>) -> bool {
+        let actual = inputs.get(&self.field).filter(|v| !is_empty(v));
+        match (self.op, actual) {
+            (ConditionOp::IsEmpty, actual) => actual.is_none(),
+            (ConditionOp::IsNotEmpty, actual) => actual.is_some(),
+            (_, None) => false,
+            (ConditionOp::Eq, Some(actual)) => values_equal(actual, &self.value),
+            (ConditionOp::Ne, Some(actual)) => !values_equal(actual, &self.value),
+            (ConditionOp::Contains, Some(actual)) => match (actual, &self.value) {
+                (Value::String(haystack), Value::String(needle)) => haystack.contains(needle.as_str()),
+                (Value::Array(items), needle) => items.iter().any(|item| values_equal(item, needle)),
+                _ => false,
+            },
+            (op, Some(actual)) => match compare(actual, &self.value) {
+                Some(ordering) => match op {
+                    ConditionOp::Gt => ordering.is_gt(),
+                    ConditionOp::Gte => ordering.is_ge(),
+                    ConditionOp::Lt => ordering.is_lt(),
+                    _ => ordering.is_le(),
+                },
+                None => false,
+            },
+        }
+    }
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.trim().is_empty(),
+        _ => false,
+    }
+}
+
+fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(x), Some(y)) => x == y,
+        _ => a == b,
+    }
+}
+
+/// Numbers compare numerically, strings lexically (which orders ISO dates correctly)
+fn compare(a: &Value, b: &Value) -> Option<std::cmp::Ordering> {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64()?.partial_cmp(&y.as_f64()?),
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(tag = "kind", content = "node_id", rename_all = "snake_case")]
+pub enum EngineError {
+    UnknownNode(String),
+    /// None of the outgoing edges of the node applies to the entered values
+    NoMatchingBranch(String),
+    /// Decision nodes lead back into each other without reaching a step
+    DecisionLoop(String),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::UnknownNode(id) => write!(f, "Node {} not found in flow", id),
+            EngineError::NoMatchingBranch(id) => write!(f, "No outgoing edge of node {} matches the entered values", id),
+            EngineError::DecisionLoop(id) => write!(f, "Decision node {} loops without reaching a step", id),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+/// Pick the edge to follow out of `node_id`
+pub fn choose_edge<'a>(edges: &'a [FlowEdge], node_id: &str, inputs: &Map<String, Value>) -> Option<&'a FlowEdge> {
+    let mut default = None;
+    for edge in edges.iter().filter(|e| e.source == node_id) {
+        match &edge.condition {
+            Some(condition) if condition.matches(inputs) => return Some(edge),
+            Some(_) => {}
+            None => {
+                default.get_or_insert(edge);
+            }
+        }
+    }
+    default
+}
+
+/// Nodes entered when leaving `current_id`: any decision nodes passed through,
+/// followed by the next step. Empty when `current_id` is an end node.
+pub fn advance<'a>(
+    nodes: &'a [FlowNode],
+    edges: &[FlowEdge],
+    current_id: &str,
+    inputs: &Map<String, Value>,
+) -> Result<Vec<&'a FlowNode>, EngineError> {
+    let find = |id: &str| {
+        nodes
+            .iter()
+            .find(|n| n.id == id)
+            .ok_or_else(|| EngineError::UnknownNode(id.to_string()))
+    };
+
+    let mut node = find(current_id)?;
+    let mut entered = Vec::new();
+    let mut decisions = HashSet::new();
+
+    loop {
+        if node.shape() == NodeShape::End {
+            return Ok(entered);
+        }
+        let edge = choose_edge(edges, &node.id, inputs).ok_or_else(|| EngineError::NoMatchingBranch(node.id.clone()))?;
+        node = find(&edge.target)?;
+        entered.push(node);
+
+        if node.shape() != NodeShape::Decision {
+            return Ok(entered);
+        }
+        if !decisions.insert(node.id.as_str()) {
+            return Err(EngineError::DecisionLoop(node.id.clone()));
+        }
+    }
+}
+
+/// Steps expected after `current_id` if the run continued with the values entered so far.
+/// Stops at an end node, at a branch that cannot be decided yet, or before revisiting a node.
+pub fn projected_path<'a>(
+    nodes: &'a [FlowNode],
+    edges: &[FlowEdge],
+    current_id: &str,
+    inputs: &Map<String, Value>,
+) -> Vec<&'a FlowNode> {
+    let mut path: Vec<&FlowNode> = Vec::new();
+    let mut seen = HashSet::from([current_id.to_string()]);
+    let mut current = current_id.to_string();
+
+    while let Ok(entered) = advance(nodes, edges, &current, inputs) {
+        let Some(next) = entered.last() else {
+            break;
+        };
+        if !seen.insert(next.id.clone()) {
+            break;
+        }
+        current = next.id.clone();
+        path.push(next);
+    }
+
+    path
+}
+
+/// Nodes reachable from the start node, each after every node that can lead to
+/// it (loops aside), with earlier outgoing edges' branches first. This is the
+/// order a printed procedure lists its steps in.
+pub fn execution_order<'a>(nodes: &'a [FlowNode], edges: &[FlowEdge]) -> Vec<&'a FlowNode> {
+    let Some(start) = nodes.iter().position(|n| n.shape() == NodeShape::Start) else {
+        return Vec::new();
+    };
+    let index: HashMap<&str, usize> = nodes.iter().enumerate().map(|(i, n)| (n.id.as_str(), i)).collect();
+    let mut outgoing: Vec<Vec<usize>> = vec![Vec::new(); nodes.len()];
+    for edge in edges {
+        if let (Some(&source), Some(&target)) = (index.get(edge.source.as_str()), index.get(edge.target.as_str())) {
+            outgoing[source].push(target);
+        }
+    }
+
+    // Reverse postorder of a depth-first walk. Children are visited last edge
+    // first so that, once reversed, the first edge's branch comes first.
+    let mut visited = vec![false; nodes.len()];
+    let mut postorder = Vec::new();
+    let mut stack = vec![(start, outgoing[start].len())];
+    visited[start] = true;
+    while let Some((node, remaining)) = stack.last_mut() {
+        if *remaining == 0 {
+            postorder.push(*node);
+            stack.pop();
+            continue;
+        }
+        *remaining -= 1;
+        let next = outgoing[*node][*remaining];
+        if !visited[next] {
+            visited[next] = true;
+            stack.push((next, outgoing[next].len()));
+        }
+    }
+
+    postorder.into_iter().rev().map(|i| &nodes[i]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow::fixtures::{edge, node, with_condition};
+    use serde_json::json;
+
+    fn condition(condition: Value) -> EdgeCondition {
+        serde_json::from_value(condition).unwrap()
+    }
+
+    fn inputs(values: Value) -> Map<String, Value> {
+        serde_json::from_value(values).unwrap()
+    }
+
+    fn ids(nodes: Vec<&FlowNode>) -> Vec<&str> {
+        nodes.into_iter().map(|n| n.id.as_str()).collect()
+    }
+
+    /// start -> form -> decision: amount >= 100 goes to "approve", anything else to "file"
+    fn approval_flow() -> (Vec<FlowNode>, Vec<FlowEdge>) {
+        let nodes = vec![
+            node("start", "start"),
+            node("form", "form"),
+            node("decide", "decision"),
+            node("approve", "read"),
+            node("file", "read"),
+            node("end", "end"),
+        ];
+        let edges = vec![
+            edge("start", "form"),
+            edge("form", "decide"),
+            with_condition(edge("decide", "approve"), json!({"field": "amount", "op": "gte", "value": 100})),
+            edge("decide", "file"),
+            edge("approve", "end"),
+            edge("file", "end"),
+        ];
+        (nodes, edges)
+    }
+
+    #[test]
+    fn conditions() {
+        let values = inputs(json!({"amount": 150, "note": "  ", "tags": ["a", "b"], "due": "2024-05-01", "who": "ops team"}));
+        let holds = |c: Value| condition(c).matches(&values);
+
+        assert!(holds(json!({"field": "amount", "op": "eq", "value": 150.0})));
+        assert!(holds(json!({"field": "amount", "op": "ne", "value": 10})));
+        assert!(holds(json!({"field": "amount", "op": "gt", "value": 100})));
+        assert!(!holds(json!({"field": "amount", "op": "lt", "value": 150})));
+        assert!(holds(json!({"field": "amount", "op": "lte", "value": 150})));
+        // ISO dates order as strings
+        assert!(holds(json!({"field": "due", "op": "gte", "value": "2024-04-30"})));
+        assert!(holds(json!({"field": "who", "op": "contains", "value": "ops"})));
+        assert!(holds(json!({"field": "tags", "op": "contains", "value": "b"})));
+        // Blank strings and missing fields are empty
+        assert!(holds(json!({"field": "note", "op": "is_empty"})));
+        assert!(holds(json!({"field": "missing", "op": "is_empty"})));
+        assert!(holds(json!({"field": "amount", "op": "is_not_empty"})));
+        // A missing value fails every comparison, even "not equal"
+        assert!(!holds(json!({"field": "missing", "op": "ne", "value": 1})));
+        // Mismatched types don't compare
+        assert!(!holds(json!({"field": "amount", "op": "gt", "value": "100"})));
+    }
+
+    #[test]
+    fn choose_edge_prefers_a_matching_condition_over_the_default() {
+        let (_, edges) = approval_flow();
+        let chosen = |values: Value| choose_edge(&edges, "decide", &inputs(values)).map(|e| e.target.as_str());
+
+        assert_eq!(chosen(json!({"amount": 100})), Some("approve"));
+        assert_eq!(chosen(json!({"amount": 99})), Some("file"));
+        assert_eq!(chosen(json!({})), Some("file"));
+        assert_eq!(choose_edge(&edges, "end", &Map::new()), None);
+
+        // Without a default branch nothing applies
+        let conditional = &edges[2..3];
+        assert_eq!(choose_edge(conditional, "decide", &inputs(json!({"amount": 1}))), None);
+    }
+
+    #[test]
+    fn advance_passes_through_decisions() {
+        let (nodes, edges) = approval_flow();
+
+        assert_eq!(ids(advance(&nodes, &edges, "start", &Map::new()).unwrap()), vec!["form"]);
+        assert_eq!(
+            ids(advance(&nodes, &edges, "form", &inputs(json!({"amount": 250}))).unwrap()),
+            vec!["decide", "approve"]
+        );
+        assert_eq!(ids(advance(&nodes, &edges, "form", &Map::new()).unwrap()), vec!["decide", "file"]);
+        assert!(advance(&nodes, &edges, "end", &Map::new()).unwrap().is_empty());
+        assert_eq!(
+            advance(&nodes, &edges, "ghost", &Map::new()),
+            Err(EngineError::UnknownNode("ghost".to_string()))
+        );
+    }
+
+    #[test]
+    fn advance_reports_stuck_runs() {
+        let (nodes, mut edges) = approval_flow();
+        edges.retain(|e| e.id != "decide-file");
+        assert_eq!(
+            advance(&nodes, &edges, "form", &Map::new()),
+            Err(EngineError::NoMatchingBranch("decide".to_string()))
+        );
+
+        let nodes = vec![node("read", "read"), node("a", "decision"), node("b", "decision")];
+        let edges = vec![edge("read", "a"), edge("a", "b"), edge("b", "a")];
+        assert_eq!(
+            advance(&nodes, &edges, "read", &Map::new()),
+            Err(EngineError::DecisionLoop("a".to_string()))
+        );
+    }
+
+    #[test]
+    fn projected_path_follows_the_entered_values() {
+        let (nodes, edges) = approval_flow();
+
+        assert_eq!(
+            ids(projected_path(&nodes, &edges, "start", &inputs(json!({"amount": 500})))),
+            vec!["form", "approve", "end"]
+        );
+        assert_eq!(ids(projected_path(&nodes, &edges, "start", &Map::new())), vec!["form", "file", "end"]);
+        assert!(projected_path(&nodes, &edges, "end", &Map::new()).is_empty());
+
+        // Stops before going round a loop again
+        let nodes = vec![node("start", "start"), node("a", "read"), node("b", "read")];
+        let edges = vec![edge("start", "a"), edge("a", "b"), edge("b", "a")];
+        assert_eq!(ids(projected_path(&nodes, &edges, "start", &Map::new())), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn execution_order_lists_both_branches_before_they_join() {
+        let nodes = vec![
+            node("end", "end"),
+            node("join", "read"),
+            node("b", "read"),
+            node("a", "read"),
+            node("decide", "decision"),
+            node("start", "start"),
+        ];
+        let edges = vec![
+            edge("start", "decide"),
+            edge("decide", "a"),
+            edge("decide", "b"),
+            edge("a", "join"),
+            edge("b", "join"),
+            edge("join", "end"),
+        ];
+        assert_eq!(ids(execution_order(&nodes, &edges)), ["start", "decide", "a", "b", "join", "end"]);
+
+        let (nodes, edges) = approval_flow();
+        assert_eq!(ids(execution_order(&nodes, &edges)), ["start", "form", "decide", "approve", "file", "end"]);
+    }
+
+    #[test]
+    fn execution_order_survives_cycles_and_skips_unreachable_nodes() {
+        let nodes = vec![
+            node("start", "start"),
+            node("fill", "form"),
+            node("check", "decision"),
+            node("orphan", "read"),
+            node("end", "end"),
+        ];
+        let edges = vec![
+            edge("start", "fill"),
+            edge("fill", "check"),
+            edge("check", "fill"),
+            edge("check", "end"),
+            edge("orphan", "end"),
+        ];
+        assert_eq!(ids(execution_order(&nodes, &edges)), ["start", "fill", "check", "end"]);
+        assert!(execution_order(&nodes[1..], &edges).is_empty());
+    }
+}
@@ -0,0 +1,238 @@
+//! Error type returned by every command.
+//!
+//! Errors reach the frontend as `{ code, message, details? }`. `code` is stable
+//! so the UI can branch on it and localize; `message` is an English fallback.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+
+use crate::engine::EngineError;
+use crate::migrations::MigrationError;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The requested record does not exist
+    NotFound,
+    /// Input was rejected; `details` lists what is wrong
+    Validation,
+    /// The operation doesn't apply to the record in its current state
+    InvalidState,
+    /// A write would violate a uniqueness or reference constraint
+    Conflict,
+    /// Another connection holds the database
+    DatabaseLocked,
+    Database,
+    /// The database was written by a newer version of the app, or could not be upgraded
+    Migration,
+    Io,
+    AiNotConfigured,
+    AiUnauthorized,
+    AiRateLimited,
+    AiModelNotFound,
+    /// The provider could not be reached
+    AiUnreachable,
+    /// The provider's host name could not be resolved
+    AiHostNotFound,
+    /// The TLS handshake with the provider failed, e.g. an untrusted certificate
+    AiTls,
+    /// The provider did not answer in time
+    AiTimeout,
+    /// The provider answered with something that is not a usable SOP
+    AiBadResponse,
+    /// Any other error reported by the provider
+    AiProvider,
+    /// The Python agent sidecar could not be started or failed
+    Sidecar,
+    /// The user cancelled the operation
+    Cancelled,
+    /// The encrypted secret store could not be read or decrypted
+    SecretStore,
+    Internal,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ZopError {
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+pub type ZopResult<T> = Result<T, ZopError>;
+
+impl ZopError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    /// Attach structured context, e.g. the diagnostics that made validation fail
+    pub fn with_details(mut self, details: impl Serialize) -> Self {
+        self.details = serde_json::to_value(details).ok();
+        self
+    }
+
+    pub fn not_found(what: &str, id: impl fmt::Display) -> Self {
+        Self::new(ErrorCode::NotFound, format!("{} {} not found", what, id))
+    }
+
+    pub fn validation(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Validation, message)
+    }
+
+    pub fn invalid_state(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidState, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+}
+
+impl fmt::Display for ZopError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ZopError {}
+
+impl From<rusqlite::Error> for ZopError {
+    fn from(e: rusqlite::Error) -> Self {
+        use rusqlite::ErrorCode as Sqlite;
+
+        let code = match &e {
+            rusqlite::Error::QueryReturnedNoRows => ErrorCode::NotFound,
+            rusqlite::Error::SqliteFailure(failure, _) => match failure.code {
+                Sqlite::DatabaseBusy | Sqlite::DatabaseLocked => ErrorCode::DatabaseLocked,
+                Sqlite::ConstraintViolation => ErrorCode::Conflict,
+                _ => ErrorCode::Database,
+            },
+            _ => ErrorCode::Database,
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+impl From<MigrationError> for ZopError {
+    fn from(e: MigrationError) -> Self {
+        match e {
+            MigrationError::Sqlite(e) => e.into(),
+            e @ MigrationError::TooNew { .. } => Self::new(ErrorCode::Migration, e.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for ZopError {
+    fn from(e: std::io::Error) -> Self {
+        Self::new(ErrorCode::Io, e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for ZopError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::new(ErrorCode::Internal, e.to_string())
+    }
+}
+
+impl From<tauri::Error> for ZopError {
+    fn from(e: tauri::Error) -> Self {
+        Self::internal(e.to_string())
+    }
+}
+
+impl From<EngineError> for ZopError {
+    fn from(e: EngineError) -> Self {
+        Self::invalid_state(e.to_string()).with_details(&e)
+    }
+}
+
+impl From<async_openai::error::OpenAIError> for ZopError {
+    fn from(e: async_openai::error::OpenAIError) -> Self {
+        use async_openai::error::OpenAIError;
+
+        let code = match &e {
+            OpenAIError::ApiError(api) => {
+                let kind = format!(
+                    "{} {}",
+                    api.code.as_deref().unwrap_or_default(),
+                    api.r#type.as_deref().unwrap_or_default()
+                );
+                if kind.contains("invalid_api_key") || kind.contains("authentication") || kind.contains("permission") {
+                    ErrorCode::AiUnauthorized
+                } else if kind.contains("rate_limit") || kind.contains("insufficient_quota") {
+                    ErrorCode::AiRateLimited
+                } else if kind.contains("model_not_found") {
+                    ErrorCode::AiModelNotFound
+                } else {
+                    ErrorCode::AiProvider
+                }
+            }
+            OpenAIError::Reqwest(e) => return ai_http_error(e),
+            OpenAIError::JSONDeserialize(_) => ErrorCode::AiBadResponse,
+            // Streams only keep the text of their errors, e.g. "Invalid status code: 401 Unauthorized"
+            OpenAIError::StreamError(message) => match message.strip_prefix("Invalid status code: ") {
+                Some(status) => status.get(..3).and_then(|s| s.parse().ok()).map_or(ErrorCode::AiProvider, ai_status_code),
+                None if message.starts_with("Transport error") => ErrorCode::AiUnreachable,
+                None => ErrorCode::AiBadResponse,
+            },
+            _ => ErrorCode::AiProvider,
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+/// Classify a failed HTTP exchange with an AI provider or the agent
+pub fn ai_http_error(e: &reqwest::Error) -> ZopError {
+    let code = match e.status() {
+        Some(status) => ai_status_code(status.as_u16()),
+        None if e.is_timeout() => ErrorCode::AiTimeout,
+        None if e.is_connect() => connect_error_code(e),
+        None if e.is_decode() => ErrorCode::AiBadResponse,
+        None => ErrorCode::AiProvider,
+    };
+    ZopError::new(code, error_chain(e))
+}
+
+/// The error code for an HTTP error status from an AI provider or the agent
+pub fn ai_status_code(status: u16) -> ErrorCode {
+    match status {
+        401 | 403 => ErrorCode::AiUnauthorized,
+        404 => ErrorCode::AiModelNotFound,
+        408 | 504 => ErrorCode::AiTimeout,
+        429 => ErrorCode::AiRateLimited,
+        _ => ErrorCode::AiProvider,
+    }
+}
+
+/// Why a connection could not be made. reqwest only says that connecting
+/// failed, so this looks at the errors it wraps.
+fn connect_error_code(e: &reqwest::Error) -> ErrorCode {
+    let chain = error_chain(e).to_lowercase();
+    let dns = ["dns error", "failed to lookup address", "name or service not known", "no such host"];
+    let tls = ["certificate", "tls", "ssl", "handshake"];
+    if dns.iter().any(|s| chain.contains(s)) {
+        ErrorCode::AiHostNotFound
+    } else if tls.iter().any(|s| chain.contains(s)) {
+        ErrorCode::AiTls
+    } else {
+        ErrorCode::AiUnreachable
+    }
+}
+
+/// `e` and everything it wraps, since reqwest's own message rarely says what went wrong
+fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut text = e.to_string();
+    let mut source = e.source();
+    while let Some(inner) = source {
+        text.push_str(": ");
+        text.push_str(&inner.to_string());
+        source = inner.source();
+    }
+    text
+}
@@ -0,0 +1,45 @@
+//! Where exported files go.
+
+use std::path::PathBuf;
+
+use crate::error::{ErrorCode, ZopError, ZopResult};
+
+/// `path` when the user chose one, otherwise a file named after the SOP in the
+/// Downloads folder, numbered so an earlier export is not overwritten
+pub fn output_path(path: Option<&str>, name: &str, extension: &str) -> ZopResult<PathBuf> {
+    if let Some(path) = path.filter(|p| !p.trim().is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+
+    let dir = dirs::download_dir()
+        .or_else(dirs::home_dir)
+        .ok_or_else(|| ZopError::new(ErrorCode::Io, "Could not find a folder to export to; choose a path"))?;
+    let stem = file_stem(name);
+
+    let mut candidate = dir.join(format!("{}.{}", stem, extension));
+    let mut n = 2;
+    while candidate.exists() {
+        candidate = dir.join(format!("{} ({}).{}", stem, n, extension));
+        n += 1;
+    }
+    Ok(candidate)
+}
+
+/// `name` with characters that aren't allowed in file names replaced
+fn file_stem(name: &str) -> String {
+    let stem: String = name
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let stem = stem.trim_matches('.').trim();
+    if stem.is_empty() {
+        "sop".to_string()
+    } else {
+        stem.to_string()
+    }
+}
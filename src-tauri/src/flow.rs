@@ -0,0 +1,697 @@
+//! Typed model of a flowchart SOP.
+//!
+//! The graph is stored in the same shape React Flow uses on the frontend, so
+//! nodes keep their `data` object. Every struct carries an `extra` map that
+//! collects fields the backend does not know about (React Flow's `measured`,
+//! `selected`, handles, ...) so saving a graph never drops them.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+
+use crate::engine::EdgeCondition;
+use crate::form::{self, FormField};
+
+/// React Flow node and edge types the editor renders
+const EDITOR_NODE_TYPE: &str = "editable";
+const EDITOR_EDGE_TYPE: &str = "smoothstep";
+
+/// Kind of step a node represents
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeShape {
+    Start,
+    Read,
+    Form,
+    /// Routes the run along one of its outgoing edges based on earlier inputs
+    Decision,
+    End,
+}
+
+impl NodeShape {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeShape::Start => "start",
+            NodeShape::Read => "read",
+            NodeShape::Form => "form",
+            NodeShape::Decision => "decision",
+            NodeShape::End => "end",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct FlowPosition {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Step-specific configuration of a node
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct NodeConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Inputs collected by a form node
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FormField>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FlowNodeData {
+    #[serde(default)]
+    pub label: String,
+    pub shape: NodeShape,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<NodeConfig>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FlowNode {
+    pub id: String,
+    #[serde(default)]
+    pub position: FlowPosition,
+    pub data: FlowNodeData,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl FlowNode {
+    /// A node the editor can display, for graphs built outside of it (imports)
+    pub fn new(id: String, shape: NodeShape, label: String, position: FlowPosition) -> Self {
+        Self {
+            id,
+            position,
+            data: FlowNodeData {
+                label,
+                shape,
+                config: None,
+                extra: Map::new(),
+            },
+            extra: Map::from_iter([("type".to_string(), Value::from(EDITOR_NODE_TYPE))]),
+        }
+    }
+
+    pub fn shape(&self) -> NodeShape {
+        self.data.shape
+    }
+
+    pub fn label(&self) -> &str {
+        &self.data.label
+    }
+
+    /// Markdown content of a read/form step, if any
+    pub fn content(&self) -> Option<&str> {
+        self.data.config.as_ref().and_then(|c| c.content.as_deref())
+    }
+
+    /// Input fields declared by a form node
+    pub fn fields(&self) -> &[FormField] {
+        self.data.config.as_ref().map_or(&[], |c| c.fields.as_slice())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FlowEdge {
+    pub id: String,
+    pub source: String,
+    pub target: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Edge is only followed when this holds; edges without one are the default branch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<EdgeCondition>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl FlowEdge {
+    /// An edge the editor can display, for graphs built outside of it (imports)
+    pub fn new(id: String, source: String, target: String, label: Option<String>) -> Self {
+        Self {
+            id,
+            source,
+            target,
+            label,
+            condition: None,
+            extra: Map::from_iter([("type".to_string(), Value::from(EDITOR_EDGE_TYPE))]),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Stable identifier of a validation rule, so the frontend can localize messages
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticCode {
+    MissingStart,
+    DuplicateStart,
+    UnreachableNode,
+    DeadEnd,
+    Cycle,
+    DanglingEdge,
+    EmptyContent,
+    InvalidField,
+    UnknownConditionField,
+    MissingDefaultBranch,
+    AmbiguousBranch,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FlowDiagnostic {
+    pub code: DiagnosticCode,
+    pub severity: Severity,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edge_id: Option<String>,
+}
+
+impl FlowDiagnostic {
+    fn node(code: DiagnosticCode, severity: Severity, node: &FlowNode, message: String) -> Self {
+        Self {
+            code,
+            severity,
+            message,
+            node_id: Some(node.id.clone()),
+            edge_id: None,
+        }
+    }
+
+    fn edge(code: DiagnosticCode, severity: Severity, edge: &FlowEdge, message: String) -> Self {
+        Self {
+            code,
+            severity,
+            message,
+            node_id: None,
+            edge_id: Some(edge.id.clone()),
+        }
+    }
+}
+
+/// Whether any diagnostic prevents the flow from being executed
+pub fn has_errors(diagnostics: &[FlowDiagnostic]) -> bool {
+    diagnostics.iter().any(|d| d.severity == Severity::Error)
+}
+
+/// Check a flow graph for structural problems
+pub fn validate(nodes: &[FlowNode], edges: &[FlowEdge]) -> Vec<FlowDiagnostic> {
+    let mut diagnostics = Vec::new();
+    let index: HashMap<&str, usize> = nodes.iter().enumerate().map(|(i, n)| (n.id.as_str(), i)).collect();
+
+    // Adjacency over edges whose endpoints both exist
+    let mut outgoing: Vec<Vec<(usize, &FlowEdge)>> = vec![Vec::new(); nodes.len()];
+    for edge in edges {
+        match (index.get(edge.source.as_str()), index.get(edge.target.as_str())) {
+            (Some(&source), Some(&target)) => outgoing[source].push((target, edge)),
+            _ => diagnostics.push(FlowDiagnostic::edge(
+                DiagnosticCode::DanglingEdge,
+                Severity::Error,
+                edge,
+                format!("Edge connects missing node(s) {} -> {}", edge.source, edge.target),
+            )),
+        }
+    }
+
+    let starts: Vec<usize> = (0..nodes.len()).filter(|&i| nodes[i].shape() == NodeShape::Start).collect();
+    match starts.split_first() {
+        None => diagnostics.push(FlowDiagnostic {
+            code: DiagnosticCode::MissingStart,
+            severity: Severity::Error,
+            message: "Flow has no start node".to_string(),
+            node_id: None,
+            edge_id: None,
+        }),
+        Some((_, duplicates)) => {
+            for &i in duplicates {
+                diagnostics.push(FlowDiagnostic::node(
+                    DiagnosticCode::DuplicateStart,
+                    Severity::Error,
+                    &nodes[i],
+                    format!("Flow has more than one start node: \"{}\"", nodes[i].label()),
+                ));
+            }
+        }
+    }
+
+    if !starts.is_empty() {
+        let mut reachable = vec![false; nodes.len()];
+        let mut stack = starts.clone();
+        while let Some(i) = stack.pop() {
+            if std::mem::replace(&mut reachable[i], true) {
+                continue;
+            }
+            stack.extend(outgoing[i].iter().map(|(target, _)| *target));
+        }
+        for (node, _) in nodes.iter().zip(&reachable).filter(|(_, reached)| !**reached) {
+            diagnostics.push(FlowDiagnostic::node(
+                DiagnosticCode::UnreachableNode,
+                Severity::Warning,
+                node,
+                format!("\"{}\" cannot be reached from the start node", node.label()),
+            ));
+        }
+    }
+
+    for (i, node) in nodes.iter().enumerate() {
+        if node.shape() != NodeShape::End && outgoing[i].is_empty() {
+            diagnostics.push(FlowDiagnostic::node(
+                DiagnosticCode::DeadEnd,
+                Severity::Error,
+                node,
+                format!("\"{}\" has no outgoing edge and is not an end node", node.label()),
+            ));
+        }
+    }
+
+    for (i, node) in nodes.iter().enumerate() {
+        let defaults = outgoing[i].iter().filter(|(_, e)| e.condition.is_none()).count();
+        if node.shape() == NodeShape::Decision && !outgoing[i].is_empty() && defaults == 0 {
+            diagnostics.push(FlowDiagnostic::node(
+                DiagnosticCode::MissingDefaultBranch,
+                Severity::Warning,
+                node,
+                format!("Decision \"{}\" has no default branch for values that match no condition", node.label()),
+            ));
+        }
+        // A run follows one edge at a time, so parallel branches can't be executed
+        if defaults > 1 {
+            diagnostics.push(FlowDiagnostic::node(
+                DiagnosticCode::AmbiguousBranch,
+                Severity::Error,
+                node,
+                format!(
+                    "\"{}\" has several outgoing edges without a condition; a run can only follow one",
+                    node.label()
+                ),
+            ));
+        }
+    }
+
+    let field_ids: HashSet<&str> = nodes.iter().flat_map(|n| n.fields()).map(|f| f.id.as_str()).collect();
+    for edge in edges {
+        if let Some(condition) = &edge.condition {
+            if !field_ids.contains(condition.field.as_str()) {
+                diagnostics.push(FlowDiagnostic::edge(
+                    DiagnosticCode::UnknownConditionField,
+                    Severity::Error,
+                    edge,
+                    format!("Edge condition refers to unknown field \"{}\"", condition.field),
+                ));
+            }
+        }
+    }
+
+    for (edge, target) in find_back_edges(nodes.len(), &outgoing) {
+        diagnostics.push(FlowDiagnostic {
+            code: DiagnosticCode::Cycle,
+            severity: Severity::Warning,
+            message: format!("Edge back to \"{}\" creates a cycle", nodes[target].label()),
+            node_id: Some(nodes[target].id.clone()),
+            edge_id: Some(edge.id.clone()),
+        });
+    }
+
+    for node in nodes {
+        let is_step = matches!(node.shape(), NodeShape::Read | NodeShape::Form);
+        let has_content = !node.content().map(str::trim).unwrap_or_default().is_empty();
+        if is_step && !has_content && node.fields().is_empty() {
+            diagnostics.push(FlowDiagnostic::node(
+                DiagnosticCode::EmptyContent,
+                Severity::Warning,
+                node,
+                format!("\"{}\" has no content", node.label()),
+            ));
+        }
+
+        for error in form::check_definitions(node.fields()) {
+            diagnostics.push(FlowDiagnostic::node(
+                DiagnosticCode::InvalidField,
+                Severity::Error,
+                node,
+                format!("\"{}\": {}", node.label(), error.message),
+            ));
+        }
+    }
+
+    diagnostics
+}
+
+/// A node or edge present in both versions of a flow, with the aspects that differ
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ItemChange<T> {
+    pub id: String,
+    pub before: T,
+    pub after: T,
+    pub changed: Vec<&'static str>,
+}
+
+/// Structural difference between two versions of a flow
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct FlowDiff {
+    pub nodes_added: Vec<FlowNode>,
+    pub nodes_removed: Vec<FlowNode>,
+    pub nodes_changed: Vec<ItemChange<FlowNode>>,
+    pub edges_added: Vec<FlowEdge>,
+    pub edges_removed: Vec<FlowEdge>,
+    pub edges_changed: Vec<ItemChange<FlowEdge>>,
+}
+
+/// Compare two versions of a flow by node and edge id.
+/// Editor-only state kept in `extra` (selection, measured size, ...) is not a change.
+pub fn diff(before: (&[FlowNode], &[FlowEdge]), after: (&[FlowNode], &[FlowEdge])) -> FlowDiff {
+    let (nodes_added, nodes_removed, nodes_changed) = diff_items(before.0, after.0, |n| &n.id, node_changes);
+    let (edges_added, edges_removed, edges_changed) = diff_items(before.1, after.1, |e| &e.id, edge_changes);
+    FlowDiff {
+        nodes_added,
+        nodes_removed,
+        nodes_changed,
+        edges_added,
+        edges_removed,
+        edges_changed,
+    }
+}
+
+type ItemDiff<T> = (Vec<T>, Vec<T>, Vec<ItemChange<T>>);
+
+fn diff_items<T: Clone>(
+    before: &[T],
+    after: &[T],
+    id: fn(&T) -> &String,
+    changes: fn(&T, &T) -> Vec<&'static str>,
+) -> ItemDiff<T> {
+    let old: HashMap<&str, &T> = before.iter().map(|item| (id(item).as_str(), item)).collect();
+    let new: HashSet<&str> = after.iter().map(|item| id(item).as_str()).collect();
+
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    for item in after {
+        match old.get(id(item).as_str()) {
+            None => added.push(item.clone()),
+            Some(previous) => {
+                let aspects = changes(previous, item);
+                if !aspects.is_empty() {
+                    changed.push(ItemChange {
+                        id: id(item).clone(),
+                        before: (*previous).clone(),
+                        after: item.clone(),
+                        changed: aspects,
+                    });
+                }
+            }
+        }
+    }
+    let removed = before.iter().filter(|item| !new.contains(id(item).as_str())).cloned().collect();
+
+    (added, removed, changed)
+}
+
+fn node_changes(a: &FlowNode, b: &FlowNode) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    if a.data.label != b.data.label {
+        changed.push("label");
+    }
+    if a.data.shape != b.data.shape {
+        changed.push("shape");
+    }
+    if a.data.config != b.data.config {
+        changed.push("config");
+    }
+    if a.position != b.position {
+        changed.push("position");
+    }
+    changed
+}
+
+fn edge_changes(a: &FlowEdge, b: &FlowEdge) -> Vec<&'static str> {
+    let mut changed = Vec::new();
+    if a.source != b.source || a.target != b.target {
+        changed.push("endpoints");
+    }
+    if a.label != b.label {
+        changed.push("label");
+    }
+    if a.condition != b.condition {
+        changed.push("condition");
+    }
+    changed
+}
+
+/// Edges that close a cycle during a depth-first walk, with the node they point back to
+fn find_back_edges<'a>(len: usize, outgoing: &[Vec<(usize, &'a FlowEdge)>]) -> Vec<(&'a FlowEdge, usize)> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        New,
+        Active,
+        Done,
+    }
+
+    let mut marks = vec![Mark::New; len];
+    let mut back_edges = Vec::new();
+
+    for root in 0..len {
+        if marks[root] != Mark::New {
+            continue;
+        }
+        // (node, index of the next outgoing edge to visit)
+        let mut stack = vec![(root, 0)];
+        marks[root] = Mark::Active;
+        while let Some((node, next)) = stack.pop() {
+            match outgoing[node].get(next) {
+                Some(&(target, edge)) => {
+                    stack.push((node, next + 1));
+                    match marks[target] {
+                        Mark::New => {
+                            marks[target] = Mark::Active;
+                            stack.push((target, 0));
+                        }
+                        Mark::Active => back_edges.push((edge, target)),
+                        Mark::Done => {}
+                    }
+                }
+                None => marks[node] = Mark::Done,
+            }
+        }
+    }
+
+    back_edges
+}
+
+/// Builders for the flows used in tests here and in the modules that run or store them
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+    use serde_json::json;
+
+    /// A node labelled with its id
+    pub fn node(id: &str, shape: &str) -> FlowNode {
+        serde_json::from_value(json!({"id": id, "data": {"label": id, "shape": shape}})).unwrap()
+    }
+
+    /// An edge with the id `source-target`
+    pub fn edge(source: &str, target: &str) -> FlowEdge {
+        serde_json::from_value(json!({"id": format!("{}-{}", source, target), "source": source, "target": target})).unwrap()
+    }
+
+    pub fn with_content(mut node: FlowNode, content: &str) -> FlowNode {
+        node.data.config.get_or_insert_with(NodeConfig::default).content = Some(content.to_string());
+        node
+    }
+
+    pub fn with_fields(mut node: FlowNode, fields: Value) -> FlowNode {
+        node.data.config.get_or_insert_with(NodeConfig::default).fields = serde_json::from_value(fields).unwrap();
+        node
+    }
+
+    pub fn with_condition(mut edge: FlowEdge, condition: Value) -> FlowEdge {
+        edge.condition = Some(serde_json::from_value(condition).unwrap());
+        edge
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixtures::*;
+    use super::*;
+    use serde_json::json;
+
+    fn conditional(source: &str, target: &str, field: &str) -> FlowEdge {
+        with_condition(edge(source, target), json!({"field": field, "op": "is_not_empty"}))
+    }
+
+    /// start -> read -> end
+    fn linear() -> (Vec<FlowNode>, Vec<FlowEdge>) {
+        (
+            vec![node("start", "start"), with_content(node("read", "read"), "Do it"), node("end", "end")],
+            vec![edge("start", "read"), edge("read", "end")],
+        )
+    }
+
+    /// Diagnostics as (code, severity, node id, edge id)
+    fn codes(nodes: &[FlowNode], edges: &[FlowEdge]) -> Vec<(DiagnosticCode, Severity, Option<String>, Option<String>)> {
+        validate(nodes, edges)
+            .into_iter()
+            .map(|d| (d.code, d.severity, d.node_id, d.edge_id))
+            .collect()
+    }
+
+    fn on_node(code: DiagnosticCode, severity: Severity, id: &str) -> (DiagnosticCode, Severity, Option<String>, Option<String>) {
+        (code, severity, Some(id.to_string()), None)
+    }
+
+    #[test]
+    fn keeps_unknown_fields() {
+        let raw = json!([{
+            "id": "n1",
+            "type": "editable",
+            "position": {"x": 1.5, "y": 2.25},
+            "data": {"label": "Start", "shape": "start", "config": {"content": "x", "other": [1]}, "color": 1},
+            "measured": {"width": 80, "height": 80},
+            "selected": true
+        }]);
+        let nodes: Vec<FlowNode> = serde_json::from_value(raw.clone()).unwrap();
+        assert_eq!(nodes[0].shape(), NodeShape::Start);
+        assert_eq!(serde_json::to_value(&nodes).unwrap(), raw);
+    }
+
+    #[test]
+    fn valid_flow_has_no_diagnostics() {
+        let (nodes, edges) = linear();
+        assert!(validate(&nodes, &edges).is_empty());
+    }
+
+    #[test]
+    fn missing_and_duplicate_start() {
+        assert_eq!(
+            codes(&[], &[]),
+            vec![(DiagnosticCode::MissingStart, Severity::Error, None, None)]
+        );
+
+        let (mut nodes, mut edges) = linear();
+        nodes.push(node("again", "start"));
+        edges.push(edge("again", "read"));
+        assert_eq!(codes(&nodes, &edges), vec![on_node(DiagnosticCode::DuplicateStart, Severity::Error, "again")]);
+    }
+
+    #[test]
+    fn unreachable_node_and_dead_end() {
+        let (mut nodes, edges) = linear();
+        nodes.push(with_content(node("orphan", "read"), "Lost"));
+        assert_eq!(
+            codes(&nodes, &edges),
+            vec![
+                on_node(DiagnosticCode::UnreachableNode, Severity::Warning, "orphan"),
+                on_node(DiagnosticCode::DeadEnd, Severity::Error, "orphan"),
+            ]
+        );
+    }
+
+    #[test]
+    fn dangling_edge() {
+        let (nodes, mut edges) = linear();
+        edges.push(edge("read", "ghost"));
+        assert_eq!(
+            codes(&nodes, &edges),
+            vec![(DiagnosticCode::DanglingEdge, Severity::Error, None, Some("read-ghost".to_string()))]
+        );
+    }
+
+    #[test]
+    fn cycle() {
+        let (mut nodes, mut edges) = linear();
+        nodes.push(with_content(node("check", "form"), "Check it"));
+        let fields = json!([{"id": "ok", "type": "checkbox"}]);
+        nodes[3] = with_fields(nodes[3].clone(), fields);
+        edges[1] = edge("read", "check");
+        edges.push(conditional("check", "end", "ok"));
+        edges.push(edge("check", "read"));
+        assert_eq!(
+            codes(&nodes, &edges),
+            vec![(
+                DiagnosticCode::Cycle,
+                Severity::Warning,
+                Some("read".to_string()),
+                Some("check-read".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn empty_content_and_invalid_field() {
+        let (mut nodes, edges) = linear();
+        nodes[1] = with_content(node("read", "read"), "  ");
+        assert_eq!(codes(&nodes, &edges), vec![on_node(DiagnosticCode::EmptyContent, Severity::Warning, "read")]);
+
+        nodes[1] = with_fields(
+            node("read", "form"),
+            json!([{"id": "a", "type": "text"}, {"id": "a", "type": "select"}]),
+        );
+        let diagnostics = validate(&nodes, &edges);
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics
+            .iter()
+            .all(|d| d.code == DiagnosticCode::InvalidField && d.severity == Severity::Error));
+        assert!(has_errors(&diagnostics));
+    }
+
+    #[test]
+    fn branches() {
+        let (mut nodes, mut edges) = linear();
+        nodes[1] = with_fields(with_content(node("read", "form"), "Amount"), json!([{"id": "amount", "type": "number"}]));
+        nodes.push(node("decide", "decision"));
+        nodes.push(with_content(node("extra", "read"), "Extra"));
+        edges[1] = edge("read", "decide");
+        edges.push(conditional("decide", "extra", "amount"));
+        edges.push(conditional("decide", "end", "nope"));
+        edges.push(edge("extra", "end"));
+        assert_eq!(
+            codes(&nodes, &edges),
+            vec![
+                on_node(DiagnosticCode::MissingDefaultBranch, Severity::Warning, "decide"),
+                (DiagnosticCode::UnknownConditionField, Severity::Error, None, Some("decide-end".to_string())),
+            ]
+        );
+
+        // Two default branches out of one node can't both be followed
+        edges.push(edge("extra", "decide"));
+        let ambiguous = codes(&nodes, &edges);
+        assert!(ambiguous.contains(&on_node(DiagnosticCode::AmbiguousBranch, Severity::Error, "extra")));
+    }
+
+    #[test]
+    fn diff_by_id() {
+        let (before_nodes, before_edges) = linear();
+        let mut nodes = before_nodes.clone();
+        let mut edges = before_edges.clone();
+        nodes[1].data.label = "Read carefully".to_string();
+        nodes[1].position.x = 10.0;
+        // Editor state is not a change
+        nodes[2].extra.insert("selected".to_string(), json!(true));
+        nodes.push(with_content(node("review", "read"), "Review"));
+        edges.retain(|e| e.id != "read-end");
+        edges.push(edge("read", "review"));
+        edges[0].label = Some("go".to_string());
+
+        let changes = diff((&before_nodes, &before_edges), (&nodes, &edges));
+        assert_eq!(changes.nodes_added.iter().map(|n| n.id.as_str()).collect::<Vec<_>>(), vec!["review"]);
+        assert!(changes.nodes_removed.is_empty());
+        assert_eq!(changes.nodes_changed.len(), 1);
+        assert_eq!(changes.nodes_changed[0].id, "read");
+        assert_eq!(changes.nodes_changed[0].changed, vec!["label", "position"]);
+        assert_eq!(changes.edges_added.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["read-review"]);
+        assert_eq!(changes.edges_removed.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["read-end"]);
+        assert_eq!(changes.edges_changed[0].changed, vec!["label"]);
+
+        assert_eq!(diff((&nodes, &edges), (&nodes, &edges)), FlowDiff::default());
+    }
+}
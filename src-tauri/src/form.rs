@@ -0,0 +1,245 @@
+//! Typed input fields of `form` nodes and validation of submitted values.
+//!
+//! Field definitions live in the node's `config.fields`; submitted values are a
+//! JSON object keyed by field id.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FormFieldType {
+    Text,
+    Number,
+    Checkbox,
+    Select,
+    Date,
+    FilePath,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FormField {
+    pub id: String,
+    #[serde(default)]
+    pub label: String,
+    #[serde(rename = "type")]
+    pub field_type: FormFieldType,
+    #[serde(default)]
+    pub required: bool,
+    /// Allowed values of a `select` field
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl FormField {
+    fn display_name(&self) -> &str {
+        if self.label.is_empty() {
+            &self.id
+        } else {
+            &self.label
+        }
+    }
+}
+
+/// A problem with a single field, either in its definition or in a submitted value
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FieldError {
+    pub field_id: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field_id: &str, message: String) -> Self {
+        Self {
+            field_id: field_id.to_string(),
+            message,
+        }
+    }
+}
+
+/// Problems in the field definitions themselves
+pub fn check_definitions(fields: &[FormField]) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    let mut seen = HashSet::new();
+
+    for field in fields {
+        if field.id.trim().is_empty() {
+            errors.push(FieldError::new(&field.id, "Field has an empty id".to_string()));
+        } else if !seen.insert(field.id.as_str()) {
+            errors.push(FieldError::new(&field.id, format!("Field id \"{}\" is used more than once", field.id)));
+        }
+        if field.field_type == FormFieldType::Select && field.options.is_empty() {
+            errors.push(FieldError::new(
+                &field.id,
+                format!("Select field \"{}\" has no options", field.display_name()),
+            ));
+        }
+    }
+
+    errors
+}
+
+/// Check submitted values against the field definitions.
+/// An empty result means the values can be stored.
+pub fn validate_values(fields: &[FormField], values: &Map<String, Value>) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    for key in values.keys() {
+        if !fields.iter().any(|f| &f.id == key) {
+            errors.push(FieldError::new(key, format!("Unknown field \"{}\"", key)));
+        }
+    }
+
+    for field in fields {
+        let name = field.display_name();
+        let value = values.get(&field.id).filter(|v| !is_blank(v));
+
+        let Some(value) = value else {
+            if field.required {
+                errors.push(FieldError::new(&field.id, format!("\"{}\" is required", name)));
+            }
+            continue;
+        };
+
+        let problem = match field.field_type {
+            FormFieldType::Text | FormFieldType::FilePath => {
+                (!value.is_string()).then(|| format!("\"{}\" must be text", name))
+            }
+            FormFieldType::Number => (!value.is_number()).then(|| format!("\"{}\" must be a number", name)),
+            FormFieldType::Checkbox => match value.as_bool() {
+                None => Some(format!("\"{}\" must be checked or unchecked", name)),
+                Some(false) if field.required => Some(format!("\"{}\" must be checked", name)),
+                Some(_) => None,
+            },
+            FormFieldType::Select => match value.as_str() {
+                Some(choice) if field.options.iter().any(|o| o == choice) => None,
+                _ => Some(format!("\"{}\" must be one of: {}", name, field.options.join(", "))),
+            },
+            FormFieldType::Date => match value.as_str() {
+                Some(date) if chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok() => None,
+                _ => Some(format!("\"{}\" must be a date (YYYY-MM-DD)", name)),
+            },
+        };
+
+        if let Some(message) = problem {
+            errors.push(FieldError::new(&field.id, message));
+        }
+    }
+
+    errors
+}
+
+/// Null and whitespace-only strings count as "not filled in"
+fn is_blank(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.trim().is_empty(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn field(id: &str, field_type: &str, required: bool) -> FormField {
+        serde_json::from_value(json!({"id": id, "type": field_type, "required": required})).unwrap()
+    }
+
+    fn select(id: &str, options: &[&str]) -> FormField {
+        serde_json::from_value(json!({"id": id, "type": "select", "options": options})).unwrap()
+    }
+
+    fn values(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    /// Ids of the fields with problems
+    fn invalid(fields: &[FormField], submitted: Value) -> Vec<String> {
+        validate_values(fields, &values(submitted)).into_iter().map(|e| e.field_id).collect()
+    }
+
+    #[test]
+    fn accepts_values_of_the_right_type() {
+        let fields = [
+            field("name", "text", true),
+            field("count", "number", true),
+            field("agreed", "checkbox", true),
+            select("size", &["S", "M"]),
+            field("due", "date", false),
+            field("report", "file_path", false),
+        ];
+        let submitted = json!({
+            "name": "Ada", "count": 3.5, "agreed": true, "size": "M", "due": "2024-02-29", "report": "/tmp/a.pdf"
+        });
+        assert_eq!(invalid(&fields, submitted), Vec::<String>::new());
+    }
+
+    #[test]
+    fn rejects_numbers_and_strings_in_each_others_place() {
+        let fields = [field("count", "number", false), field("name", "text", false)];
+        assert_eq!(invalid(&fields, json!({"count": "3", "name": 3})), ["count", "name"]);
+    }
+
+    #[test]
+    fn requires_a_required_checkbox_to_be_checked() {
+        let fields = [field("agreed", "checkbox", true), field("optional", "checkbox", false)];
+        assert_eq!(invalid(&fields, json!({"agreed": false, "optional": false})), ["agreed"]);
+        assert_eq!(invalid(&fields, json!({"agreed": "yes"})), ["agreed"]);
+    }
+
+    #[test]
+    fn rejects_a_choice_outside_the_options() {
+        let fields = [select("size", &["S", "M"])];
+        let errors = validate_values(&fields, &values(json!({"size": "XL"})));
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("S, M"));
+        // Options are matched exactly
+        assert_eq!(invalid(&fields, json!({"size": "s"})), ["size"]);
+    }
+
+    #[test]
+    fn rejects_malformed_dates() {
+        let fields = [field("due", "date", false)];
+        for date in ["2024-02-30", "29/02/2024", "2024-2-3x", "tomorrow"] {
+            assert_eq!(invalid(&fields, json!({ "due": date })), ["due"], "{}", date);
+        }
+    }
+
+    #[test]
+    fn treats_an_empty_file_path_as_missing() {
+        let fields = [field("report", "file_path", true), field("extra", "file_path", false)];
+        assert_eq!(invalid(&fields, json!({"report": "  ", "extra": ""})), ["report"]);
+        assert_eq!(invalid(&fields, json!({"report": null})), ["report"]);
+    }
+
+    #[test]
+    fn reports_unknown_keys() {
+        let fields = [field("name", "text", false)];
+        let errors = validate_values(&fields, &values(json!({"name": "x", "nmae": "y"})));
+        assert_eq!(errors, [FieldError::new("nmae", "Unknown field \"nmae\"".to_string())]);
+    }
+
+    #[test]
+    fn checks_field_definitions() {
+        let fields = [
+            field("name", "text", false),
+            field("name", "number", false),
+            field(" ", "text", false),
+            select("size", &[]),
+        ];
+        let errors: Vec<(String, bool)> = check_definitions(&fields)
+            .into_iter()
+            .map(|e| (e.field_id, e.message.contains("more than once")))
+            .collect();
+        assert_eq!(
+            errors,
+            [("name".to_string(), true), (" ".to_string(), false), ("size".to_string(), false)]
+        );
+        assert!(check_definitions(&[field("a", "text", true), select("b", &["x"])]).is_empty());
+    }
+}
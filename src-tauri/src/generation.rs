@@ -0,0 +1,600 @@
+//! Streaming SOP generation.
+//!
+//! Generation goes through the Python agent when it runs, and directly to the
+//! provider otherwise. Both paths stream, and each step reaches the webview as
+//! soon as it is complete. These events all carry the generation's id:
+//!
+//! - `sop-generation://progress`: the generation started, received more text,
+//!   or restarted, either on the direct path after the agent failed or because
+//!   the model is asked again after an answer that wasn't a usable SOP
+//! - `sop-generation://step`: a completed step and its position
+//! - `sop-generation://done`: the whole SOP
+//! - `sop-generation://error`: why it failed; the code is `cancelled` after
+//!   `cancel_generation`
+//!
+//! Answers are read with `sop_json`, which repairs what it can. An answer it
+//! can't repair is sent back to the model with the problems found, at most
+//! `MAX_REASKS` times.
+//!
+//! Cancelling drops the request, which also closes the connection to the agent
+//! or provider so nothing keeps generating in the background.
+
+use async_openai::{
+    config::OpenAIConfig,
+    types::{
+        ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
+        ChatCompletionRequestUserMessageArgs, CreateChatCompletionRequestArgs,
+    },
+    Client,
+};
+use futures::StreamExt;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager};
+use tokio::sync::oneshot;
+
+use crate::error::{ErrorCode, ZopError, ZopResult};
+use crate::sidecar::{spawn_agent_sidecar, AgentAiConfig, AgentStreamEvent, SidecarState};
+use crate::sop_json;
+use crate::{GeneratedSop, SopStep};
+
+pub const PROGRESS_EVENT: &str = "sop-generation://progress";
+pub const STEP_EVENT: &str = "sop-generation://step";
+pub const DONE_EVENT: &str = "sop-generation://done";
+pub const ERROR_EVENT: &str = "sop-generation://error";
+
+/// Least time between two progress events for received text
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often the model is asked again after an unusable answer
+const MAX_REASKS: usize = 2;
+
+const SYSTEM_PROMPT: &str = r#"You are an SOP (Standard Operating Procedure) generator. Based on the user's description, generate a structured SOP with clear steps.
+
+Output format must be valid JSON with this structure:
+{
+  "title": "SOP Title",
+  "steps": [
+    {"step_type": "start", "label": "Start", "content": null},
+    {"step_type": "read", "label": "Step Name", "content": "Detailed description"},
+    {"step_type": "form", "label": "Input Step", "content": "What user needs to input"},
+    {"step_type": "end", "label": "End", "content": null}
+  ]
+}
+
+Rules:
+1. Always start with a "start" step and end with an "end" step
+2. Use "read" for information/instruction steps
+3. Use "form" for steps that require user input or action
+4. Keep labels concise (2-5 words)
+5. Content should be clear and actionable
+6. Generate 4-10 steps typically
+7. Output ONLY the JSON, no other text"#;
+
+/// Generations that are running, so they can be cancelled
+#[derive(Default)]
+pub struct Generations {
+    running: Mutex<HashMap<String, oneshot::Sender<()>>>,
+    next_id: AtomicU64,
+}
+
+impl Generations {
+    /// An id for a generation the frontend didn't name
+    pub fn new_id(&self) -> String {
+        format!("generation-{}", self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+
+    /// Stop generation `id`; false if it is not running (anymore)
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.running().remove(id) {
+            Some(cancel) => cancel.send(()).is_ok(),
+            None => false,
+        }
+    }
+
+    fn start(&self, id: &str) -> ZopResult<oneshot::Receiver<()>> {
+        let mut running = self.running();
+        if running.contains_key(id) {
+            return Err(ZopError::new(ErrorCode::Conflict, format!("Generation {} is already running", id)));
+        }
+        let (cancel, cancelled) = oneshot::channel();
+        running.insert(id.to_string(), cancel);
+        Ok(cancelled)
+    }
+
+    fn finish(&self, id: &str) {
+        self.running().remove(id);
+    }
+
+    fn running(&self) -> std::sync::MutexGuard<'_, HashMap<String, oneshot::Sender<()>>> {
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum Stage {
+    Started,
+    Streaming,
+    /// The agent failed or the model is asked again; steps so far are void
+    Restarted,
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct ProgressPayload<'a> {
+    generation_id: &'a str,
+    stage: Stage,
+    title: Option<&'a str>,
+    /// Steps completed so far
+    steps: usize,
+    /// Characters of model output received so far
+    received: usize,
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct StepPayload<'a> {
+    generation_id: &'a str,
+    index: usize,
+    step: &'a SopStep,
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct DonePayload<'a> {
+    generation_id: &'a str,
+    sop: &'a GeneratedSop,
+}
+
+#[derive(Debug, Serialize, Clone)]
+struct ErrorPayload<'a> {
+    generation_id: &'a str,
+    error: &'a ZopError,
+}
+
+/// Sends the events of one generation
+struct Reporter<'a> {
+    app: &'a tauri::AppHandle,
+    generation_id: &'a str,
+    title: Option<String>,
+    steps: usize,
+    received: usize,
+    last_progress: Instant,
+}
+
+impl<'a> Reporter<'a> {
+    fn new(app: &'a tauri::AppHandle, generation_id: &'a str) -> Self {
+        Self {
+            app,
+            generation_id,
+            title: None,
+            steps: 0,
+            received: 0,
+            last_progress: Instant::now(),
+        }
+    }
+
+    fn progress(&mut self, stage: Stage) {
+        self.last_progress = Instant::now();
+        self.emit(
+            PROGRESS_EVENT,
+            ProgressPayload {
+                generation_id: self.generation_id,
+                stage,
+                title: self.title.as_deref(),
+                steps: self.steps,
+                received: self.received,
+            },
+        );
+    }
+
+    fn restart(&mut self) {
+        self.title = None;
+        self.steps = 0;
+        self.received = 0;
+        self.progress(Stage::Restarted);
+    }
+
+    fn received(&mut self, text: &str) {
+        self.received += text.chars().count();
+        if self.last_progress.elapsed() >= PROGRESS_INTERVAL {
+            self.progress(Stage::Streaming);
+        }
+    }
+
+    fn title(&mut self, title: String) {
+        self.title = Some(title);
+        self.progress(Stage::Streaming);
+    }
+
+    fn step(&mut self, step: &SopStep) {
+        let normalized;
+        let step = match sop_json::normalize_step_type(&step.step_type) {
+            Some(step_type) if step_type != step.step_type => {
+                normalized = SopStep {
+                    step_type: step_type.to_string(),
+                    ..step.clone()
+                };
+                &normalized
+            }
+            _ => step,
+        };
+        self.emit(
+            STEP_EVENT,
+            StepPayload {
+                generation_id: self.generation_id,
+                index: self.steps,
+                step,
+            },
+        );
+        self.steps += 1;
+        self.progress(Stage::Streaming);
+    }
+
+    fn done(&self, sop: &GeneratedSop) {
+        self.emit(
+            DONE_EVENT,
+            DonePayload {
+                generation_id: self.generation_id,
+                sop,
+            },
+        );
+    }
+
+    fn error(&self, error: &ZopError) {
+        self.emit(
+            ERROR_EVENT,
+            ErrorPayload {
+                generation_id: self.generation_id,
+                error,
+            },
+        );
+    }
+
+    fn emit(&self, event: &str, payload: impl Serialize + Clone) {
+        if let Err(e) = self.app.emit(event, payload) {
+            eprintln!("Failed to emit {}: {}", event, e);
+        }
+    }
+}
+
+/// Generate an SOP for `prompt`, streaming it as events tagged `generation_id`.
+/// Resolves with the whole SOP, like the `done` event.
+pub async fn generate(
+    app: &tauri::AppHandle,
+    config: AgentAiConfig,
+    prompt: String,
+    generation_id: &str,
+) -> ZopResult<GeneratedSop> {
+    let generations = app.state::<Generations>();
+    let cancelled = generations.start(generation_id)?;
+    let mut reporter = Reporter::new(app, generation_id);
+    reporter.progress(Stage::Started);
+
+    let result = tokio::select! {
+        result = run(app, &config, prompt, &mut reporter) => result,
+        _ = cancelled => Err(ZopError::new(ErrorCode::Cancelled, "The generation was cancelled")),
+    };
+    generations.finish(generation_id);
+
+    match &result {
+        Ok(sop) => reporter.done(sop),
+        Err(e) => reporter.error(e),
+    }
+    result
+}
+
+async fn run(
+    app: &tauri::AppHandle,
+    config: &AgentAiConfig,
+    prompt: String,
+    reporter: &mut Reporter<'_>,
+) -> ZopResult<GeneratedSop> {
+    // Try to use Python agent sidecar first
+    if let Err(e) = spawn_agent_sidecar(app).await {
+        eprintln!("Failed to spawn sidecar, falling back to Rust implementation: {}", e);
+        return generate_direct(config, prompt, reporter).await;
+    }
+
+    let sidecar_state = app.state::<SidecarState>();
+    let streamed = sidecar_state
+        .generate_sop_stream(prompt.clone(), config.clone(), |event| match event {
+            AgentStreamEvent::Title { title } => reporter.title(title),
+            AgentStreamEvent::Step { step } => reporter.step(&SopStep {
+                step_type: step.step_type,
+                label: step.label,
+                content: step.content,
+            }),
+            AgentStreamEvent::Done { .. } | AgentStreamEvent::Error { .. } => {}
+        })
+        .await;
+
+    let validated = streamed.and_then(|result| {
+        let sop = GeneratedSop {
+            title: result.title,
+            steps: result
+                .steps
+                .into_iter()
+                .map(|s| SopStep {
+                    step_type: s.step_type,
+                    label: s.label,
+                    content: s.content,
+                })
+                .collect(),
+        };
+        sop_json::validate(&sop).map_err(|e| ZopError::new(ErrorCode::AiBadResponse, e.to_string()))
+    });
+
+    match validated {
+        Ok(sop) => Ok(sop),
+        Err(e) => {
+            eprintln!("Python agent failed, falling back to Rust: {}", e);
+            reporter.restart();
+            generate_direct(config, prompt, reporter).await
+        }
+    }
+}
+
+/// Stream the SOP straight from the provider, picking steps out of the JSON as
+/// it arrives, and ask again while the answer isn't a usable SOP
+async fn generate_direct(config: &AgentAiConfig, prompt: String, reporter: &mut Reporter<'_>) -> ZopResult<GeneratedSop> {
+    // Create OpenAI client with custom config
+    let openai_config = OpenAIConfig::new()
+        .with_api_key(&config.api_key)
+        .with_api_base(&config.base_url);
+
+    let client = Client::with_config(openai_config);
+
+    let mut messages: Vec<ChatCompletionRequestMessage> = vec![
+        ChatCompletionRequestSystemMessageArgs::default()
+            .content(SYSTEM_PROMPT)
+            .build()?
+            .into(),
+        ChatCompletionRequestUserMessageArgs::default()
+            .content(prompt)
+            .build()?
+            .into(),
+    ];
+
+    let mut reasks = 0;
+    loop {
+        let answer = stream_answer(&client, config, messages.clone(), reporter).await?;
+        let invalid = match sop_json::parse(&answer) {
+            Ok(sop) => return Ok(sop),
+            Err(invalid) => invalid,
+        };
+        if reasks == MAX_REASKS {
+            return Err(ZopError::new(
+                ErrorCode::AiBadResponse,
+                format!("The AI response is not a usable SOP: {}", invalid),
+            )
+            .with_details(serde_json::json!({ "response": answer, "problems": invalid.problems })));
+        }
+        reasks += 1;
+        eprintln!("Unusable AI response, asking again ({}/{}): {}", reasks, MAX_REASKS, invalid);
+
+        messages.push(
+            ChatCompletionRequestAssistantMessageArgs::default()
+                .content(answer)
+                .build()?
+                .into(),
+        );
+        messages.push(
+            ChatCompletionRequestUserMessageArgs::default()
+                .content(invalid.reask_prompt())
+                .build()?
+                .into(),
+        );
+        reporter.restart();
+    }
+}
+
+/// The model's whole answer to `messages`, reporting the title and steps as they arrive
+async fn stream_answer(
+    client: &Client<OpenAIConfig>,
+    config: &AgentAiConfig,
+    messages: Vec<ChatCompletionRequestMessage>,
+    reporter: &mut Reporter<'_>,
+) -> ZopResult<String> {
+    let request = CreateChatCompletionRequestArgs::default()
+        .model(&config.model_name)
+        .messages(messages)
+        .build()?;
+
+    let mut stream = client.chat().create_stream(request).await?;
+    let mut parser = StepParser::default();
+    while let Some(response) = stream.next().await {
+        for choice in response?.choices {
+            let Some(text) = choice.delta.content else { continue };
+            reporter.received(&text);
+            for parsed in parser.push(&text) {
+                match parsed {
+                    Parsed::Title(title) => reporter.title(title),
+                    Parsed::Step(step) => reporter.step(&step),
+                }
+            }
+        }
+    }
+    Ok(parser.text().to_string())
+}
+
+/// A part of the SOP found while its JSON streams in
+#[derive(Debug)]
+enum Parsed {
+    Title(String),
+    Step(SopStep),
+}
+
+/// Picks the title and each complete step out of the SOP's JSON while it is
+/// still arriving. Text around the JSON object, such as a code fence, is
+/// skipped; steps that don't parse are left to `sop_json` to repair or report.
+#[derive(Default)]
+struct StepParser {
+    text: String,
+    /// How much of `text` has been scanned
+    scanned: usize,
+    /// Open objects and arrays
+    stack: Vec<u8>,
+    in_string: bool,
+    escaped: bool,
+    string_start: usize,
+    /// Whether the next string in the top-level object is a key
+    expect_key: bool,
+    key: String,
+    in_steps: bool,
+    step_start: Option<usize>,
+    /// Whether the object has ended; anything after it is ignored
+    closed: bool,
+}
+
+impl StepParser {
+    fn push(&mut self, chunk: &str) -> Vec<Parsed> {
+        self.text.push_str(chunk);
+        let mut parsed = Vec::new();
+
+        for i in self.scanned..self.text.len() {
+            let byte = self.text.as_bytes()[i];
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if byte == b'\\' {
+                    self.escaped = true;
+                } else if byte == b'"' {
+                    self.in_string = false;
+                    self.end_string(i, &mut parsed);
+                }
+                continue;
+            }
+            if self.closed || (self.stack.is_empty() && byte != b'{') {
+                continue;
+            }
+
+            match byte {
+                b'"' => {
+                    self.in_string = true;
+                    self.string_start = i;
+                }
+                b'{' => {
+                    if self.in_steps && self.stack.len() == 2 {
+                        self.step_start = Some(i);
+                    }
+                    self.stack.push(byte);
+                    if self.stack.len() == 1 {
+                        self.expect_key = true;
+                    }
+                }
+                b'[' => {
+                    self.stack.push(byte);
+                    if self.stack.len() == 2 && !self.expect_key && self.key == "steps" {
+                        self.in_steps = true;
+                    }
+                }
+                b'}' | b']' => {
+                    self.stack.pop();
+                    if byte == b'}' && self.in_steps && self.stack.len() == 2 {
+                        if let Some(start) = self.step_start.take() {
+                            if let Ok(step) = serde_json::from_str::<SopStep>(&self.text[start..=i]) {
+                                parsed.push(Parsed::Step(step));
+                            }
+                        }
+                    } else if byte == b']' && self.stack.len() == 1 {
+                        self.in_steps = false;
+                    }
+                    self.closed = self.stack.is_empty();
+                }
+                b',' if self.stack.len() == 1 => self.expect_key = true,
+                b':' if self.stack.len() == 1 => self.expect_key = false,
+                _ => {}
+            }
+        }
+        self.scanned = self.text.len();
+        parsed
+    }
+
+    fn end_string(&mut self, end: usize, parsed: &mut Vec<Parsed>) {
+        if self.stack.len() != 1 {
+            return;
+        }
+        let value: String = serde_json::from_str(&self.text[self.string_start..=end]).unwrap_or_default();
+        if self.expect_key {
+            self.key = value;
+        } else if self.key == "title" {
+            parsed.push(Parsed::Title(value));
+        }
+    }
+
+    /// Everything received so far
+    fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fenced answer whose strings hold escapes, braces and brackets, with
+    /// nested objects both at the top level and inside a step
+    const ANSWER: &str = r#"```json
+{
+  "meta": {"title": "not the title", "steps": [{"step_type": "read", "label": "nested"}]},
+  "title": "Say \"hi\" {politely}",
+  "steps": [
+    {"step_type": "start", "label": "Start", "content": null},
+    {"step_type": "read", "label": "Quote \"}\" and \\", "content": "a [b] {c}", "extra": {"deep": {"x": ["}"]}}},
+    {"step_type": "form", "label": "Ask", "content": "Name?"},
+    {"step_type": "end", "label": "End", "content": null}
+  ],
+  "after": {"steps": []}
+}
+```
+Anything else?"#;
+
+    fn describe(parsed: Vec<Parsed>) -> Vec<String> {
+        parsed
+            .into_iter()
+            .map(|parsed| match parsed {
+                Parsed::Title(title) => format!("title {}", title),
+                Parsed::Step(step) => format!("{} {} {:?}", step.step_type, step.label, step.content),
+            })
+            .collect()
+    }
+
+    fn feed(chunks: &[&str]) -> Vec<String> {
+        let mut parser = StepParser::default();
+        let parsed = chunks.iter().flat_map(|chunk| parser.push(chunk)).collect();
+        describe(parsed)
+    }
+
+    fn expected() -> Vec<String> {
+        vec![
+            "title Say \"hi\" {politely}".to_string(),
+            "start Start None".to_string(),
+            "read Quote \"}\" and \\ Some(\"a [b] {c}\")".to_string(),
+            "form Ask Some(\"Name?\")".to_string(),
+            "end End None".to_string(),
+        ]
+    }
+
+    #[test]
+    fn reads_the_whole_answer_at_once() {
+        assert_eq!(feed(&[ANSWER]), expected());
+    }
+
+    #[test]
+    fn reads_an_answer_split_anywhere() {
+        let boundaries: Vec<usize> = ANSWER.char_indices().map(|(i, _)| i).skip(1).collect();
+        // Every single split point: mid key, mid escape, between a brace and its step
+        for &split in &boundaries {
+            assert_eq!(feed(&[&ANSWER[..split], &ANSWER[split..]]), expected(), "split at {}", split);
+        }
+        // and small chunks, like a token stream
+        let chars: Vec<char> = ANSWER.chars().collect();
+        for size in 1..=4 {
+            let chunks: Vec<String> = chars.chunks(size).map(|chunk| chunk.iter().collect()).collect();
+            let chunks: Vec<&str> = chunks.iter().map(String::as_str).collect();
+            assert_eq!(feed(&chunks), expected(), "chunks of {}", size);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Self-contained, printable HTML for handing an SOP to people without zop.
+//!
+//! Todo SOPs become a numbered checklist with a box and an initials line per
+//! item. Flowcharts become numbered steps in execution order: read steps show
+//! their content, form steps a blank line (or boxes) per field, and steps that
+//! branch list where each branch leads. Start and end nodes are implied by the
+//! first and last step. Both end with a sign-off block. Styles are inline and
+//! sized for A4/Letter paper, so the file prints as-is.
+
+use quick_xml::escape::escape;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::bundle::{BundledFlow, SopBundle};
+use crate::engine::{self, ConditionOp, EdgeCondition};
+use crate::error::ZopResult;
+use crate::flow::{FlowEdge, FlowNode, NodeShape};
+use crate::form::{FormField, FormFieldType};
+
+pub const FILE_EXTENSION: &str = "html";
+
+const STYLE: &str = r#"
+@page { margin: 18mm; }
+* { box-sizing: border-box; }
+body { font: 11pt/1.5 -apple-system, "Segoe UI", Roboto, "Helvetica Neue", Arial, "PingFang SC", "Microsoft YaHei", sans-serif; color: #111; max-width: 800px; margin: 2rem auto; padding: 0 1rem; }
+h1 { font-size: 18pt; margin: 0 0 0.25rem; }
+.meta { color: #666; font-size: 9pt; margin: 0 0 1.5rem; }
+ol { padding-left: 1.75rem; }
+li { break-inside: avoid; page-break-inside: avoid; }
+.checklist li { padding: 0.4rem 0; border-bottom: 1px solid #ddd; }
+.checklist .row { display: flex; align-items: baseline; gap: 0.75rem; }
+.checklist .text { flex: 1; white-space: pre-wrap; }
+.box { display: inline-block; width: 0.9em; text-align: center; }
+.initials { width: 8rem; border-bottom: 1px solid #999; color: #999; font-size: 8pt; }
+.steps > li { margin-bottom: 1.25rem; }
+.steps h2 { font-size: 12pt; margin: 0 0 0.35rem; }
+.content { white-space: pre-wrap; margin: 0 0 0.5rem; }
+.field { display: flex; align-items: baseline; gap: 0.5rem; margin: 0.5rem 0; }
+.field .label { min-width: 10rem; }
+.field .line { flex: 1; border-bottom: 1px solid #999; height: 1.2em; }
+.options { display: flex; flex-wrap: wrap; gap: 1rem; }
+.required { color: #b00; }
+.next { color: #444; font-size: 10pt; margin: 0.25rem 0 0; padding-left: 1rem; }
+.sign-off { margin-top: 2.5rem; display: flex; gap: 1.5rem; break-inside: avoid; page-break-inside: avoid; }
+.sign-off div { flex: 1; border-top: 1px solid #111; padding-top: 0.25rem; font-size: 9pt; color: #444; }
+@media print { body { margin: 0; max-width: none; padding: 0; } }
+"#;
+
+/// An HTML document for the SOP in `bundle`
+pub fn render(bundle: &SopBundle) -> String {
+    let name = bundle.sop.name.trim();
+    let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str(&format!("<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n", escape(name), STYLE));
+    html.push_str(&format!("<h1>{}</h1>\n", escape(name)));
+    html.push_str(&format!(
+        "<p class=\"meta\">Printed from zop on {}</p>\n",
+        chrono::Local::now().format("%Y-%m-%d")
+    ));
+
+    match &bundle.flow {
+        Some(flow) if bundle.sop.item_type == "flowchart" => steps(&mut html, flow),
+        _ => checklist(&mut html, bundle),
+    }
+
+    html.push_str(
+        "<section class=\"sign-off\">\n<div>Completed by</div>\n<div>Signature</div>\n<div>Date</div>\n</section>\n",
+    );
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+pub fn write(bundle: &SopBundle, path: &Path) -> ZopResult<()> {
+    fs::write(path, render(bundle))?;
+    Ok(())
+}
+
+fn checklist(html: &mut String, bundle: &SopBundle) {
+    html.push_str("<ol class=\"checklist\">\n");
+    for todo in &bundle.todos {
+        html.push_str(&format!(
+            "<li><div class=\"row\"><span class=\"box\">{}</span><span class=\"text\">{}</span><span class=\"initials\">Initials</span></div></li>\n",
+            if todo.completed { "&#9745;" } else { "&#9744;" },
+            escape(todo.content.trim())
+        ));
+    }
+    html.push_str("</ol>\n");
+}
+
+fn steps(html: &mut String, flow: &BundledFlow) {
+    let order: Vec<&FlowNode> = engine::execution_order(&flow.nodes, &flow.edges)
+        .into_iter()
+        .filter(|node| !matches!(node.shape(), NodeShape::Start | NodeShape::End))
+        .collect();
+    let numbers: HashMap<&str, usize> = order.iter().enumerate().map(|(i, node)| (node.id.as_str(), i + 1)).collect();
+    let nodes: HashMap<&str, &FlowNode> = flow.nodes.iter().map(|node| (node.id.as_str(), node)).collect();
+
+    html.push_str("<ol class=\"steps\">\n");
+    for (i, node) in order.iter().enumerate() {
+        html.push_str(&format!("<li id=\"step-{}\">\n<h2>{}</h2>\n", i + 1, escape(node.label().trim())));
+        if let Some(content) = node.content().map(str::trim).filter(|c| !c.is_empty()) {
+            html.push_str(&format!("<p class=\"content\">{}</p>\n", escape(content)));
+        }
+        for field in node.fields() {
+            form_field(html, field);
+        }
+
+        // Say where to go unless it is simply the next step, or the end after the last one
+        let outgoing: Vec<&FlowEdge> = flow.edges.iter().filter(|e| e.source == node.id).collect();
+        let destination = |edge: &FlowEdge| match numbers.get(edge.target.as_str()) {
+            Some(number) => format!("go to step {}", number),
+            None => "finish".to_string(),
+        };
+        let implied = |edge: &FlowEdge| match numbers.get(edge.target.as_str()) {
+            Some(&number) => number == i + 2,
+            None => i + 1 == order.len(),
+        };
+        match outgoing.as_slice() {
+            [edge] if !implied(edge) => {
+                html.push_str(&format!("<p class=\"next\">Then {}.</p>\n", destination(edge)));
+            }
+            [_] | [] => {}
+            branches => {
+                html.push_str("<ul class=\"next\">\n");
+                for edge in branches {
+                    html.push_str(&format!(
+                        "<li>{}: {}</li>\n",
+                        escape(branch_text(edge, &nodes)),
+                        destination(edge)
+                    ));
+                }
+                html.push_str("</ul>\n");
+            }
+        }
+        html.push_str("</li>\n");
+    }
+    html.push_str("</ol>\n");
+}
+
+fn form_field(html: &mut String, field: &FormField) {
+    let label = if field.label.trim().is_empty() { &field.id } else { field.label.trim() };
+    let required = if field.required { " <span class=\"required\">*</span>" } else { "" };
+    match field.field_type {
+        FormFieldType::Checkbox => html.push_str(&format!(
+            "<div class=\"field\"><span class=\"box\">&#9744;</span><span>{}{}</span></div>\n",
+            escape(label),
+            required
+        )),
+        FormFieldType::Select if !field.options.is_empty() => {
+            let options: Vec<String> = field
+                .options
+                .iter()
+                .map(|option| format!("<span><span class=\"box\">&#9744;</span> {}</span>", escape(option.as_str())))
+                .collect();
+            html.push_str(&format!(
+                "<div class=\"field\"><span class=\"label\">{}{}</span><span class=\"options\">{}</span></div>\n",
+                escape(label),
+                required,
+                options.join("")
+            ));
+        }
+        _ => html.push_str(&format!(
+            "<div class=\"field\"><span class=\"label\">{}{}</span><span class=\"line\"></span></div>\n",
+            escape(label),
+            required
+        )),
+    }
+}
+
+/// When a branch is taken: its label, else its condition spelled out
+fn branch_text(edge: &FlowEdge, nodes: &HashMap<&str, &FlowNode>) -> String {
+    if let Some(label) = edge.label.as_deref().map(str::trim).filter(|l| !l.is_empty()) {
+        return label.to_string();
+    }
+    match &edge.condition {
+        Some(condition) => condition_text(condition, nodes),
+        None => "Otherwise".to_string(),
+    }
+}
+
+fn condition_text(condition: &EdgeCondition, nodes: &HashMap<&str, &FlowNode>) -> String {
+    // Conditions refer to fields by id; show the label the reader filled in
+    let field = nodes
+        .values()
+        .flat_map(|node| node.fields())
+        .find(|field| field.id == condition.field)
+        .map(|field| field.label.trim())
+        .filter(|label| !label.is_empty())
+        .unwrap_or(&condition.field);
+    let value = match &condition.value {
+        serde_json::Value::String(s) => format!("\"{}\"", s),
+        other => other.to_string(),
+    };
+    match condition.op {
+        ConditionOp::Eq => format!("If {} is {}", field, value),
+        ConditionOp::Ne => format!("If {} is not {}", field, value),
+        ConditionOp::Gt => format!("If {} is more than {}", field, value),
+        ConditionOp::Gte => format!("If {} is at least {}", field, value),
+        ConditionOp::Lt => format!("If {} is less than {}", field, value),
+        ConditionOp::Lte => format!("If {} is at most {}", field, value),
+        ConditionOp::Contains => format!("If {} contains {}", field, value),
+        ConditionOp::IsEmpty => format!("If {} is empty", field),
+        ConditionOp::IsNotEmpty => format!("If {} is filled in", field),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundle::{BundledSop, BundledTodo};
+    use crate::flow::fixtures::{edge, node, with_condition, with_content, with_fields};
+    use serde_json::json;
+
+    fn bundle(item_type: &str) -> SopBundle {
+        SopBundle::new(BundledSop {
+            name: "Close <up>".to_string(),
+            icon: "list".to_string(),
+            item_type: item_type.to_string(),
+            tags: Vec::new(),
+            created_at: String::new(),
+            updated_at: String::new(),
+        })
+    }
+
+    fn labelled(mut node: FlowNode, label: &str) -> FlowNode {
+        node.data.label = label.to_string();
+        node
+    }
+
+    #[test]
+    fn todos_become_a_checklist() {
+        let mut bundle = bundle("todo");
+        bundle.todos = vec![
+            BundledTodo { content: "Lock the <back> door".to_string(), completed: true },
+            BundledTodo { content: "Lights & alarm".to_string(), completed: false },
+        ];
+        let html = render(&bundle);
+
+        assert!(html.contains("<h1>Close &lt;up&gt;</h1>"));
+        let list = &html[html.find("<ol class=\"checklist\">").unwrap()..html.find("</ol>").unwrap()];
+        assert_eq!(list.matches("<li>").count(), 2);
+        let first = list.find("&#9745;</span><span class=\"text\">Lock the &lt;back&gt; door</span>").unwrap();
+        let second = list.find("&#9744;</span><span class=\"text\">Lights &amp; alarm</span>").unwrap();
+        assert!(first < second);
+    }
+
+    #[test]
+    fn flows_become_steps_in_execution_order() {
+        // Stored out of order, so the numbering can only come from walking the edges
+        let nodes = vec![
+            node("end", "end"),
+            labelled(with_content(node("file", "read"), "File it <here>"), "File"),
+            labelled(node("approve", "read"), "Approve"),
+            labelled(node("decide", "decision"), "Big & urgent?"),
+            labelled(
+                with_fields(
+                    node("claim", "form"),
+                    json!([
+                        {"id": "amount", "label": "Amount", "type": "number", "required": true},
+                        {"id": "kind", "label": "Kind", "type": "select", "options": ["Travel & food", "<Other>"]}
+                    ]),
+                ),
+                "Claim",
+            ),
+            node("start", "start"),
+        ];
+        let edges = vec![
+            edge("start", "claim"),
+            edge("claim", "decide"),
+            with_condition(edge("decide", "approve"), json!({"field": "amount", "op": "gte", "value": 100})),
+            edge("decide", "file"),
+            edge("approve", "end"),
+            edge("file", "end"),
+        ];
+        let mut bundle = bundle("flowchart");
+        bundle.flow = Some(BundledFlow { nodes, edges });
+        let html = render(&bundle);
+
+        let headings: Vec<usize> = ["Claim", "Big &amp; urgent?", "Approve", "File"]
+            .iter()
+            .enumerate()
+            .map(|(i, label)| {
+                let heading = format!("<li id=\"step-{}\">\n<h2>{}</h2>", i + 1, label);
+                html.find(&heading).unwrap_or_else(|| panic!("{} missing", heading))
+            })
+            .collect();
+        assert!(headings.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(!html.contains("step-5"));
+
+        assert!(html.contains("<li>If Amount is at least 100: go to step 3</li>"));
+        assert!(html.contains("<li>Otherwise: go to step 4</li>"));
+        // Approving skips filing, so it says so; the last step just ends
+        assert!(html.contains("<p class=\"next\">Then finish.</p>"));
+        assert_eq!(html.matches("class=\"next\"").count(), 2);
+
+        assert!(html.contains("<p class=\"content\">File it &lt;here&gt;</p>"));
+        assert!(html.contains("Amount <span class=\"required\">*</span>"));
+        assert!(html.contains("&#9744;</span> Travel &amp; food</span>"));
+        assert!(html.contains("&#9744;</span> &lt;Other&gt;</span>"));
+        assert!(!html.contains("<Other>") && !html.contains("<here>"));
+    }
+}
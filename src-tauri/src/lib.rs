@@ -1,20 +1,116 @@
 use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
 use tauri::Manager;
-use async_openai::{
-    Client,
-    config::OpenAIConfig,
-    types::{CreateChatCompletionRequestArgs, ChatCompletionRequestUserMessageArgs, ChatCompletionRequestSystemMessageArgs},
-};
 
+mod bpmn;
+mod bundle;
 mod db;
+mod diagram;
+mod engine;
+mod error;
+mod export;
+mod flow;
+mod form;
+mod generation;
+mod html;
+mod markdown;
+mod migrations;
+mod provider;
+mod search;
+mod secrets;
 mod sidecar;
+mod sop_json;
+mod workspace;
 
+use bundle::SopBundle;
 use db::Database;
-use sidecar::{SidecarState, AgentAiConfig, spawn_agent_sidecar};
+use error::{ErrorCode, ZopError, ZopResult};
+use flow::{FlowDiagnostic, FlowDiff, FlowEdge, FlowNode, NodeShape};
+use form::FieldError;
+use generation::Generations;
+use secrets::SecretStore;
+use sidecar::{AgentAiConfig, SidecarState};
+use workspace::{Workspace, WorkspaceList, WorkspaceRegistry, DEFAULT_WORKSPACE_ID};
 
 pub struct AppState {
-    pub db: Mutex<Database>,
+    /// Database of the active workspace, replaced when switching workspaces
+    db: RwLock<Arc<Database>>,
+    workspaces: Mutex<WorkspaceRegistry>,
+    /// API keys, shared by all workspaces
+    secrets: Mutex<SecretStore>,
+}
+
+impl AppState {
+    /// Open the active workspace of the registry in `data_dir`
+    fn load(data_dir: &Path) -> ZopResult<Self> {
+        let mut workspaces = WorkspaceRegistry::load(data_dir)?;
+        let mut secrets = SecretStore::open(data_dir)?;
+        let active = workspaces.active().clone();
+
+        let (id, db) = match open_database(&active.path, &mut secrets) {
+            Ok(db) => (active.id, db),
+            // e.g. a shared folder that isn't mounted: start in the default workspace rather than not at all
+            Err(e) if active.id != DEFAULT_WORKSPACE_ID => {
+                eprintln!(
+                    "Failed to open workspace {}, falling back to the default one: {}",
+                    active.name, e
+                );
+                let default = workspaces
+                    .get(DEFAULT_WORKSPACE_ID)
+                    .expect("default workspace is always registered");
+                (
+                    default.id.clone(),
+                    open_database(&default.path, &mut secrets)?,
+                )
+            }
+            Err(e) => return Err(e),
+        };
+        workspaces.set_active(&id)?;
+
+        Ok(Self {
+            db: RwLock::new(Arc::new(db)),
+            workspaces: Mutex::new(workspaces),
+            secrets: Mutex::new(secrets),
+        })
+    }
+
+    /// Database of the active workspace. Commands hold on to it until they finish,
+    /// so a workspace switch in the meantime doesn't affect them.
+    pub fn db(&self) -> Arc<Database> {
+        self.db.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn workspaces(&self) -> MutexGuard<'_, WorkspaceRegistry> {
+        self.workspaces.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn secrets(&self) -> MutexGuard<'_, SecretStore> {
+        self.secrets.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn open_db(&self, path: &Path) -> ZopResult<Database> {
+        open_database(path, &mut self.secrets())
+    }
+
+    fn set_db(&self, db: Database) {
+        *self.db.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(db);
+    }
+}
+
+/// Open a workspace database, moving API keys saved in plaintext by earlier
+/// versions into the secret store
+fn open_database(path: &Path, secrets: &mut SecretStore) -> ZopResult<Database> {
+    let db = Database::open(path)?;
+    let plaintext = db.plaintext_api_keys()?;
+    for (id, api_key) in &plaintext {
+        let secret = secrets.insert(api_key)?;
+        db.seal_api_key(*id, &secret)?;
+    }
+    if !plaintext.is_empty() {
+        db.erase_freed_data()?;
+    }
+    Ok(db)
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -23,6 +119,8 @@ pub struct SopItem {
     pub name: String,
     pub icon: String,
     pub item_type: String, // "todo" or "flowchart"
+    pub folder_id: Option<i64>,
+    pub tag_ids: Vec<i64>,
     pub created_at: String,
     pub updated_at: String,
     pub deleted_at: Option<String>,
@@ -33,6 +131,43 @@ pub struct CreateSopItem {
     pub name: String,
     pub icon: String,
     pub item_type: String,
+    #[serde(default)]
+    pub folder_id: Option<i64>,
+}
+
+/// Narrows down `get_all_sop_items`; the default lists everything
+#[derive(Debug, Deserialize, Default)]
+pub struct SopItemFilter {
+    /// Only items in this folder
+    #[serde(default)]
+    pub folder_id: Option<i64>,
+    /// With `folder_id`, also items in the folders below it
+    #[serde(default)]
+    pub recursive: bool,
+    /// Only items outside of any folder; ignored when `folder_id` is set
+    #[serde(default)]
+    pub unfiled: bool,
+    /// Only items carrying every one of these tags
+    #[serde(default)]
+    pub tag_ids: Vec<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Folder {
+    pub id: i64,
+    pub name: String,
+    /// `None` for a top-level folder
+    pub parent_id: Option<i64>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Tag {
+    pub id: i64,
+    pub name: String,
+    pub color: Option<String>,
+    pub created_at: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -46,6 +181,30 @@ pub struct TodoItem {
     pub updated_at: String,
 }
 
+/// Todo items of an SOP along with what the undo history allows
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TodoListState {
+    pub items: Vec<TodoItem>,
+    pub can_undo: bool,
+    pub can_redo: bool,
+}
+
+/// An SOP, todo or flow node matching a search
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchHit {
+    pub sop_id: i64,
+    pub sop_name: String,
+    pub item_type: String,
+    /// What matched: "sop" (its name), "todo" or "node"
+    pub kind: String,
+    pub todo_id: Option<i64>,
+    pub node_id: Option<String>,
+    /// Excerpt of the matched text with `<mark>`...`</mark>` around the matches
+    pub snippet: String,
+    /// Higher is a better match
+    pub score: f64,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateTodoItem {
     pub sop_id: i64,
@@ -56,18 +215,123 @@ pub struct CreateTodoItem {
 pub struct FlowData {
     pub id: i64,
     pub sop_id: i64,
-    pub nodes: String,
-    pub edges: String,
+    pub nodes: Vec<FlowNode>,
+    pub edges: Vec<FlowEdge>,
     pub created_at: String,
     pub updated_at: String,
 }
 
+/// A flow as it was just saved, with the diagnostics of its graph
+#[derive(Debug, Serialize, Clone)]
+pub struct SavedFlow {
+    #[serde(flatten)]
+    pub flow: FlowData,
+    pub diagnostics: Vec<FlowDiagnostic>,
+}
+
+/// Snapshot of a flow as it was saved at one point in time
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FlowRevision {
+    pub id: i64,
+    pub sop_id: i64,
+    pub revision: i64,
+    pub nodes: Vec<FlowNode>,
+    pub edges: Vec<FlowEdge>,
+    /// Revision number this snapshot was restored from, if it was a rollback
+    pub restored_from: Option<i64>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FlowRevisionSummary {
+    pub id: i64,
+    pub sop_id: i64,
+    pub revision: i64,
+    pub node_count: i64,
+    pub edge_count: i64,
+    pub restored_from: Option<i64>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FlowRun {
+    pub id: i64,
+    pub sop_id: i64,
+    /// Revision of the flow the run executes
+    pub revision_id: Option<i64>,
+    pub operator: Option<String>,
+    pub status: String, // "active", "completed" or "abandoned"
+    pub current_node_id: Option<String>,
+    /// Unsubmitted form values of the current step, kept so the run can be resumed
+    pub draft_values: Option<serde_json::Map<String, serde_json::Value>>,
+    pub started_at: String,
+    pub updated_at: String,
+    pub finished_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ActiveFlowRun {
+    pub run: FlowRun,
+    pub sop_name: String,
+    pub current_node_label: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FlowRunStep {
+    pub id: i64,
+    pub run_id: i64,
+    pub seq: i64,
+    pub node_id: String,
+    pub node_label: String,
+    pub node_shape: String,
+    pub entered_at: String,
+    pub reverted_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FlowRunInput {
+    pub id: i64,
+    pub run_id: i64,
+    pub step_id: i64,
+    pub node_id: String,
+    pub values: serde_json::Map<String, serde_json::Value>,
+    pub submitted_at: String,
+    pub updated_at: String,
+}
+
+/// Steps a run has gone through and the ones expected to follow
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FlowRunPath {
+    pub visited: Vec<FlowRunStep>,
+    pub upcoming: Vec<FlowNode>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FlowRunDetail {
+    pub run: FlowRun,
+    pub steps: Vec<FlowRunStep>,
+    pub inputs: Vec<FlowRunInput>,
+}
+
+/// A named AI provider profile as the frontend sees it; the API key itself
+/// stays in the secret store
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AiConfig {
     pub id: i64,
+    pub name: String,
     pub base_url: String,
-    pub api_key: String,
+    /// Id of the API key in the secret store
+    #[serde(skip)]
+    pub api_key_secret: Option<String>,
+    /// The API key with most of it hidden, for display
+    pub api_key_masked: Option<String>,
+    /// Why the stored API key can't be read, e.g. a changed passphrase; the
+    /// profile stays usable once a new key is saved
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub api_key_error: Option<ZopError>,
     pub model_name: String,
+    /// Used by `generate_sop` when no profile is given
+    pub is_default: bool,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -75,7 +339,9 @@ pub struct AiConfig {
 #[derive(Debug, Deserialize)]
 pub struct SaveAiConfig {
     pub base_url: String,
-    pub api_key: String,
+    /// A new API key; empty or missing keeps the stored one
+    #[serde(default)]
+    pub api_key: Option<String>,
     pub model_name: String,
 }
 
@@ -85,268 +351,1098 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
-async fn toggle_always_on_top(app: tauri::AppHandle) -> Result<bool, String> {
-    let window = app.get_webview_window("main").ok_or("Window not found")?;
-    let current_state = window.is_always_on_top().map_err(|e| e.to_string())?;
+async fn toggle_always_on_top(app: tauri::AppHandle) -> ZopResult<bool> {
+    let window = app
+        .get_webview_window("main")
+        .ok_or_else(|| ZopError::not_found("Window", "main"))?;
+    let current_state = window.is_always_on_top()?;
     let new_state = !current_state;
-    window
-        .set_always_on_top(new_state)
-        .map_err(|e| e.to_string())?;
+    window.set_always_on_top(new_state)?;
     Ok(new_state)
 }
 
 #[tauri::command]
-fn create_sop_item(state: tauri::State<AppState>, item: CreateSopItem) -> Result<SopItem, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.create_sop_item(&item).map_err(|e| e.to_string())
+fn create_sop_item(state: tauri::State<AppState>, item: CreateSopItem) -> ZopResult<SopItem> {
+    let db = state.db();
+    Ok(db.create_sop_item(&item)?)
 }
 
+/// Create a flowchart SOP and its graph in one step, so a failure leaves no empty SOP behind
 #[tauri::command]
-fn get_all_sop_items(state: tauri::State<AppState>) -> Result<Vec<SopItem>, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.get_all_sop_items().map_err(|e| e.to_string())
+fn create_sop_with_flow(
+    state: tauri::State<AppState>,
+    item: CreateSopItem,
+    nodes: Vec<FlowNode>,
+    edges: Vec<FlowEdge>,
+) -> ZopResult<SopItem> {
+    let db = state.db();
+    Ok(db.create_sop_with_flow(&item, &nodes, &edges)?)
 }
 
+/// Create a todo SOP and its items in one step
 #[tauri::command]
-fn delete_sop_item(state: tauri::State<AppState>, id: i64) -> Result<(), String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.soft_delete_sop_item(id).map_err(|e| e.to_string())
+fn create_sop_with_todos(
+    state: tauri::State<AppState>,
+    item: CreateSopItem,
+    todos: Vec<String>,
+) -> ZopResult<SopItem> {
+    let db = state.db();
+    Ok(db.create_sop_with_todos(&item, &todos)?)
 }
 
 #[tauri::command]
-fn rename_sop_item(state: tauri::State<AppState>, id: i64, name: String) -> Result<SopItem, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.rename_sop_item(id, &name).map_err(|e| e.to_string())
+fn get_all_sop_items(
+    state: tauri::State<AppState>,
+    filter: Option<SopItemFilter>,
+) -> ZopResult<Vec<SopItem>> {
+    let db = state.db();
+    Ok(db.get_all_sop_items(&filter.unwrap_or_default())?)
 }
 
+/// Search SOP names, todo contents and flow node text, best matches first
 #[tauri::command]
-fn get_deleted_sop_items(state: tauri::State<AppState>) -> Result<Vec<SopItem>, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.get_deleted_sop_items().map_err(|e| e.to_string())
+fn search(
+    state: tauri::State<AppState>,
+    query: String,
+    limit: Option<u32>,
+) -> ZopResult<Vec<SearchHit>> {
+    let db = state.db();
+    Ok(db.search(&query, limit.unwrap_or(50))?)
 }
 
 #[tauri::command]
-fn restore_sop_item(state: tauri::State<AppState>, id: i64) -> Result<SopItem, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.restore_sop_item(id).map_err(|e| e.to_string())
+fn delete_sop_item(state: tauri::State<AppState>, id: i64) -> ZopResult<()> {
+    let db = state.db();
+    Ok(db.soft_delete_sop_item(id)?)
 }
 
 #[tauri::command]
-fn permanently_delete_sop_item(state: tauri::State<AppState>, id: i64) -> Result<(), String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.permanently_delete_sop_item(id).map_err(|e| e.to_string())
+fn rename_sop_item(state: tauri::State<AppState>, id: i64, name: String) -> ZopResult<SopItem> {
+    let db = state.db();
+    Ok(db.rename_sop_item(id, &name)?)
 }
 
 #[tauri::command]
-fn create_todo_item(state: tauri::State<AppState>, item: CreateTodoItem) -> Result<TodoItem, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.create_todo_item(&item).map_err(|e| e.to_string())
+fn get_deleted_sop_items(state: tauri::State<AppState>) -> ZopResult<Vec<SopItem>> {
+    let db = state.db();
+    Ok(db.get_deleted_sop_items()?)
 }
 
 #[tauri::command]
-fn get_todo_items(state: tauri::State<AppState>, sop_id: i64) -> Result<Vec<TodoItem>, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.get_todo_items(sop_id).map_err(|e| e.to_string())
+fn restore_sop_item(state: tauri::State<AppState>, id: i64) -> ZopResult<SopItem> {
+    let db = state.db();
+    Ok(db.restore_sop_item(id)?)
 }
 
 #[tauri::command]
-fn toggle_todo_item(state: tauri::State<AppState>, id: i64) -> Result<TodoItem, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.toggle_todo_item(id).map_err(|e| e.to_string())
+fn permanently_delete_sop_item(state: tauri::State<AppState>, id: i64) -> ZopResult<()> {
+    let db = state.db();
+    Ok(db.permanently_delete_sop_item(id)?)
 }
 
+/// Write an SOP to a `.zop.json` bundle and return the path written. Without
+/// `path` the file goes to the Downloads folder, named after the SOP.
 #[tauri::command]
-fn delete_todo_item(state: tauri::State<AppState>, id: i64) -> Result<(), String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.delete_todo_item(id).map_err(|e| e.to_string())
+fn export_sop(
+    state: tauri::State<AppState>,
+    id: i64,
+    path: Option<String>,
+    include_revisions: Option<bool>,
+) -> ZopResult<String> {
+    let db = state.db();
+    let bundle = db.export_sop_bundle(id, include_revisions.unwrap_or(false))?;
+    let path = export::output_path(path.as_deref(), &bundle.sop.name, bundle::FILE_EXTENSION)?;
+    bundle.write(&path)?;
+    Ok(path.display().to_string())
 }
 
+/// Write a todo SOP as a Markdown task list; returns the path written
 #[tauri::command]
-fn update_todo_item(state: tauri::State<AppState>, id: i64, content: String) -> Result<TodoItem, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.update_todo_item(id, &content).map_err(|e| e.to_string())
+fn export_sop_markdown(
+    state: tauri::State<AppState>,
+    id: i64,
+    path: Option<String>,
+) -> ZopResult<String> {
+    let db = state.db();
+    let bundle = db.export_sop_bundle(id, false)?;
+    let path = export::output_path(path.as_deref(), &bundle.sop.name, markdown::FILE_EXTENSION)?;
+    markdown::write(&bundle, &path)?;
+    Ok(path.display().to_string())
 }
 
+/// Write a flowchart SOP as a Mermaid flowchart; returns the path written
 #[tauri::command]
-fn reorder_todo_items(state: tauri::State<AppState>, item_ids: Vec<i64>) -> Result<(), String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.reorder_todo_items(&item_ids).map_err(|e| e.to_string())
+fn export_sop_mermaid(
+    state: tauri::State<AppState>,
+    id: i64,
+    path: Option<String>,
+) -> ZopResult<String> {
+    let db = state.db();
+    let bundle = db.export_sop_bundle(id, false)?;
+    let path = export::output_path(
+        path.as_deref(),
+        &bundle.sop.name,
+        diagram::MERMAID_EXTENSION,
+    )?;
+    diagram::write_mermaid(&bundle, &path)?;
+    Ok(path.display().to_string())
 }
 
+/// Write a flowchart SOP as a Graphviz DOT graph; returns the path written
 #[tauri::command]
-fn get_flow_data(state: tauri::State<AppState>, sop_id: i64) -> Result<Option<FlowData>, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.get_flow_data(sop_id).map_err(|e| e.to_string())
+fn export_sop_dot(
+    state: tauri::State<AppState>,
+    id: i64,
+    path: Option<String>,
+) -> ZopResult<String> {
+    let db = state.db();
+    let bundle = db.export_sop_bundle(id, false)?;
+    let path = export::output_path(path.as_deref(), &bundle.sop.name, diagram::DOT_EXTENSION)?;
+    diagram::write_dot(&bundle, &path)?;
+    Ok(path.display().to_string())
 }
 
+/// Write a flowchart SOP as a BPMN 2.0 process; returns the path written
 #[tauri::command]
-fn save_flow_data(state: tauri::State<AppState>, sop_id: i64, nodes: String, edges: String) -> Result<FlowData, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.save_flow_data(sop_id, &nodes, &edges).map_err(|e| e.to_string())
+fn export_sop_bpmn(
+    state: tauri::State<AppState>,
+    id: i64,
+    path: Option<String>,
+) -> ZopResult<String> {
+    let db = state.db();
+    let bundle = db.export_sop_bundle(id, false)?;
+    let path = export::output_path(path.as_deref(), &bundle.sop.name, bpmn::FILE_EXTENSION)?;
+    bpmn::write(&bundle, &path)?;
+    Ok(path.display().to_string())
 }
 
+/// Write an SOP as a printable, self-contained HTML page; returns the path written
 #[tauri::command]
-fn get_ai_config(state: tauri::State<AppState>) -> Result<Option<AiConfig>, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.get_ai_config().map_err(|e| e.to_string())
+fn export_sop_html(
+    state: tauri::State<AppState>,
+    id: i64,
+    path: Option<String>,
+) -> ZopResult<String> {
+    let db = state.db();
+    let bundle = db.export_sop_bundle(id, false)?;
+    let path = export::output_path(path.as_deref(), &bundle.sop.name, html::FILE_EXTENSION)?;
+    html::write(&bundle, &path)?;
+    Ok(path.display().to_string())
 }
 
+/// Create a new SOP from a bundle file, a Markdown task list, a Mermaid
+/// flowchart or a BPMN process, optionally inside a folder
 #[tauri::command]
-fn save_ai_config(state: tauri::State<AppState>, config: SaveAiConfig) -> Result<AiConfig, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.save_ai_config(&config).map_err(|e| e.to_string())
+fn import_sop(
+    state: tauri::State<AppState>,
+    path: String,
+    folder_id: Option<i64>,
+) -> ZopResult<SopItem> {
+    let db = state.db();
+    if let Some(folder_id) = folder_id {
+        ensure_folder_exists(&db, folder_id)?;
+    }
+    let path = Path::new(&path);
+    let bundle = if markdown::is_markdown(path) {
+        markdown::read(path)?
+    } else if diagram::is_mermaid(path) {
+        diagram::read_mermaid(path)?
+    } else if bpmn::is_bpmn(path) {
+        bpmn::read(path)?
+    } else {
+        SopBundle::read(path)?
+    };
+    Ok(db.import_sop_bundle(&bundle, folder_id)?)
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct SopStep {
-    pub step_type: String,  // "start", "read", "form", "end"
-    pub label: String,
-    pub content: Option<String>,
+/// Move an SOP into a folder, or to the top level when `folder_id` is None
+#[tauri::command]
+fn move_sop_item(
+    state: tauri::State<AppState>,
+    id: i64,
+    folder_id: Option<i64>,
+) -> ZopResult<SopItem> {
+    let db = state.db();
+    if let Some(folder_id) = folder_id {
+        ensure_folder_exists(&db, folder_id)?;
+    }
+    Ok(db.move_sop_item(id, folder_id)?)
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct GeneratedSop {
-    pub title: String,
-    pub steps: Vec<SopStep>,
+#[tauri::command]
+fn set_sop_item_tags(
+    state: tauri::State<AppState>,
+    id: i64,
+    tag_ids: Vec<i64>,
+) -> ZopResult<SopItem> {
+    let db = state.db();
+    Ok(db.set_sop_item_tags(id, &tag_ids)?)
+}
+
+#[tauri::command]
+fn get_folders(state: tauri::State<AppState>) -> ZopResult<Vec<Folder>> {
+    let db = state.db();
+    Ok(db.get_folders()?)
+}
+
+#[tauri::command]
+fn create_folder(
+    state: tauri::State<AppState>,
+    name: String,
+    parent_id: Option<i64>,
+) -> ZopResult<Folder> {
+    let db = state.db();
+    let name = required_name(&name, "Folder")?;
+    if let Some(parent_id) = parent_id {
+        ensure_folder_exists(&db, parent_id)?;
+    }
+    Ok(db.create_folder(name, parent_id)?)
+}
+
+#[tauri::command]
+fn rename_folder(state: tauri::State<AppState>, id: i64, name: String) -> ZopResult<Folder> {
+    let db = state.db();
+    let name = required_name(&name, "Folder")?;
+    ensure_folder_exists(&db, id)?;
+    Ok(db.rename_folder(id, name)?)
+}
+
+/// Move a folder below another one, or to the top level when `parent_id` is None
+#[tauri::command]
+fn move_folder(
+    state: tauri::State<AppState>,
+    id: i64,
+    parent_id: Option<i64>,
+) -> ZopResult<Folder> {
+    let db = state.db();
+    ensure_folder_exists(&db, id)?;
+    if let Some(parent_id) = parent_id {
+        ensure_folder_exists(&db, parent_id)?;
+        if db.is_folder_within(parent_id, id)? {
+            return Err(ZopError::invalid_state(
+                "A folder cannot be moved into itself or one of its subfolders",
+            ));
+        }
+    }
+    Ok(db.move_folder(id, parent_id)?)
+}
+
+/// Delete a folder; the SOPs and folders in it move up one level
+#[tauri::command]
+fn delete_folder(state: tauri::State<AppState>, id: i64) -> ZopResult<()> {
+    let db = state.db();
+    ensure_folder_exists(&db, id)?;
+    Ok(db.delete_folder(id)?)
+}
+
+#[tauri::command]
+fn get_tags(state: tauri::State<AppState>) -> ZopResult<Vec<Tag>> {
+    let db = state.db();
+    Ok(db.get_tags()?)
+}
+
+#[tauri::command]
+fn create_tag(
+    state: tauri::State<AppState>,
+    name: String,
+    color: Option<String>,
+) -> ZopResult<Tag> {
+    let db = state.db();
+    let name = required_name(&name, "Tag")?;
+    Ok(db.create_tag(name, color.as_deref())?)
+}
+
+#[tauri::command]
+fn update_tag(
+    state: tauri::State<AppState>,
+    id: i64,
+    name: String,
+    color: Option<String>,
+) -> ZopResult<Tag> {
+    let db = state.db();
+    let name = required_name(&name, "Tag")?;
+    Ok(db.update_tag(id, name, color.as_deref())?)
+}
+
+#[tauri::command]
+fn delete_tag(state: tauri::State<AppState>, id: i64) -> ZopResult<()> {
+    let db = state.db();
+    Ok(db.delete_tag(id)?)
+}
+
+fn ensure_folder_exists(db: &Database, id: i64) -> ZopResult<()> {
+    match db.get_folder(id)? {
+        Some(_) => Ok(()),
+        None => Err(ZopError::not_found("Folder", id)),
+    }
+}
+
+/// `name` without surrounding whitespace, rejected if nothing is left
+fn required_name<'a>(name: &'a str, what: &str) -> ZopResult<&'a str> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ZopError::validation(format!(
+            "{} name must not be empty",
+            what
+        )));
+    }
+    Ok(name)
+}
+
+#[tauri::command]
+fn create_todo_item(state: tauri::State<AppState>, item: CreateTodoItem) -> ZopResult<TodoItem> {
+    let db = state.db();
+    Ok(db.create_todo_item(&item)?)
+}
+
+#[tauri::command]
+fn get_todo_items(state: tauri::State<AppState>, sop_id: i64) -> ZopResult<Vec<TodoItem>> {
+    let db = state.db();
+    Ok(db.get_todo_items(sop_id)?)
+}
+
+#[tauri::command]
+fn toggle_todo_item(state: tauri::State<AppState>, id: i64) -> ZopResult<TodoItem> {
+    let db = state.db();
+    Ok(db.toggle_todo_item(id)?)
+}
+
+#[tauri::command]
+fn delete_todo_item(state: tauri::State<AppState>, id: i64) -> ZopResult<()> {
+    let db = state.db();
+    Ok(db.delete_todo_item(id)?)
+}
+
+#[tauri::command]
+fn update_todo_item(
+    state: tauri::State<AppState>,
+    id: i64,
+    content: String,
+) -> ZopResult<TodoItem> {
+    let db = state.db();
+    Ok(db.update_todo_item(id, &content)?)
+}
+
+#[tauri::command]
+fn reorder_todo_items(state: tauri::State<AppState>, item_ids: Vec<i64>) -> ZopResult<()> {
+    let db = state.db();
+    db.reorder_todo_items(&item_ids)
+}
+
+#[tauri::command]
+fn get_todo_list_state(state: tauri::State<AppState>, sop_id: i64) -> ZopResult<TodoListState> {
+    let db = state.db();
+    Ok(db.get_todo_list_state(sop_id)?)
+}
+
+#[tauri::command]
+fn undo_todo(state: tauri::State<AppState>, sop_id: i64) -> ZopResult<TodoListState> {
+    let db = state.db();
+    Ok(db.undo_todo_operation(sop_id)?)
+}
+
+#[tauri::command]
+fn redo_todo(state: tauri::State<AppState>, sop_id: i64) -> ZopResult<TodoListState> {
+    let db = state.db();
+    Ok(db.redo_todo_operation(sop_id)?)
+}
+
+#[tauri::command]
+fn get_flow_data(state: tauri::State<AppState>, sop_id: i64) -> ZopResult<Option<FlowData>> {
+    let db = state.db();
+    Ok(db.get_flow_data(sop_id)?)
+}
+
+/// Save a flow's graph as it is, even mid-edit; its diagnostics come back with it
+/// and only keep it from being run
+#[tauri::command]
+fn save_flow_data(
+    state: tauri::State<AppState>,
+    sop_id: i64,
+    nodes: Vec<FlowNode>,
+    edges: Vec<FlowEdge>,
+) -> ZopResult<SavedFlow> {
+    let db = state.db();
+    let flow = db.save_flow_data(sop_id, &nodes, &edges)?;
+    let diagnostics = flow::validate(&flow.nodes, &flow.edges);
+    Ok(SavedFlow { flow, diagnostics })
+}
+
+/// Fail with the error diagnostics of a graph, after `what`
+fn check_flow(nodes: &[FlowNode], edges: &[FlowEdge], what: &str) -> ZopResult<()> {
+    let diagnostics = flow::validate(nodes, edges);
+    if !flow::has_errors(&diagnostics) {
+        return Ok(());
+    }
+    let messages: Vec<&str> = diagnostics
+        .iter()
+        .filter(|d| d.severity == flow::Severity::Error)
+        .map(|d| d.message.as_str())
+        .collect();
+    Err(
+        ZopError::validation(format!("{}: {}", what, messages.join("; ")))
+            .with_details(&diagnostics),
+    )
+}
+
+#[tauri::command]
+fn validate_flow(state: tauri::State<AppState>, sop_id: i64) -> ZopResult<Vec<FlowDiagnostic>> {
+    let db = state.db();
+    let data = db.get_flow_data(sop_id)?;
+    Ok(match data {
+        Some(data) => flow::validate(&data.nodes, &data.edges),
+        None => flow::validate(&[], &[]),
+    })
+}
+
+#[tauri::command]
+fn list_flow_revisions(
+    state: tauri::State<AppState>,
+    sop_id: i64,
+) -> ZopResult<Vec<FlowRevisionSummary>> {
+    let db = state.db();
+    Ok(db.list_flow_revisions(sop_id)?)
+}
+
+#[tauri::command]
+fn get_flow_revision(state: tauri::State<AppState>, revision_id: i64) -> ZopResult<FlowRevision> {
+    let db = state.db();
+    load_revision(&db, revision_id)
+}
+
+/// Structural diff from one revision to another, or to the current flow when `to_revision_id` is omitted
+#[tauri::command]
+fn diff_flow_revisions(
+    state: tauri::State<AppState>,
+    from_revision_id: i64,
+    to_revision_id: Option<i64>,
+) -> ZopResult<FlowDiff> {
+    let db = state.db();
+    let from = load_revision(&db, from_revision_id)?;
+    let (nodes, edges) = match to_revision_id {
+        Some(id) => {
+            let to = load_revision(&db, id)?;
+            (to.nodes, to.edges)
+        }
+        None => {
+            let current = load_flow(&db, from.sop_id)?;
+            (current.nodes, current.edges)
+        }
+    };
+    Ok(flow::diff((&from.nodes, &from.edges), (&nodes, &edges)))
+}
+
+#[tauri::command]
+fn restore_flow_revision(state: tauri::State<AppState>, revision_id: i64) -> ZopResult<FlowData> {
+    let db = state.db();
+    load_revision(&db, revision_id)?;
+    Ok(db.restore_flow_revision(revision_id)?)
+}
+
+fn load_revision(db: &Database, revision_id: i64) -> ZopResult<FlowRevision> {
+    db.get_flow_revision(revision_id)?
+        .ok_or_else(|| ZopError::not_found("Flow revision", revision_id))
+}
+
+/// Name of the OS user, recorded as the operator when the frontend doesn't send one
+fn current_operator() -> Option<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+}
+
+fn load_flow(db: &Database, sop_id: i64) -> ZopResult<FlowData> {
+    db.get_flow_data(sop_id)?
+        .ok_or_else(|| ZopError::not_found("Flow", sop_id))
+}
+
+/// The graph a run executes: the revision it was started on, so later edits
+/// to the flow don't change a run in progress
+fn load_run_graph(db: &Database, run: &FlowRun) -> ZopResult<(Vec<FlowNode>, Vec<FlowEdge>)> {
+    match run.revision_id {
+        Some(id) => {
+            let revision = load_revision(db, id)?;
+            Ok((revision.nodes, revision.edges))
+        }
+        None => {
+            let flow = load_flow(db, run.sop_id)?;
+            Ok((flow.nodes, flow.edges))
+        }
+    }
 }
 
+fn load_active_run(db: &Database, run_id: i64) -> ZopResult<FlowRun> {
+    let run = db
+        .get_flow_run(run_id)?
+        .ok_or_else(|| ZopError::not_found("Flow run", run_id))?;
+    if run.status != "active" {
+        return Err(ZopError::invalid_state(format!(
+            "Flow run {} is already {}",
+            run_id, run.status
+        )));
+    }
+    Ok(run)
+}
+
+#[tauri::command]
+fn start_flow_run(
+    state: tauri::State<AppState>,
+    sop_id: i64,
+    operator: Option<String>,
+) -> ZopResult<FlowRun> {
+    let db = state.db();
+    let flow = load_flow(&db, sop_id)?;
+    check_flow(&flow.nodes, &flow.edges, "Flow cannot be run")?;
+
+    let start = flow
+        .nodes
+        .iter()
+        .find(|n| n.shape() == NodeShape::Start)
+        .ok_or_else(|| ZopError::validation("Flow has no start node"))?;
+    let operator = operator.or_else(current_operator);
+    Ok(db.create_flow_run(sop_id, operator.as_deref(), start)?)
+}
+
+/// Move the run to the next step chosen by the engine from the values entered so far.
+/// A form step can only be left once its stored values pass validation.
+#[tauri::command]
+fn advance_flow_run(state: tauri::State<AppState>, run_id: i64) -> ZopResult<FlowRun> {
+    let db = state.db();
+    let run = load_active_run(&db, run_id)?;
+    let (nodes, edges) = load_run_graph(&db, &run)?;
+    let current = run
+        .current_node_id
+        .as_deref()
+        .ok_or_else(|| ZopError::invalid_state("Flow run has no current node"))?;
+
+    if let Some(node) = nodes
+        .iter()
+        .find(|n| n.id == current && n.shape() == NodeShape::Form)
+    {
+        let step = db.get_current_flow_run_step(run_id)?.ok_or_else(|| {
+            ZopError::invalid_state(format!("Flow run {} has no current step", run_id))
+        })?;
+        let values = db.get_flow_run_step_values(step.id)?;
+        check_form_values(form::validate_values(node.fields(), &values))?;
+    }
+
+    let inputs = db.get_flow_run_values(run_id)?;
+
+    let entered = engine::advance(&nodes, &edges, current, &inputs)?;
+    if entered.is_empty() {
+        return Err(ZopError::invalid_state(
+            "Flow run is already at an end node",
+        ));
+    }
+
+    Ok(db.push_flow_run_steps(run_id, &entered)?)
+}
+
+/// Return to the previous step, skipping back over decision nodes passed through
 #[tauri::command]
-async fn generate_sop(app: tauri::AppHandle, state: tauri::State<'_, AppState>, prompt: String) -> Result<GeneratedSop, String> {
-    // Get AI config
-    let config = {
-        let db = state.db.lock().map_err(|e| e.to_string())?;
-        db.get_ai_config().map_err(|e| e.to_string())?
+fn go_back_flow_run(state: tauri::State<AppState>, run_id: i64) -> ZopResult<FlowRun> {
+    let db = state.db();
+    load_active_run(&db, run_id)?;
+    Ok(db.go_back_flow_run(run_id)?)
+}
+
+#[tauri::command]
+fn get_flow_run_path(state: tauri::State<AppState>, run_id: i64) -> ZopResult<FlowRunPath> {
+    let db = state.db();
+    let run = db
+        .get_flow_run(run_id)?
+        .ok_or_else(|| ZopError::not_found("Flow run", run_id))?;
+    let (nodes, edges) = load_run_graph(&db, &run)?;
+    let inputs = db.get_flow_run_values(run_id)?;
+
+    let visited = db
+        .get_flow_run_steps(run_id)?
+        .into_iter()
+        .filter(|s| s.reverted_at.is_none() && s.node_shape != NodeShape::Decision.as_str())
+        .collect();
+    let upcoming = match (&run.status[..], run.current_node_id.as_deref()) {
+        ("active", Some(current)) => engine::projected_path(&nodes, &edges, current, &inputs)
+            .into_iter()
+            .cloned()
+            .collect(),
+        _ => Vec::new(),
     };
+    Ok(FlowRunPath { visited, upcoming })
+}
+
+#[tauri::command]
+fn complete_flow_run(state: tauri::State<AppState>, run_id: i64) -> ZopResult<FlowRun> {
+    let db = state.db();
+    let run = load_active_run(&db, run_id)?;
+    let (nodes, _) = load_run_graph(&db, &run)?;
+    let at_end = nodes
+        .iter()
+        .any(|n| Some(&n.id) == run.current_node_id.as_ref() && n.shape() == NodeShape::End);
+    if !at_end {
+        return Err(ZopError::invalid_state(
+            "A flow run can only be completed at an end node",
+        ));
+    }
+    Ok(db.finish_flow_run(run_id, "completed")?)
+}
+
+#[tauri::command]
+fn abandon_flow_run(state: tauri::State<AppState>, run_id: i64) -> ZopResult<FlowRun> {
+    let db = state.db();
+    load_active_run(&db, run_id)?;
+    Ok(db.finish_flow_run(run_id, "abandoned")?)
+}
+
+#[tauri::command]
+fn list_flow_runs(state: tauri::State<AppState>, sop_id: i64) -> ZopResult<Vec<FlowRun>> {
+    let db = state.db();
+    Ok(db.list_flow_runs(sop_id)?)
+}
+
+#[tauri::command]
+fn get_flow_run(state: tauri::State<AppState>, run_id: i64) -> ZopResult<FlowRunDetail> {
+    let db = state.db();
+    let run = db
+        .get_flow_run(run_id)?
+        .ok_or_else(|| ZopError::not_found("Flow run", run_id))?;
+    let steps = db.get_flow_run_steps(run_id)?;
+    let inputs = db.get_flow_run_inputs(run_id)?;
+    Ok(FlowRunDetail { run, steps, inputs })
+}
+
+/// The most recent unfinished run of every SOP, for "resume where you left off"
+#[tauri::command]
+fn get_active_runs(state: tauri::State<AppState>) -> ZopResult<Vec<ActiveFlowRun>> {
+    let db = state.db();
+    Ok(db.get_active_flow_runs()?)
+}
+
+/// Keep partially entered form values so an interrupted run can be resumed
+#[tauri::command]
+fn save_form_draft(
+    state: tauri::State<AppState>,
+    run_id: i64,
+    values: serde_json::Map<String, serde_json::Value>,
+) -> ZopResult<FlowRun> {
+    let db = state.db();
+    load_active_run(&db, run_id)?;
+    Ok(db.save_flow_run_draft(run_id, &values)?)
+}
 
-    let config = config.ok_or("AI configuration not found. Please configure AI settings first.")?;
+#[tauri::command]
+fn validate_form_input(
+    state: tauri::State<AppState>,
+    sop_id: i64,
+    node_id: String,
+    values: serde_json::Map<String, serde_json::Value>,
+) -> ZopResult<Vec<FieldError>> {
+    let db = state.db();
+    let flow = load_flow(&db, sop_id)?;
+    let node = flow
+        .nodes
+        .iter()
+        .find(|n| n.id == node_id)
+        .ok_or_else(|| ZopError::not_found("Node", &node_id))?;
+    Ok(form::validate_values(node.fields(), &values))
+}
+
+/// Store the values entered on the run's current form step
+#[tauri::command]
+fn submit_form_input(
+    state: tauri::State<AppState>,
+    run_id: i64,
+    values: serde_json::Map<String, serde_json::Value>,
+) -> ZopResult<FlowRunInput> {
+    let db = state.db();
+    let run = load_active_run(&db, run_id)?;
+    let step = db.get_current_flow_run_step(run_id)?.ok_or_else(|| {
+        ZopError::invalid_state(format!("Flow run {} has no current step", run_id))
+    })?;
+    let (nodes, _) = load_run_graph(&db, &run)?;
+    let node = nodes
+        .iter()
+        .find(|n| n.id == step.node_id && n.shape() == NodeShape::Form)
+        .ok_or_else(|| ZopError::invalid_state("The current step is not a form step"))?;
 
-    // Try to use Python agent sidecar first
-    let sidecar_state = app.state::<SidecarState>();
+    check_form_values(form::validate_values(node.fields(), &values))?;
+    Ok(db.save_flow_run_input(run_id, &step, &values)?)
+}
 
-    // Spawn sidecar if not running
-    if let Err(e) = spawn_agent_sidecar(&app).await {
-        eprintln!("Failed to spawn sidecar, falling back to Rust implementation: {}", e);
-        // Fall back to Rust implementation
-        return generate_sop_rust(&config, prompt).await;
+/// Reject form values with problems, listing them per field in the details
+fn check_form_values(errors: Vec<FieldError>) -> ZopResult<()> {
+    if errors.is_empty() {
+        return Ok(());
     }
+    let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+    Err(ZopError::validation(messages.join("; ")).with_details(&errors))
+}
+
+#[tauri::command]
+fn get_ai_config(state: tauri::State<AppState>) -> ZopResult<Option<AiConfig>> {
+    let db = state.db();
+    Ok(db
+        .get_ai_config()?
+        .map(|config| mask_api_key(&state, config)))
+}
 
-    // Use Python agent
-    let agent_config = AgentAiConfig {
-        base_url: config.base_url.clone(),
-        api_key: config.api_key.clone(),
-        model_name: config.model_name.clone(),
+/// Save the default AI profile
+#[tauri::command]
+fn save_ai_config(state: tauri::State<AppState>, config: SaveAiConfig) -> ZopResult<AiConfig> {
+    let db = state.db();
+    let existing = db.get_ai_config()?.and_then(|c| c.api_key_secret);
+    let secret = store_api_key(&state, config.api_key.as_deref(), existing)?;
+    let saved = db.save_ai_config(&config, &secret)?;
+    Ok(mask_api_key(&state, saved))
+}
+
+#[tauri::command]
+fn list_ai_profiles(state: tauri::State<AppState>) -> ZopResult<Vec<AiConfig>> {
+    let db = state.db();
+    Ok(db
+        .list_ai_profiles()?
+        .into_iter()
+        .map(|profile| mask_api_key(&state, profile))
+        .collect())
+}
+
+#[tauri::command]
+fn create_ai_profile(
+    state: tauri::State<AppState>,
+    name: String,
+    config: SaveAiConfig,
+) -> ZopResult<AiConfig> {
+    let db = state.db();
+    let name = required_name(&name, "Profile")?;
+    let secret = store_api_key(&state, config.api_key.as_deref(), None)?;
+    let created = db.create_ai_profile(name, &config, &secret)?;
+    Ok(mask_api_key(&state, created))
+}
+
+/// Update a profile; a missing or empty API key keeps the stored one
+#[tauri::command]
+fn update_ai_profile(
+    state: tauri::State<AppState>,
+    id: i64,
+    name: String,
+    config: SaveAiConfig,
+) -> ZopResult<AiConfig> {
+    let db = state.db();
+    let name = required_name(&name, "Profile")?;
+    let existing = db.get_ai_profile(id)?.api_key_secret;
+    let secret = store_api_key(&state, config.api_key.as_deref(), existing)?;
+    let updated = db.update_ai_profile(id, name, &config, &secret)?;
+    Ok(mask_api_key(&state, updated))
+}
+
+#[tauri::command]
+fn set_default_ai_profile(state: tauri::State<AppState>, id: i64) -> ZopResult<AiConfig> {
+    let db = state.db();
+    let profile = db.set_default_ai_profile(id)?;
+    Ok(mask_api_key(&state, profile))
+}
+
+#[tauri::command]
+fn delete_ai_profile(state: tauri::State<AppState>, id: i64) -> ZopResult<()> {
+    let db = state.db();
+    if let Some(secret) = db.delete_ai_profile(id)? {
+        state.secrets().remove(&secret)?;
+    }
+    Ok(())
+}
+
+/// Check AI settings against the provider before saving them
+#[tauri::command]
+async fn test_ai_config(
+    state: tauri::State<'_, AppState>,
+    config: SaveAiConfig,
+    profile_id: Option<i64>,
+) -> ZopResult<provider::ConnectionCheck> {
+    let credentials = form_credentials(&state, &config, profile_id)?;
+    provider::check(&credentials).await
+}
+
+/// Models offered by the provider of the given AI settings
+#[tauri::command]
+async fn list_models(
+    state: tauri::State<'_, AppState>,
+    config: SaveAiConfig,
+    profile_id: Option<i64>,
+) -> ZopResult<Vec<String>> {
+    let credentials = form_credentials(&state, &config, profile_id)?;
+    provider::list_models(&credentials).await
+}
+
+/// AI settings from the config form, which may not be saved yet. Without a new
+/// API key, the one stored for profile `profile_id` is used.
+fn form_credentials(
+    state: &AppState,
+    config: &SaveAiConfig,
+    profile_id: Option<i64>,
+) -> ZopResult<AgentAiConfig> {
+    let api_key = match config
+        .api_key
+        .as_deref()
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+    {
+        Some(api_key) => api_key.to_string(),
+        None => match profile_id {
+            Some(id) => ai_credentials(state, Some(id))?.api_key,
+            None => return Err(ZopError::validation("An API key is required")),
+        },
     };
+    Ok(AgentAiConfig {
+        base_url: config.base_url.trim().to_string(),
+        api_key,
+        model_name: config.model_name.trim().to_string(),
+    })
+}
 
-    match sidecar_state.generate_sop(prompt.clone(), agent_config).await {
-        Ok(result) => {
-            // Convert AgentGeneratedSop to GeneratedSop
-            Ok(GeneratedSop {
-                title: result.title,
-                steps: result.steps.into_iter().map(|s| SopStep {
-                    step_type: s.step_type,
-                    label: s.label,
-                    content: s.content,
-                }).collect(),
-            })
+/// Seal `api_key` under the profile's `existing` secret, or a new one. Without
+/// a new key the existing one is kept.
+fn store_api_key(
+    state: &AppState,
+    api_key: Option<&str>,
+    existing: Option<String>,
+) -> ZopResult<String> {
+    let api_key = api_key.map(str::trim).filter(|key| !key.is_empty());
+    let mut secrets = state.secrets();
+    match (api_key, existing) {
+        (Some(api_key), Some(secret)) => {
+            secrets.set(&secret, api_key)?;
+            Ok(secret)
         }
-        Err(e) => {
-            eprintln!("Python agent failed, falling back to Rust: {}", e);
-            generate_sop_rust(&config, prompt).await
+        (Some(api_key), None) => secrets.insert(api_key),
+        (None, Some(secret)) => Ok(secret),
+        (None, None) => Err(ZopError::validation("An API key is required")),
+    }
+}
+
+/// `config` with its API key masked for display. A key that can't be
+/// decrypted is reported on the profile instead of failing the whole read.
+fn mask_api_key(state: &AppState, mut config: AiConfig) -> AiConfig {
+    if let Some(secret) = &config.api_key_secret {
+        match state.secrets().get(secret) {
+            Ok(key) => config.api_key_masked = key.map(|key| secrets::mask(&key)),
+            Err(e) => config.api_key_error = Some(e),
         }
     }
+    config
 }
 
-async fn generate_sop_rust(config: &AiConfig, prompt: String) -> Result<GeneratedSop, String> {
-    // Create OpenAI client with custom config
-    let openai_config = OpenAIConfig::new()
-        .with_api_key(&config.api_key)
-        .with_api_base(&config.base_url);
-
-    let client = Client::with_config(openai_config);
-
-    let system_prompt = r#"You are an SOP (Standard Operating Procedure) generator. Based on the user's description, generate a structured SOP with clear steps.
-
-Output format must be valid JSON with this structure:
-{
-  "title": "SOP Title",
-  "steps": [
-    {"step_type": "start", "label": "Start", "content": null},
-    {"step_type": "read", "label": "Step Name", "content": "Detailed description"},
-    {"step_type": "form", "label": "Input Step", "content": "What user needs to input"},
-    {"step_type": "end", "label": "End", "content": null}
-  ]
-}
-
-Rules:
-1. Always start with a "start" step and end with an "end" step
-2. Use "read" for information/instruction steps
-3. Use "form" for steps that require user input or action
-4. Keep labels concise (2-5 words)
-5. Content should be clear and actionable
-6. Generate 4-10 steps typically
-7. Output ONLY the JSON, no other text"#;
-
-    let request = CreateChatCompletionRequestArgs::default()
-        .model(&config.model_name)
-        .messages([
-            ChatCompletionRequestSystemMessageArgs::default()
-                .content(system_prompt)
-                .build()
-                .map_err(|e| e.to_string())?
-                .into(),
-            ChatCompletionRequestUserMessageArgs::default()
-                .content(prompt)
-                .build()
-                .map_err(|e| e.to_string())?
-                .into(),
-        ])
-        .build()
-        .map_err(|e| e.to_string())?;
+/// The settings of AI profile `profile_id`, or the default one, with the API
+/// key decrypted, for calls to the provider only
+fn ai_credentials(state: &AppState, profile_id: Option<i64>) -> ZopResult<AgentAiConfig> {
+    let not_configured = || {
+        ZopError::new(
+            ErrorCode::AiNotConfigured,
+            "AI configuration not found. Please configure AI settings first.",
+        )
+    };
+    let config = match profile_id {
+        Some(id) => state.db().get_ai_profile(id)?,
+        None => state.db().get_ai_config()?.ok_or_else(not_configured)?,
+    };
+    let api_key = match &config.api_key_secret {
+        Some(secret) => state.secrets().get(secret)?,
+        None => None,
+    };
+    Ok(AgentAiConfig {
+        base_url: config.base_url,
+        api_key: api_key.ok_or_else(not_configured)?,
+        model_name: config.model_name,
+    })
+}
+
+#[tauri::command]
+fn list_workspaces(state: tauri::State<AppState>) -> ZopResult<WorkspaceList> {
+    Ok(state.workspaces().list())
+}
+
+/// Register a workspace and create its database. `location` is a folder or
+/// `.db` file, e.g. on a shared drive; by default it goes in the data directory.
+#[tauri::command]
+fn create_workspace(
+    state: tauri::State<AppState>,
+    name: String,
+    location: Option<String>,
+) -> ZopResult<Workspace> {
+    let mut workspaces = state.workspaces();
+    let workspace = workspaces.create(&name, location.as_deref().map(Path::new))?;
 
-    let response = client.chat().create(request).await.map_err(|e| e.to_string())?;
+    if let Err(e) = state.open_db(&workspace.path) {
+        workspaces.remove(&workspace.id)?;
+        return Err(e);
+    }
+    Ok(workspace)
+}
+
+#[tauri::command]
+fn switch_workspace(state: tauri::State<AppState>, id: String) -> ZopResult<Workspace> {
+    let mut workspaces = state.workspaces();
+    let path = workspaces
+        .get(&id)
+        .ok_or_else(|| ZopError::not_found("Workspace", &id))?
+        .path
+        .clone();
 
-    let content = response.choices
-        .first()
-        .and_then(|c| c.message.content.as_ref())
-        .ok_or("No response from AI")?;
+    let db = state.open_db(&path)?;
+    let workspace = workspaces.set_active(&id)?;
+    state.set_db(db);
+    Ok(workspace)
+}
 
-    // Parse JSON response
-    let generated: GeneratedSop = serde_json::from_str(content)
-        .map_err(|e| format!("Failed to parse AI response: {}. Response: {}", e, content))?;
+/// Remove a workspace from the list, leaving its database file in place.
+/// Closing the active workspace switches to the default one.
+#[tauri::command]
+fn close_workspace(state: tauri::State<AppState>, id: String) -> ZopResult<WorkspaceList> {
+    let mut workspaces = state.workspaces();
+    if workspaces.active().id != id {
+        workspaces.remove(&id)?;
+        return Ok(workspaces.list());
+    }
 
-    Ok(generated)
+    // Open the default workspace first, so a failure leaves the closed one registered and open
+    let default = workspaces
+        .get(DEFAULT_WORKSPACE_ID)
+        .expect("default workspace is always registered")
+        .clone();
+    let db = state.open_db(&default.path)?;
+    workspaces.remove(&id)?;
+    workspaces.set_active(&default.id)?;
+    state.set_db(db);
+    Ok(workspaces.list())
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SopStep {
+    pub step_type: String, // "start", "read", "form", "end"
+    pub label: String,
+    pub content: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GeneratedSop {
+    pub title: String,
+    pub steps: Vec<SopStep>,
+}
+
+/// Generate an SOP, streaming its steps as `sop-generation://` events tagged
+/// `generation_id` (see generation.rs)
+#[tauri::command]
+async fn generate_sop(
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+    prompt: String,
+    profile_id: Option<i64>,
+    generation_id: Option<String>,
+) -> ZopResult<GeneratedSop> {
+    // The picked AI profile, or the default one
+    let config = ai_credentials(&state, profile_id)?;
+    let generation_id = generation_id.unwrap_or_else(|| app.state::<Generations>().new_id());
+    generation::generate(&app, config, prompt, &generation_id).await
+}
+
+/// Stop a running generation; false if it already finished
+#[tauri::command]
+fn cancel_generation(
+    generations: tauri::State<Generations>,
+    generation_id: String,
+) -> ZopResult<bool> {
+    Ok(generations.cancel(&generation_id))
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let db = Database::new().expect("Failed to initialize database");
+    let state = match workspace::data_dir().and_then(|dir| AppState::load(&dir)) {
+        Ok(state) => state,
+        Err(e) => {
+            // There is no window to report to yet
+            eprintln!("zop could not start: {} ({:?})", e, e.code);
+            std::process::exit(1);
+        }
+    };
 
     tauri::Builder::default()
-        .manage(AppState { db: Mutex::new(db) })
+        .manage(state)
         .manage(SidecarState::new())
+        .manage(Generations::default())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_shell::init())
         .invoke_handler(tauri::generate_handler![
             greet,
             toggle_always_on_top,
             create_sop_item,
+            create_sop_with_flow,
+            create_sop_with_todos,
             get_all_sop_items,
+            search,
             delete_sop_item,
             rename_sop_item,
             get_deleted_sop_items,
             restore_sop_item,
             permanently_delete_sop_item,
+            export_sop,
+            export_sop_markdown,
+            export_sop_mermaid,
+            export_sop_dot,
+            export_sop_bpmn,
+            export_sop_html,
+            import_sop,
+            move_sop_item,
+            set_sop_item_tags,
+            get_folders,
+            create_folder,
+            rename_folder,
+            move_folder,
+            delete_folder,
+            get_tags,
+            create_tag,
+            update_tag,
+            delete_tag,
             create_todo_item,
             get_todo_items,
             toggle_todo_item,
             delete_todo_item,
             update_todo_item,
             reorder_todo_items,
+            get_todo_list_state,
+            undo_todo,
+            redo_todo,
             get_flow_data,
             save_flow_data,
+            list_flow_revisions,
+            get_flow_revision,
+            diff_flow_revisions,
+            restore_flow_revision,
+            validate_flow,
+            start_flow_run,
+            advance_flow_run,
+            go_back_flow_run,
+            get_flow_run_path,
+            complete_flow_run,
+            abandon_flow_run,
+            list_flow_runs,
+            get_flow_run,
+            get_active_runs,
+            save_form_draft,
+            validate_form_input,
+            submit_form_input,
             get_ai_config,
             save_ai_config,
-            generate_sop
+            list_ai_profiles,
+            create_ai_profile,
+            update_ai_profile,
+            set_default_ai_profile,
+            delete_ai_profile,
+            test_ai_config,
+            list_models,
+            generate_sop,
+            cancel_generation,
+            list_workspaces,
+            create_workspace,
+            switch_workspace,
+            close_workspace
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
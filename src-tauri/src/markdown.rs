@@ -0,0 +1,222 @@
+//! Todo SOPs as GitHub-style Markdown task lists.
+//!
+//! An exported file is a level-1 heading with the SOP's name followed by one
+//! `- [ ]` / `- [x]` item per todo, in list order. Importing accepts any
+//! checklist: `-`, `*`, `+` and numbered items, with or without a checkbox.
+//! Nested items are flattened in document order, since todo lists are flat.
+//! Multi-line todos continue on indented lines, backslash-escaped where they
+//! would read as a list item or code fence.
+//! Conversion goes through `SopBundle` so importing shares the bundle path.
+
+use std::fs;
+use std::path::Path;
+
+use crate::bundle::{BundledSop, BundledTodo, SopBundle};
+use crate::error::{ZopError, ZopResult};
+
+pub const FILE_EXTENSION: &str = "md";
+
+/// Icon of SOPs created from a Markdown file, which has no icon of its own
+const IMPORTED_ICON: &str = "file-text";
+
+/// Whether `path` names a Markdown file rather than a bundle
+pub fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown"))
+}
+
+/// The todos of a bundle as a task list headed by the SOP's name
+pub fn task_list(bundle: &SopBundle) -> String {
+    let mut text = format!("# {}\n\n", bundle.sop.name.trim());
+    for todo in &bundle.todos {
+        let mut lines = todo.content.trim().lines();
+        let checkbox = if todo.completed { "[x]" } else { "[ ]" };
+        text.push_str(&format!("- {} {}\n", checkbox, lines.next().unwrap_or("")));
+        // Continuation lines are indented to stay part of the item, and escaped
+        // where they would otherwise start an item or a code block of their own
+        for line in lines {
+            let line = line.trim();
+            let escape = if needs_escape(line) { "\\" } else { "" };
+            text.push_str(&format!("  {}{}\n", escape, line));
+        }
+    }
+    text
+}
+
+/// A todo SOP from a task list. It is named after the first `#` heading, or
+/// `fallback_name` (usually the file name) when there is none.
+pub fn parse_task_list(text: &str, fallback_name: &str) -> ZopResult<SopBundle> {
+    let mut title: Option<String> = None;
+    let mut todos: Vec<BundledTodo> = Vec::new();
+    // Whether the previous line belonged to a list item, so indented text continues it
+    let mut in_item = false;
+    let mut in_code_block = false;
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if is_fence(trimmed) {
+            in_code_block = !in_code_block;
+            in_item = false;
+            continue;
+        }
+        if in_code_block {
+            continue;
+        }
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        if let Some(item) = list_item(trimmed) {
+            in_item = !item.content.is_empty();
+            if in_item {
+                todos.push(BundledTodo {
+                    content: item.content.to_string(),
+                    completed: item.completed,
+                });
+            }
+        } else if in_item && trimmed.len() < line.len() {
+            let todo = todos.last_mut().expect("in_item implies a todo was pushed");
+            let continuation = trimmed.trim_end();
+            todo.content.push('\n');
+            todo.content.push_str(match continuation.strip_prefix('\\') {
+                Some(escaped) if needs_escape(escaped) => escaped,
+                _ => continuation,
+            });
+        } else {
+            in_item = false;
+            if title.is_none() {
+                if let Some(heading) = trimmed.strip_prefix("# ") {
+                    title = Some(heading.trim().trim_end_matches('#').trim().to_string());
+                }
+            }
+        }
+    }
+
+    if todos.is_empty() {
+        return Err(ZopError::validation("No task list items found in the Markdown file"));
+    }
+
+    let name = title
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| fallback_name.trim().to_string());
+    let now = chrono::Utc::now().to_rfc3339();
+    let mut bundle = SopBundle::new(BundledSop {
+        name,
+        icon: IMPORTED_ICON.to_string(),
+        item_type: "todo".to_string(),
+        tags: Vec::new(),
+        created_at: now.clone(),
+        updated_at: now,
+    });
+    bundle.todos = todos;
+    Ok(bundle)
+}
+
+pub fn read(path: &Path) -> ZopResult<SopBundle> {
+    let fallback_name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("Imported checklist");
+    parse_task_list(&fs::read_to_string(path)?, fallback_name)
+}
+
+pub fn write(bundle: &SopBundle, path: &Path) -> ZopResult<()> {
+    if bundle.sop.item_type != "todo" {
+        return Err(ZopError::validation("Only todo SOPs can be exported as a Markdown task list"));
+    }
+    fs::write(path, task_list(bundle))?;
+    Ok(())
+}
+
+fn is_fence(line: &str) -> bool {
+    line.starts_with("```") || line.starts_with("~~~")
+}
+
+/// Whether a continuation line needs a leading `\` to be read back as plain text
+fn needs_escape(line: &str) -> bool {
+    list_item(line).is_some() || is_fence(line) || line.starts_with('\\')
+}
+
+struct ListItem<'a> {
+    content: &'a str,
+    completed: bool,
+}
+
+/// `line` (without its indentation) as a list item, if it is one
+fn list_item(line: &str) -> Option<ListItem<'_>> {
+    let rest = if let Some(rest) = line.strip_prefix(['-', '*', '+']) {
+        rest
+    } else {
+        let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits == 0 {
+            return None;
+        }
+        line[digits..].strip_prefix(['.', ')'])?
+    };
+    // The marker has to be followed by a space, or `**bold**` and `-1` would count
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    let rest = rest.trim_start();
+
+    let (completed, content) = match rest.get(..3) {
+        Some("[ ]") => (false, &rest[3..]),
+        Some("[x]") | Some("[X]") => (true, &rest[3..]),
+        _ => (false, rest),
+    };
+    Some(ListItem {
+        content: content.trim(),
+        completed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn todo(content: &str, completed: bool) -> BundledTodo {
+        BundledTodo {
+            content: content.to_string(),
+            completed,
+        }
+    }
+
+    fn contents(bundle: &SopBundle) -> Vec<(&str, bool)> {
+        bundle.todos.iter().map(|t| (t.content.as_str(), t.completed)).collect()
+    }
+
+    #[test]
+    fn nested_items_are_flattened_in_order() {
+        let text = "# Release\n\n- [x] Build\n  - [ ] Test on Linux\n    1. [X] Unit tests\n* Tag\n";
+        let bundle = parse_task_list(text, "file").unwrap();
+        assert_eq!(bundle.sop.name, "Release");
+        assert_eq!(
+            contents(&bundle),
+            [("Build", true), ("Test on Linux", false), ("Unit tests", true), ("Tag", false)]
+        );
+    }
+
+    #[test]
+    fn round_trips_checked_state_and_markdown_like_text() {
+        let mut bundle = parse_task_list("- a", "Checklist").unwrap();
+        bundle.todos = vec![
+            todo("Build", true),
+            todo("Test\n- not a separate item\n```\nstill the same todo", false),
+            todo("[x] looks checked\n2. not numbered\n\\ backslash", true),
+            todo("# not a heading\n* [ ] not a checkbox", false),
+        ];
+
+        let text = task_list(&bundle);
+        let parsed = parse_task_list(&text, "other").unwrap();
+        assert_eq!(parsed.sop.name, "Checklist");
+        assert_eq!(contents(&parsed), contents(&bundle));
+        // and exporting again gives the same file
+        assert_eq!(task_list(&parsed), text);
+    }
+
+    #[test]
+    fn requires_a_task_list() {
+        assert!(parse_task_list("# Title\n\nJust prose.\n", "file").is_err());
+    }
+}
@@ -0,0 +1,854 @@
+//! Versioned schema migrations for the SQLite database.
+//!
+//! The schema version is tracked with SQLite's `PRAGMA user_version`, and every
+//! applied migration is also recorded in the `schema_migrations` ledger. Each
+//! migration runs inside its own transaction together with the version bump, so
+//! a failure leaves the database at the previous version.
+//!
+//! Databases created before migrations existed report `user_version = 0` while
+//! already containing tables, so the early migrations inspect the live schema
+//! instead of assuming an empty database.
+
+use rusqlite::{Connection, Result as SqliteResult, Transaction};
+use std::fmt;
+
+/// A single ordered up-migration
+struct Migration {
+    version: i64,
+    description: &'static str,
+    up: fn(&Transaction) -> SqliteResult<()>,
+}
+
+/// All migrations, in the order they must be applied
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create initial tables",
+        up: create_initial_tables,
+    },
+    Migration {
+        version: 2,
+        description: "add sop_items.deleted_at",
+        up: add_sop_items_deleted_at,
+    },
+    Migration {
+        version: 3,
+        description: "add todo_items.sort_order",
+        up: add_todo_items_sort_order,
+    },
+    Migration {
+        version: 4,
+        description: "drop ai_config.provider without losing credentials",
+        up: drop_ai_config_provider,
+    },
+    Migration {
+        version: 5,
+        description: "create flow_runs and flow_run_steps",
+        up: create_flow_runs,
+    },
+    Migration {
+        version: 6,
+        description: "create flow_run_inputs",
+        up: create_flow_run_inputs,
+    },
+    Migration {
+        version: 7,
+        description: "add flow_runs.draft_values",
+        up: add_flow_runs_draft_values,
+    },
+    Migration {
+        version: 8,
+        description: "create flow_revisions and add flow_runs.revision_id",
+        up: create_flow_revisions,
+    },
+    Migration {
+        version: 9,
+        description: "create todo_operations",
+        up: create_todo_operations,
+    },
+    Migration {
+        version: 10,
+        description: "create search_index",
+        up: create_search_index,
+    },
+    Migration {
+        version: 11,
+        description: "create folders and tags",
+        up: create_folders_and_tags,
+    },
+    Migration {
+        version: 12,
+        description: "add ai_config.api_key_secret",
+        up: add_ai_config_api_key_secret,
+    },
+    Migration {
+        version: 13,
+        description: "turn ai_config rows into named profiles with a default",
+        up: add_ai_config_profiles,
+    },
+    Migration {
+        version: 14,
+        description: "delete rows orphaned while foreign keys were off",
+        up: delete_orphaned_rows,
+    },
+];
+
+/// Schema version this build of the app knows how to use
+pub const LATEST_VERSION: i64 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Sqlite(rusqlite::Error),
+    /// The database was written by a newer version of the app
+    TooNew { found: i64, supported: i64 },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Sqlite(e) => write!(f, "Database error: {}", e),
+            MigrationError::TooNew { found, supported } => write!(
+                f,
+                "Database schema version {} is newer than the supported version {}. Please update zop.",
+                found, supported
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(e: rusqlite::Error) -> Self {
+        MigrationError::Sqlite(e)
+    }
+}
+
+/// Read the current schema version
+pub fn current_version(conn: &Connection) -> SqliteResult<i64> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Bring the database up to `LATEST_VERSION`, applying pending migrations in order
+pub fn migrate(conn: &mut Connection) -> Result<(), MigrationError> {
+    let version = current_version(conn)?;
+    if version > LATEST_VERSION {
+        return Err(MigrationError::TooNew {
+            found: version,
+            supported: LATEST_VERSION,
+        });
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+        let tx = conn.transaction()?;
+        tx.execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version INTEGER PRIMARY KEY,
+                description TEXT NOT NULL,
+                applied_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+        (migration.up)(&tx)?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, description, applied_at) VALUES (?1, ?2, ?3)",
+            (migration.version, migration.description, chrono::Utc::now().to_rfc3339()),
+        )?;
+        // PRAGMA does not accept bound parameters
+        tx.execute_batch(&format!("PRAGMA user_version = {}", migration.version))?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Column names of `table`, empty if the table does not exist
+fn table_columns(conn: &Connection, table: &str) -> SqliteResult<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    columns.collect()
+}
+
+fn create_initial_tables(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS sop_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            icon TEXT NOT NULL,
+            item_type TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            deleted_at TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS todo_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sop_id INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            completed INTEGER NOT NULL DEFAULT 0,
+            sort_order INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY (sop_id) REFERENCES sop_items(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS flow_data (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sop_id INTEGER NOT NULL UNIQUE,
+            nodes TEXT NOT NULL,
+            edges TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY (sop_id) REFERENCES sop_items(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS ai_config (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            base_url TEXT NOT NULL,
+            api_key TEXT NOT NULL,
+            model_name TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );",
+    )
+}
+
+fn add_sop_items_deleted_at(tx: &Transaction) -> SqliteResult<()> {
+    if !table_columns(tx, "sop_items")?.iter().any(|c| c == "deleted_at") {
+        tx.execute("ALTER TABLE sop_items ADD COLUMN deleted_at TEXT", [])?;
+    }
+    Ok(())
+}
+
+fn add_todo_items_sort_order(tx: &Transaction) -> SqliteResult<()> {
+    if table_columns(tx, "todo_items")?.iter().any(|c| c == "sort_order") {
+        return Ok(());
+    }
+
+    tx.execute(
+        "ALTER TABLE todo_items ADD COLUMN sort_order INTEGER NOT NULL DEFAULT 0",
+        [],
+    )?;
+
+    // Keep the previous (insertion) order instead of leaving every item at 0
+    tx.execute(
+        "UPDATE todo_items SET sort_order = (
+            SELECT COUNT(*) FROM todo_items AS earlier
+            WHERE earlier.sop_id = todo_items.sop_id AND earlier.id < todo_items.id
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Endpoints of the providers the provider-based AI config could name
+const PROVIDER_BASE_URLS: &[(&str, &str)] = &[
+    ("openai", "https://api.openai.com/v1"),
+    ("deepseek", "https://api.deepseek.com/v1"),
+    ("moonshot", "https://api.moonshot.cn/v1"),
+    ("qwen", "https://dashscope.aliyuncs.com/compatible-mode/v1"),
+    ("dashscope", "https://dashscope.aliyuncs.com/compatible-mode/v1"),
+    ("zhipu", "https://open.bigmodel.cn/api/paas/v4"),
+    ("openrouter", "https://openrouter.ai/api/v1"),
+    ("ollama", "http://localhost:11434/v1"),
+];
+
+/// SQL giving the endpoint of the provider named in `column`, or '' if it isn't known
+fn provider_base_url(column: &str) -> String {
+    let cases: Vec<String> = PROVIDER_BASE_URLS
+        .iter()
+        .map(|(provider, url)| format!("WHEN '{}' THEN '{}'", provider, url))
+        .collect();
+    format!("CASE lower(trim({})) {} ELSE '' END", column, cases.join(" "))
+}
+
+/// Rows that only named a provider get that provider's endpoint; unknown
+/// providers are left with an empty base URL for the user to fill in.
+fn drop_ai_config_provider(tx: &Transaction) -> SqliteResult<()> {
+    let columns = table_columns(tx, "ai_config")?;
+    if !columns.iter().any(|c| c == "provider") {
+        return Ok(());
+    }
+
+    // The old table is kept as ai_config_legacy so nothing is ever deleted
+    tx.execute_batch(
+        "ALTER TABLE ai_config RENAME TO ai_config_legacy;
+
+        CREATE TABLE ai_config (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            base_url TEXT NOT NULL,
+            api_key TEXT NOT NULL,
+            model_name TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );",
+    )?;
+
+    let has = |name: &str| columns.iter().any(|c| c == name);
+    let source = |candidates: &[&str], fallback: &str| {
+        candidates
+            .iter()
+            .find(|c| has(c))
+            .map(|c| format!("COALESCE({}, {})", c, fallback))
+            .unwrap_or_else(|| fallback.to_string())
+    };
+
+    let provider_base_url = provider_base_url("provider");
+    let now = format!("'{}'", chrono::Utc::now().to_rfc3339());
+    tx.execute(
+        &format!(
+            "INSERT INTO ai_config (id, base_url, api_key, model_name, created_at, updated_at)
+             SELECT id, {}, {}, {}, {}, {} FROM ai_config_legacy",
+            source(&["base_url", "api_base", "endpoint"], &provider_base_url),
+            source(&["api_key"], "''"),
+            source(&["model_name", "model"], "''"),
+            source(&["created_at"], &now),
+            source(&["updated_at"], &now),
+        ),
+        [],
+    )?;
+    Ok(())
+}
+
+fn create_flow_runs(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE flow_runs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sop_id INTEGER NOT NULL,
+            operator TEXT,
+            status TEXT NOT NULL,
+            current_node_id TEXT,
+            started_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            finished_at TEXT,
+            FOREIGN KEY (sop_id) REFERENCES sop_items(id) ON DELETE CASCADE
+        );
+
+        CREATE INDEX idx_flow_runs_sop_id ON flow_runs(sop_id);
+
+        CREATE TABLE flow_run_steps (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            run_id INTEGER NOT NULL,
+            seq INTEGER NOT NULL,
+            node_id TEXT NOT NULL,
+            node_label TEXT NOT NULL,
+            node_shape TEXT NOT NULL,
+            entered_at TEXT NOT NULL,
+            reverted_at TEXT,
+            FOREIGN KEY (run_id) REFERENCES flow_runs(id) ON DELETE CASCADE
+        );
+
+        CREATE INDEX idx_flow_run_steps_run_id ON flow_run_steps(run_id);",
+    )
+}
+
+fn create_flow_run_inputs(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE flow_run_inputs (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            run_id INTEGER NOT NULL,
+            step_id INTEGER NOT NULL UNIQUE,
+            node_id TEXT NOT NULL,
+            input_values TEXT NOT NULL,
+            submitted_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY (run_id) REFERENCES flow_runs(id) ON DELETE CASCADE,
+            FOREIGN KEY (step_id) REFERENCES flow_run_steps(id) ON DELETE CASCADE
+        );
+
+        CREATE INDEX idx_flow_run_inputs_run_id ON flow_run_inputs(run_id);",
+    )
+}
+
+fn add_flow_runs_draft_values(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute("ALTER TABLE flow_runs ADD COLUMN draft_values TEXT", [])?;
+    Ok(())
+}
+
+fn create_flow_revisions(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE flow_revisions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sop_id INTEGER NOT NULL,
+            revision INTEGER NOT NULL,
+            nodes TEXT NOT NULL,
+            edges TEXT NOT NULL,
+            restored_from INTEGER,
+            created_at TEXT NOT NULL,
+            UNIQUE (sop_id, revision),
+            FOREIGN KEY (sop_id) REFERENCES sop_items(id) ON DELETE CASCADE
+        );
+
+        INSERT INTO flow_revisions (sop_id, revision, nodes, edges, created_at)
+        SELECT sop_id, 1, nodes, edges, updated_at FROM flow_data;
+
+        ALTER TABLE flow_runs ADD COLUMN revision_id INTEGER REFERENCES flow_revisions(id);",
+    )
+}
+
+fn create_todo_operations(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE todo_operations (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sop_id INTEGER NOT NULL,
+            operation TEXT NOT NULL,
+            undone_at TEXT,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (sop_id) REFERENCES sop_items(id) ON DELETE CASCADE
+        );
+
+        CREATE INDEX idx_todo_operations_sop_id ON todo_operations(sop_id);",
+    )
+}
+
+/// Full-text index over SOP names, todo contents and flow node labels and contents,
+/// kept in sync by triggers. The trigram tokenizer matches any substring of three or
+/// more characters, which also works for text without spaces between words.
+fn create_search_index(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute_batch(
+        "CREATE VIRTUAL TABLE search_index USING fts5(
+            title,
+            body,
+            sop_id UNINDEXED,
+            kind UNINDEXED,
+            target_id UNINDEXED,
+            tokenize = 'trigram'
+        );
+
+        CREATE TRIGGER search_index_sop_insert AFTER INSERT ON sop_items BEGIN
+            INSERT INTO search_index (title, body, sop_id, kind, target_id)
+            VALUES (NEW.name, '', NEW.id, 'sop', NULL);
+        END;
+        CREATE TRIGGER search_index_sop_update AFTER UPDATE OF name ON sop_items BEGIN
+            UPDATE search_index SET title = NEW.name WHERE kind = 'sop' AND sop_id = NEW.id;
+        END;
+        CREATE TRIGGER search_index_sop_delete AFTER DELETE ON sop_items BEGIN
+            DELETE FROM search_index WHERE sop_id = OLD.id;
+        END;
+
+        CREATE TRIGGER search_index_todo_insert AFTER INSERT ON todo_items BEGIN
+            INSERT INTO search_index (title, body, sop_id, kind, target_id)
+            VALUES ('', NEW.content, NEW.sop_id, 'todo', NEW.id);
+        END;
+        CREATE TRIGGER search_index_todo_update AFTER UPDATE OF content ON todo_items BEGIN
+            UPDATE search_index SET body = NEW.content WHERE kind = 'todo' AND target_id = NEW.id;
+        END;
+        CREATE TRIGGER search_index_todo_delete AFTER DELETE ON todo_items BEGIN
+            DELETE FROM search_index WHERE kind = 'todo' AND target_id = OLD.id;
+        END;
+
+        CREATE TRIGGER search_index_flow_insert AFTER INSERT ON flow_data BEGIN
+            INSERT INTO search_index (title, body, sop_id, kind, target_id)
+            SELECT coalesce(json_extract(value, '$.data.label'), ''),
+                   coalesce(json_extract(value, '$.data.config.content'), ''),
+                   NEW.sop_id, 'node', json_extract(value, '$.id')
+            FROM json_each(NEW.nodes);
+        END;
+        CREATE TRIGGER search_index_flow_update AFTER UPDATE OF nodes ON flow_data BEGIN
+            DELETE FROM search_index WHERE kind = 'node' AND sop_id = OLD.sop_id;
+            INSERT INTO search_index (title, body, sop_id, kind, target_id)
+            SELECT coalesce(json_extract(value, '$.data.label'), ''),
+                   coalesce(json_extract(value, '$.data.config.content'), ''),
+                   NEW.sop_id, 'node', json_extract(value, '$.id')
+            FROM json_each(NEW.nodes);
+        END;
+        CREATE TRIGGER search_index_flow_delete AFTER DELETE ON flow_data BEGIN
+            DELETE FROM search_index WHERE kind = 'node' AND sop_id = OLD.sop_id;
+        END;
+
+        INSERT INTO search_index (title, body, sop_id, kind, target_id)
+        SELECT name, '', id, 'sop', NULL FROM sop_items;
+        INSERT INTO search_index (title, body, sop_id, kind, target_id)
+        SELECT '', content, sop_id, 'todo', id FROM todo_items;
+        INSERT INTO search_index (title, body, sop_id, kind, target_id)
+        SELECT coalesce(json_extract(node.value, '$.data.label'), ''),
+               coalesce(json_extract(node.value, '$.data.config.content'), ''),
+               flow_data.sop_id, 'node', json_extract(node.value, '$.id')
+        FROM flow_data, json_each(flow_data.nodes) AS node
+        WHERE json_valid(flow_data.nodes);",
+    )
+}
+
+fn create_folders_and_tags(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE folders (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            parent_id INTEGER,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY (parent_id) REFERENCES folders(id) ON DELETE CASCADE
+        );
+        CREATE INDEX idx_folders_parent_id ON folders(parent_id);
+
+        ALTER TABLE sop_items ADD COLUMN folder_id INTEGER REFERENCES folders(id) ON DELETE SET NULL;
+        CREATE INDEX idx_sop_items_folder_id ON sop_items(folder_id);
+
+        CREATE TABLE tags (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            color TEXT,
+            created_at TEXT NOT NULL
+        );
+
+        CREATE TABLE sop_item_tags (
+            sop_id INTEGER NOT NULL,
+            tag_id INTEGER NOT NULL,
+            PRIMARY KEY (sop_id, tag_id),
+            FOREIGN KEY (sop_id) REFERENCES sop_items(id) ON DELETE CASCADE,
+            FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+        );
+        CREATE INDEX idx_sop_item_tags_tag_id ON sop_item_tags(tag_id);",
+    )
+}
+
+/// API keys move to the encrypted secret store (see secrets.rs), which the
+/// database only refers to. Moving the existing plaintext keys needs the store,
+/// so it happens when the app opens the database rather than here.
+fn add_ai_config_api_key_secret(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute_batch("ALTER TABLE ai_config ADD COLUMN api_key_secret TEXT;")
+}
+
+/// Each ai_config row becomes a named provider profile. Only the newest row
+/// was ever used, so it becomes the default one.
+fn add_ai_config_profiles(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute_batch(
+        "ALTER TABLE ai_config ADD COLUMN name TEXT NOT NULL DEFAULT '';
+        ALTER TABLE ai_config ADD COLUMN is_default INTEGER NOT NULL DEFAULT 0;
+
+        UPDATE ai_config SET name = 'Profile ' || id;
+        UPDATE ai_config SET name = 'Default', is_default = 1
+            WHERE id = (SELECT MAX(id) FROM ai_config);
+
+        CREATE UNIQUE INDEX idx_ai_config_name ON ai_config(name COLLATE NOCASE);
+        CREATE UNIQUE INDEX idx_ai_config_default ON ai_config(is_default) WHERE is_default = 1;",
+    )
+}
+
+/// Connections didn't turn foreign keys on, so with a SQLite that has them off
+/// by default, deleting an SOP left its dependents behind. Apply the ON DELETE
+/// clauses now to what they missed.
+fn delete_orphaned_rows(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute_batch(
+        "DELETE FROM todo_items WHERE sop_id NOT IN (SELECT id FROM sop_items);
+        DELETE FROM todo_operations WHERE sop_id NOT IN (SELECT id FROM sop_items);
+        DELETE FROM flow_data WHERE sop_id NOT IN (SELECT id FROM sop_items);
+        DELETE FROM flow_runs WHERE sop_id NOT IN (SELECT id FROM sop_items);
+        DELETE FROM flow_revisions WHERE sop_id NOT IN (SELECT id FROM sop_items);
+        UPDATE flow_runs SET revision_id = NULL WHERE revision_id NOT IN (SELECT id FROM flow_revisions);
+        DELETE FROM flow_run_steps WHERE run_id NOT IN (SELECT id FROM flow_runs);
+        DELETE FROM flow_run_inputs
+            WHERE run_id NOT IN (SELECT id FROM flow_runs) OR step_id NOT IN (SELECT id FROM flow_run_steps);
+        DELETE FROM sop_item_tags
+            WHERE sop_id NOT IN (SELECT id FROM sop_items) OR tag_id NOT IN (SELECT id FROM tags);
+        UPDATE sop_items SET folder_id = NULL WHERE folder_id NOT IN (SELECT id FROM folders);
+        DELETE FROM search_index WHERE sop_id NOT IN (SELECT id FROM sop_items);",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(sql: &str) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(sql).unwrap();
+        conn
+    }
+
+    fn count(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    fn assert_latest_schema(conn: &Connection) {
+        assert_eq!(current_version(conn).unwrap(), LATEST_VERSION);
+        assert!(table_columns(conn, "sop_items").unwrap().contains(&"deleted_at".to_string()));
+        assert!(table_columns(conn, "todo_items").unwrap().contains(&"sort_order".to_string()));
+        assert!(table_columns(conn, "flow_data").unwrap().contains(&"nodes".to_string()));
+        assert!(table_columns(conn, "sop_items").unwrap().contains(&"folder_id".to_string()));
+        assert!(!table_columns(conn, "ai_config").unwrap().contains(&"provider".to_string()));
+        assert!(table_columns(conn, "ai_config").unwrap().contains(&"api_key_secret".to_string()));
+        assert!(table_columns(conn, "ai_config").unwrap().contains(&"is_default".to_string()));
+        assert_eq!(
+            count(conn, "SELECT COUNT(*) FROM schema_migrations"),
+            MIGRATIONS.len() as i64
+        );
+    }
+
+    /// The very first release: no soft delete, no ordering, provider-based AI config
+    const LEGACY_INITIAL: &str = "
+        CREATE TABLE sop_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            icon TEXT NOT NULL,
+            item_type TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        CREATE TABLE todo_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sop_id INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            completed INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        CREATE TABLE ai_config (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            provider TEXT NOT NULL,
+            api_key TEXT NOT NULL,
+            model TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        INSERT INTO sop_items VALUES (1, 'Deploy', 'zap', 'todo', 't0', 't0');
+        INSERT INTO todo_items VALUES (1, 1, 'first', 0, 't0', 't0');
+        INSERT INTO todo_items VALUES (2, 1, 'second', 1, 't0', 't0');
+        INSERT INTO todo_items VALUES (3, 1, 'third', 0, 't0', 't0');
+        INSERT INTO ai_config VALUES (1, 'openai', 'sk-secret', 'gpt-4o', 't0', 't1');
+    ";
+
+    /// Soft delete and ordering added, flow charts introduced, AI config still provider-based
+    const LEGACY_FLOW: &str = "
+        CREATE TABLE sop_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            icon TEXT NOT NULL,
+            item_type TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            deleted_at TEXT
+        );
+        CREATE TABLE todo_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sop_id INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            completed INTEGER NOT NULL DEFAULT 0,
+            sort_order INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        CREATE TABLE flow_data (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sop_id INTEGER NOT NULL UNIQUE,
+            nodes TEXT NOT NULL,
+            edges TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        CREATE TABLE ai_config (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            provider TEXT NOT NULL,
+            base_url TEXT,
+            api_key TEXT NOT NULL,
+            model_name TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        INSERT INTO sop_items VALUES (1, 'Onboarding', 'zap', 'flowchart', 't0', 't0', NULL);
+        INSERT INTO todo_items VALUES (1, 1, 'b', 0, 1, 't0', 't0');
+        INSERT INTO todo_items VALUES (2, 1, 'a', 0, 0, 't0', 't0');
+        INSERT INTO flow_data VALUES (1, 1, '[{\"id\":\"n1\",\"data\":{\"label\":\"Sign contract\",\"shape\":\"read\",\"config\":{\"content\":\"Send the offer\"}}}]', '[]', 't0', 't0');
+        INSERT INTO ai_config VALUES (7, 'custom', 'https://llm.example.com/v1', 'sk-keep', 'llama3', 't0', 't1');
+    ";
+
+    /// The schema produced by the last release before migrations, without a user_version
+    const UNVERSIONED_CURRENT: &str = "
+        CREATE TABLE sop_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            icon TEXT NOT NULL,
+            item_type TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            deleted_at TEXT
+        );
+        CREATE TABLE todo_items (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sop_id INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            completed INTEGER NOT NULL DEFAULT 0,
+            sort_order INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        CREATE TABLE flow_data (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            sop_id INTEGER NOT NULL UNIQUE,
+            nodes TEXT NOT NULL,
+            edges TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        CREATE TABLE ai_config (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            base_url TEXT NOT NULL,
+            api_key TEXT NOT NULL,
+            model_name TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        INSERT INTO sop_items VALUES (1, 'Release', 'zap', 'todo', 't0', 't0', 't2');
+        INSERT INTO todo_items VALUES (1, 1, 'tag', 1, 0, 't0', 't0');
+        INSERT INTO ai_config VALUES (1, 'https://api.openai.com/v1', 'sk-current', 'gpt-4o-mini', 't0', 't0');
+    ";
+
+    #[test]
+    fn migrates_empty_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        assert_latest_schema(&conn);
+    }
+
+    #[test]
+    fn migrates_legacy_initial_schema() {
+        let mut conn = fixture(LEGACY_INITIAL);
+        migrate(&mut conn).unwrap();
+        assert_latest_schema(&conn);
+
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM sop_items WHERE deleted_at IS NULL"), 1);
+        let order: Vec<(String, i64)> = conn
+            .prepare("SELECT content, sort_order FROM todo_items ORDER BY sort_order")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<SqliteResult<_>>()
+            .unwrap();
+        assert_eq!(
+            order,
+            vec![("first".into(), 0), ("second".into(), 1), ("third".into(), 2)]
+        );
+
+        let (base_url, api_key, model_name): (String, String, String) = conn
+            .query_row("SELECT base_url, api_key, model_name FROM ai_config", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(base_url, "https://api.openai.com/v1");
+        assert_eq!(api_key, "sk-secret");
+        assert_eq!(model_name, "gpt-4o");
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM ai_config_legacy"), 1);
+    }
+
+    #[test]
+    fn migrates_legacy_flow_schema() {
+        let mut conn = fixture(LEGACY_FLOW);
+        migrate(&mut conn).unwrap();
+        assert_latest_schema(&conn);
+
+        // Existing ordering is left untouched
+        let first: String = conn
+            .query_row("SELECT content FROM todo_items ORDER BY sort_order LIMIT 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(first, "a");
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM flow_data"), 1);
+        // and the saved flow becomes its first revision
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM flow_revisions WHERE sop_id = 1 AND revision = 1"), 1);
+        // Existing names, todos and nodes are indexed for search
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM search_index"), 4);
+        let node: String = conn
+            .query_row("SELECT target_id FROM search_index WHERE search_index MATCH 'offer'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(node, "n1");
+
+        let (id, base_url, api_key, created_at): (i64, String, String, String) = conn
+            .query_row("SELECT id, base_url, api_key, created_at FROM ai_config", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap();
+        assert_eq!(id, 7);
+        assert_eq!(base_url, "https://llm.example.com/v1");
+        assert_eq!(api_key, "sk-keep");
+        assert_eq!(created_at, "t0");
+    }
+
+    #[test]
+    fn fills_in_base_urls_of_known_providers() {
+        let mut conn = fixture(LEGACY_INITIAL);
+        conn.execute_batch(
+            "INSERT INTO ai_config VALUES (2, ' DeepSeek ', 'sk-2', 'deepseek-chat', 't0', 't0');
+            INSERT INTO ai_config VALUES (3, 'acme', 'sk-3', 'acme-1', 't0', 't0');",
+        )
+        .unwrap();
+        migrate(&mut conn).unwrap();
+
+        let base_url = |id: i64| -> String {
+            conn.query_row("SELECT base_url FROM ai_config WHERE id = ?1", [id], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(base_url(1), "https://api.openai.com/v1");
+        assert_eq!(base_url(2), "https://api.deepseek.com/v1");
+        // Unknown providers are left for the user to fill in
+        assert_eq!(base_url(3), "");
+    }
+
+    #[test]
+    fn migrates_unversioned_current_schema() {
+        let mut conn = fixture(UNVERSIONED_CURRENT);
+        migrate(&mut conn).unwrap();
+        assert_latest_schema(&conn);
+
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM sop_items WHERE deleted_at = 't2'"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM todo_items WHERE completed = 1"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM ai_config WHERE api_key = 'sk-current'"), 1);
+        // The existing settings become the default profile
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM ai_config WHERE name = 'Default' AND is_default = 1"), 1);
+        assert_eq!(
+            count(&conn, "SELECT COUNT(*) FROM sqlite_master WHERE name = 'ai_config_legacy'"),
+            0
+        );
+    }
+
+    #[test]
+    fn deletes_orphaned_rows() {
+        let mut conn = fixture(LEGACY_FLOW);
+        migrate(&mut conn).unwrap();
+        // Rows a deleted SOP left behind while foreign keys were off
+        conn.execute_batch(
+            "PRAGMA foreign_keys = OFF;
+            INSERT INTO todo_items (sop_id, content, completed, sort_order, created_at, updated_at) VALUES (2, 'x', 0, 0, 't0', 't0');
+            INSERT INTO flow_data (sop_id, nodes, edges, created_at, updated_at) VALUES (2, '[]', '[]', 't0', 't0');
+            INSERT INTO flow_runs (id, sop_id, status, started_at, updated_at) VALUES (5, 2, 'active', 't0', 't0');
+            INSERT INTO flow_run_steps (id, run_id, seq, node_id, node_label, node_shape, entered_at) VALUES (9, 5, 0, 'n', 'n', 'start', 't0');
+            INSERT INTO flow_run_inputs (run_id, step_id, node_id, input_values, submitted_at, updated_at) VALUES (5, 9, 'n', '{}', 't0', 't0');
+            DELETE FROM schema_migrations WHERE version = 14;
+            PRAGMA user_version = 13;
+            PRAGMA foreign_keys = ON;",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+        assert_latest_schema(&conn);
+        for table in ["todo_items", "flow_data", "flow_runs", "flow_revisions"] {
+            let sql = format!("SELECT COUNT(*) FROM {} WHERE sop_id = 2", table);
+            assert_eq!(count(&conn, &sql), 0, "{} rows left", table);
+        }
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM flow_run_steps"), 0);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM flow_run_inputs"), 0);
+        // The surviving SOP keeps its rows
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM flow_data WHERE sop_id = 1"), 1);
+    }
+
+    #[test]
+    fn migrate_is_idempotent() {
+        let mut conn = fixture(LEGACY_INITIAL);
+        migrate(&mut conn).unwrap();
+        migrate(&mut conn).unwrap();
+        assert_latest_schema(&conn);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM todo_items"), 3);
+    }
+
+    #[test]
+    fn refuses_newer_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(&format!("PRAGMA user_version = {}", LATEST_VERSION + 1))
+            .unwrap();
+        match migrate(&mut conn) {
+            Err(MigrationError::TooNew { found, supported }) => {
+                assert_eq!(found, LATEST_VERSION + 1);
+                assert_eq!(supported, LATEST_VERSION);
+            }
+            other => panic!("expected TooNew, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Checks against an OpenAI-compatible provider, so AI settings can be
+//! verified before they are used to generate an SOP.
+//!
+//! Both checks go through the provider's models API with short timeouts.
+//! Failures keep the categories of `error::ai_http_error`, so a wrong host,
+//! a TLS problem, a rejected key, an unknown model and a timeout each have
+//! their own error code.
+
+use async_openai::error::OpenAIError;
+use async_openai::{config::OpenAIConfig, Client};
+use serde::Serialize;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::error::{ErrorCode, ZopError, ZopResult};
+use crate::sidecar::AgentAiConfig;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Limit for one call, including async-openai's retries when rate limited
+const CALL_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A successful connection test
+#[derive(Debug, Serialize)]
+pub struct ConnectionCheck {
+    /// Number of models the provider lists
+    pub model_count: usize,
+    /// How long listing the models took
+    pub latency_ms: u64,
+}
+
+/// Ids of the models the provider offers, sorted
+pub async fn list_models(config: &AgentAiConfig) -> ZopResult<Vec<String>> {
+    let client = client(config)?;
+    let mut models: Vec<String> = call(client.models().list()).await?.data.into_iter().map(|m| m.id).collect();
+    models.sort();
+    models.dedup();
+    Ok(models)
+}
+
+/// Check that the endpoint answers, accepts the API key and offers the model
+pub async fn check(config: &AgentAiConfig) -> ZopResult<ConnectionCheck> {
+    let client = client(config)?;
+    let started = Instant::now();
+    // Listing models needs a reachable endpoint and a valid key
+    let models = call(client.models().list()).await?.data;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    // Some gateways list only part of what they serve, so ask for the model itself
+    if !models.iter().any(|m| m.id == config.model_name) {
+        if let Err(e) = call(client.models().retrieve(&config.model_name)).await {
+            return Err(match e.code {
+                ErrorCode::AiTimeout
+                | ErrorCode::AiUnreachable
+                | ErrorCode::AiHostNotFound
+                | ErrorCode::AiTls
+                | ErrorCode::AiUnauthorized
+                | ErrorCode::AiRateLimited => e,
+                _ => {
+                    let available: Vec<&str> = models.iter().map(|m| m.id.as_str()).collect();
+                    ZopError::new(
+                        ErrorCode::AiModelNotFound,
+                        format!("The provider does not offer the model {}", config.model_name),
+                    )
+                    .with_details(serde_json::json!({ "available": available }))
+                }
+            });
+        }
+    }
+
+    Ok(ConnectionCheck {
+        model_count: models.len(),
+        latency_ms,
+    })
+}
+
+fn client(config: &AgentAiConfig) -> ZopResult<Client<OpenAIConfig>> {
+    let url = reqwest::Url::parse(config.base_url.trim())
+        .map_err(|e| ZopError::validation(format!("The API base URL is not valid: {}", e)))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(ZopError::validation("The API base URL must start with http:// or https://"));
+    }
+
+    let http_client = reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(CALL_TIMEOUT)
+        .build()
+        .map_err(|e| ZopError::internal(e.to_string()))?;
+    let openai_config = OpenAIConfig::new()
+        .with_api_key(&config.api_key)
+        .with_api_base(config.base_url.trim().trim_end_matches('/'));
+    Ok(Client::with_config(openai_config).with_http_client(http_client))
+}
+
+/// `request`, failing with a timeout once `CALL_TIMEOUT` has passed
+async fn call<T>(request: impl Future<Output = Result<T, OpenAIError>>) -> ZopResult<T> {
+    match tokio::time::timeout(CALL_TIMEOUT, request).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(ZopError::new(
+            ErrorCode::AiTimeout,
+            format!("The provider did not answer within {} seconds", CALL_TIMEOUT.as_secs()),
+        )),
+    }
+}
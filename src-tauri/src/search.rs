@@ -0,0 +1,180 @@
+//! Query helpers for the full-text search index.
+//!
+//! `search_index` (see migrations) uses SQLite's trigram tokenizer, so a search
+//! term matches any text containing it. Terms shorter than three characters
+//! can't be looked up in a trigram index; queries with such terms fall back to
+//! `LIKE`, and their snippets are built here instead of by FTS5's `snippet()`.
+
+pub const MARK_OPEN: &str = "<mark>";
+pub const MARK_CLOSE: &str = "</mark>";
+
+/// Characters of context kept on each side of the first match in a snippet
+const SNIPPET_CONTEXT: usize = 24;
+const ELLIPSIS: &str = "…";
+
+/// Whitespace-separated terms of a query; every one of them has to match
+pub fn terms(query: &str) -> Vec<&str> {
+    query.split_whitespace().collect()
+}
+
+/// An FTS5 query requiring every term as a literal substring, or `None`
+/// when a term is too short for the trigram index
+pub fn fts_query(terms: &[&str]) -> Option<String> {
+    if terms.iter().any(|term| term.chars().count() < 3) {
+        return None;
+    }
+    let phrases: Vec<String> = terms
+        .iter()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+    Some(phrases.join(" "))
+}
+
+/// A `LIKE ... ESCAPE '\'` pattern matching text that contains `term`
+pub fn like_pattern(term: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in term.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+/// Excerpt of `text` around the first occurrence of any term, with every
+/// occurrence inside the excerpt wrapped in `MARK_OPEN`/`MARK_CLOSE`.
+/// Matching ignores case, like the index does.
+pub fn highlight(text: &str, terms: &[&str]) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let folded: Vec<char> = chars.iter().map(|c| fold(*c)).collect();
+
+    let mut matches: Vec<(usize, usize)> = Vec::new();
+    for term in terms {
+        let needle: Vec<char> = term.chars().map(fold).collect();
+        if needle.is_empty() || needle.len() > folded.len() {
+            continue;
+        }
+        for start in 0..=folded.len() - needle.len() {
+            if folded[start..start + needle.len()] == needle[..] {
+                matches.push((start, start + needle.len()));
+            }
+        }
+    }
+    matches.sort();
+
+    let first = matches.first().map_or(0, |m| m.0);
+    let from = first.saturating_sub(SNIPPET_CONTEXT);
+    let to = (first + SNIPPET_CONTEXT * 2).min(chars.len());
+
+    let mut snippet = String::new();
+    if from > 0 {
+        snippet.push_str(ELLIPSIS);
+    }
+    let mut i = from;
+    for &(start, end) in &matches {
+        // Skip matches overlapping one already marked, and stop outside the excerpt
+        if start < i {
+            continue;
+        }
+        if start >= to {
+            break;
+        }
+        snippet.extend(&chars[i..start]);
+        snippet.push_str(MARK_OPEN);
+        snippet.extend(&chars[start..end.min(to)]);
+        snippet.push_str(MARK_CLOSE);
+        i = end.min(to);
+    }
+    snippet.extend(&chars[i..to]);
+    if to < chars.len() {
+        snippet.push_str(ELLIPSIS);
+    }
+    snippet
+}
+
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_matches_in_short_text() {
+        // Shorter than the context on either side, so nothing is cut
+        assert_eq!(highlight("Deploy the app", &["app"]), "Deploy the <mark>app</mark>");
+        assert_eq!(highlight("app first", &["APP"]), "<mark>app</mark> first");
+        assert_eq!(highlight("no match here", &["zzz"]), "no match here");
+        assert_eq!(highlight("", &["a"]), "");
+    }
+
+    #[test]
+    fn marks_overlapping_matches_once() {
+        assert_eq!(highlight("aaaa", &["aa"]), "<mark>aa</mark><mark>aa</mark>");
+        assert_eq!(highlight("backup", &["back", "ckup"]), "<mark>back</mark>up");
+        assert_eq!(highlight("x backup", &["ack", "back"]), "x <mark>back</mark>up");
+    }
+
+    #[test]
+    fn cuts_long_text_around_the_first_match() {
+        let text = format!("{}needle{}", "a".repeat(40), "b".repeat(60));
+        let snippet = highlight(&text, &["needle"]);
+        let expected = format!(
+            "{}{}<mark>needle</mark>{}{}",
+            ELLIPSIS,
+            "a".repeat(SNIPPET_CONTEXT),
+            "b".repeat(SNIPPET_CONTEXT * 2 - "needle".len()),
+            ELLIPSIS
+        );
+        assert_eq!(snippet, expected);
+
+        // A match at the very end is kept whole, one at the very start gets no leading ellipsis
+        let end = format!("{}needle", "a".repeat(60));
+        assert!(highlight(&end, &["needle"]).ends_with("<mark>needle</mark>"));
+        let start = format!("needle{}", "a".repeat(60));
+        assert!(highlight(&start, &["needle"]).starts_with("<mark>needle</mark>"));
+        assert!(highlight(&start, &["needle"]).ends_with(ELLIPSIS));
+    }
+
+    #[test]
+    fn cuts_a_match_at_the_end_of_the_excerpt() {
+        let text = format!("key{}key{}", "-".repeat(SNIPPET_CONTEXT * 2 - 4), "-".repeat(10));
+        let snippet = highlight(&text, &["key"]);
+        assert!(snippet.ends_with(&format!("<mark>k</mark>{}", ELLIPSIS)), "{}", snippet);
+    }
+
+    #[test]
+    fn folds_non_ascii_case() {
+        assert_eq!(highlight("ÜBER Straße", &["über"]), "<mark>ÜBER</mark> Straße");
+        assert_eq!(highlight("Привет мир", &["МИР"]), "Привет <mark>мир</mark>");
+        // Characters that lowercase to several keep the text's positions intact
+        assert_eq!(highlight("İstanbul", &["stan"]), "İ<mark>stan</mark>bul");
+    }
+
+    #[test]
+    fn escapes_like_wildcards() {
+        assert_eq!(like_pattern("50%"), "%50\\%%");
+        assert_eq!(like_pattern("a_b"), "%a\\_b%");
+        assert_eq!(like_pattern("c:\\dir"), "%c:\\\\dir%");
+        assert_eq!(like_pattern("plain"), "%plain%");
+    }
+
+    #[test]
+    fn quotes_fts_terms() {
+        assert_eq!(fts_query(&terms("deploy  app")).as_deref(), Some("\"deploy\" \"app\""));
+        assert_eq!(fts_query(&["say\"hi\""]).as_deref(), Some("\"say\"\"hi\"\"\""));
+        assert_eq!(fts_query(&["OR", "NOT"]), None);
+        assert_eq!(fts_query(&["and", "NEAR"]).as_deref(), Some("\"and\" \"NEAR\""));
+    }
+
+    #[test]
+    fn short_terms_fall_back_to_like() {
+        assert_eq!(fts_query(&["deploy", "v2"]), None);
+        // Counted in characters, not bytes
+        assert_eq!(fts_query(&["日本"]), None);
+        assert_eq!(fts_query(&["日本語"]).as_deref(), Some("\"日本語\""));
+    }
+}
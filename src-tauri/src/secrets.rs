@@ -0,0 +1,321 @@
+//! Encrypted store for secrets such as AI API keys, kept out of the databases.
+//!
+//! Secrets live in `secrets.json` in the data directory, each sealed with
+//! ChaCha20-Poly1305 under its own random nonce. Databases only hold the id of
+//! a secret, so workspace files can be shared or backed up without leaking
+//! keys, and the webview only ever sees a masked key.
+//!
+//! The encryption key is derived from `ZOP_SECRET_PASSPHRASE` when it is set
+//! (PBKDF2-HMAC-SHA256, salted per vault). Otherwise a random key is kept in
+//! `secret.key` next to the vault, readable only by the current user, which
+//! works headless and without any OS keyring.
+
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+use crate::error::{ErrorCode, ZopError, ZopResult};
+
+const VAULT_FILE: &str = "secrets.json";
+const KEY_FILE: &str = "secret.key";
+const PASSPHRASE_ENV: &str = "ZOP_SECRET_PASSPHRASE";
+const VERSION: u32 = 1;
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 600_000;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VaultFile {
+    version: u32,
+    /// Salt of the passphrase-derived key, hex encoded
+    salt: String,
+    #[serde(default)]
+    entries: BTreeMap<String, SealedSecret>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct SealedSecret {
+    nonce: String,
+    ciphertext: String,
+}
+
+pub struct SecretStore {
+    path: PathBuf,
+    key: LessSafeKey,
+    file: VaultFile,
+    rng: SystemRandom,
+}
+
+impl SecretStore {
+    /// Open the vault in `dir`, creating it and its key on first use
+    pub fn open(dir: &Path) -> ZopResult<Self> {
+        let passphrase = std::env::var(PASSPHRASE_ENV).ok().filter(|p| !p.is_empty());
+        Self::open_with(dir, passphrase.as_deref())
+    }
+
+    /// Open the vault in `dir` with a key derived from `passphrase`, or the machine key without one
+    fn open_with(dir: &Path, passphrase: Option<&str>) -> ZopResult<Self> {
+        let rng = SystemRandom::new();
+        let path = dir.join(VAULT_FILE);
+        let file = if path.exists() {
+            serde_json::from_str(&fs::read_to_string(&path)?)
+                .map_err(|e| secret_error(format!("The secret store {} is damaged: {}", path.display(), e)))?
+        } else {
+            VaultFile {
+                version: VERSION,
+                salt: to_hex(&random::<SALT_LEN>(&rng)?),
+                entries: BTreeMap::new(),
+            }
+        };
+        if file.version > VERSION {
+            return Err(secret_error("The secret store was written by a newer version of the app"));
+        }
+
+        let key_bytes = match passphrase {
+            Some(passphrase) => {
+                let salt = from_hex(&file.salt)?;
+                let mut key = [0u8; KEY_LEN];
+                let iterations = NonZeroU32::new(PBKDF2_ITERATIONS).expect("iterations are non-zero");
+                pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, &salt, passphrase.as_bytes(), &mut key);
+                key
+            }
+            None => machine_key(&dir.join(KEY_FILE), &rng)?,
+        };
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &key_bytes).map_err(|_| secret_error("Invalid secret key"))?;
+
+        Ok(Self {
+            path,
+            key: LessSafeKey::new(key),
+            file,
+            rng,
+        })
+    }
+
+    /// Store a new secret and return its id
+    pub fn insert(&mut self, value: &str) -> ZopResult<String> {
+        let id = to_hex(&random::<16>(&self.rng)?);
+        self.set(&id, value)?;
+        Ok(id)
+    }
+
+    /// Store `value` under `id`, replacing what was there
+    pub fn set(&mut self, id: &str, value: &str) -> ZopResult<()> {
+        let nonce = random::<NONCE_LEN>(&self.rng)?;
+        let mut sealed = value.as_bytes().to_vec();
+        // The id is authenticated too, so a sealed value can't be moved to another entry
+        self.key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(id.as_bytes()), &mut sealed)
+            .map_err(|_| secret_error("Could not encrypt the secret"))?;
+
+        self.file.entries.insert(
+            id.to_string(),
+            SealedSecret {
+                nonce: to_hex(&nonce),
+                ciphertext: to_hex(&sealed),
+            },
+        );
+        self.save()
+    }
+
+    /// The decrypted secret, or `None` if there is none with this id
+    pub fn get(&self, id: &str) -> ZopResult<Option<String>> {
+        let Some(entry) = self.file.entries.get(id) else {
+            return Ok(None);
+        };
+        let nonce: [u8; NONCE_LEN] = from_hex(&entry.nonce)?
+            .try_into()
+            .map_err(|_| secret_error("The secret store is damaged"))?;
+        let mut sealed = from_hex(&entry.ciphertext)?;
+        let plain = self
+            .key
+            .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::from(id.as_bytes()), &mut sealed)
+            .map_err(|_| {
+                secret_error(format!(
+                    "Could not decrypt a stored secret; if {} was set when it was saved, set it to the same value",
+                    PASSPHRASE_ENV
+                ))
+            })?;
+        let value = String::from_utf8(plain.to_vec()).map_err(|_| secret_error("The secret store is damaged"))?;
+        Ok(Some(value))
+    }
+
+    /// Forget the secret with this id, if there is one
+    pub fn remove(&mut self, id: &str) -> ZopResult<()> {
+        if self.file.entries.remove(id).is_some() {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> ZopResult<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        write_private(&tmp, serde_json::to_string_pretty(&self.file)?.as_bytes())?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// `key` with all but its first and last few characters hidden, for display
+pub fn mask(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() < 12 {
+        return "••••••••".to_string();
+    }
+    let head: String = chars[..3].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}••••••••{}", head, tail)
+}
+
+/// The random key in `path`, generated on first use
+fn machine_key(path: &Path, rng: &SystemRandom) -> ZopResult<[u8; KEY_LEN]> {
+    if path.exists() {
+        return from_hex(fs::read_to_string(path)?.trim())?
+            .try_into()
+            .map_err(|_| secret_error(format!("The key file {} is damaged", path.display())));
+    }
+    let key = random::<KEY_LEN>(rng)?;
+    write_private(path, to_hex(&key).as_bytes())?;
+    Ok(key)
+}
+
+/// Write a file only the current user can read
+fn write_private(path: &Path, contents: &[u8]) -> ZopResult<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    std::io::Write::write_all(&mut options.open(path)?, contents)?;
+    Ok(())
+}
+
+fn random<const N: usize>(rng: &SystemRandom) -> ZopResult<[u8; N]> {
+    let mut bytes = [0u8; N];
+    rng.fill(&mut bytes).map_err(|_| secret_error("The system random number generator failed"))?;
+    Ok(bytes)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> ZopResult<Vec<u8>> {
+    // from_str_radix would also take a sign, as in "+1"
+    if text.len() % 2 == 1 || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(secret_error("The secret store is damaged"));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2).unwrap_or(""), 16))
+        .collect::<Result<_, _>>()
+        .map_err(|_| secret_error("The secret store is damaged"))
+}
+
+fn secret_error(message: impl Into<String>) -> ZopError {
+    ZopError::new(ErrorCode::SecretStore, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A fresh directory for one test's vault
+    fn temp_dir() -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("zop-secrets-{}-{}", std::process::id(), n));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn round_trips_through_the_vault_file() {
+        let dir = temp_dir();
+        let mut store = SecretStore::open(&dir).unwrap();
+        let id = store.insert("sk-live-0123456789").unwrap();
+        store.set("named", "second").unwrap();
+
+        let raw = fs::read_to_string(dir.join(VAULT_FILE)).unwrap();
+        assert!(!raw.contains("sk-live"));
+
+        let reopened = SecretStore::open(&dir).unwrap();
+        assert_eq!(reopened.get(&id).unwrap().as_deref(), Some("sk-live-0123456789"));
+        assert_eq!(reopened.get("named").unwrap().as_deref(), Some("second"));
+        assert_eq!(reopened.get("missing").unwrap(), None);
+
+        let mut reopened = reopened;
+        reopened.remove(&id).unwrap();
+        assert_eq!(SecretStore::open(&dir).unwrap().get(&id).unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_secret_moved_to_another_id() {
+        let dir = temp_dir();
+        let mut store = SecretStore::open_with(&dir, None).unwrap();
+        let id = store.insert("sk-moved").unwrap();
+        let sealed = store.file.entries[&id].clone();
+        store.file.entries.insert("other".to_string(), sealed);
+
+        let error = store.get("other").unwrap_err();
+        assert_eq!(error.code, ErrorCode::SecretStore);
+        assert_eq!(store.get(&id).unwrap().as_deref(), Some("sk-moved"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_wrong_passphrase_is_an_error() {
+        let dir = temp_dir();
+        let id = SecretStore::open_with(&dir, Some("right")).unwrap().insert("sk-pass").unwrap();
+
+        let right = SecretStore::open_with(&dir, Some("right")).unwrap();
+        assert_eq!(right.get(&id).unwrap().as_deref(), Some("sk-pass"));
+        let wrong = SecretStore::open_with(&dir, Some("wrong")).unwrap();
+        assert_eq!(wrong.get(&id).unwrap_err().code, ErrorCode::SecretStore);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_damaged_or_replaced_machine_key_is_an_error() {
+        let dir = temp_dir();
+        let id = SecretStore::open_with(&dir, None).unwrap().insert("sk-machine").unwrap();
+        let key_file = dir.join(KEY_FILE);
+
+        fs::write(&key_file, "not hex").unwrap();
+        assert_eq!(SecretStore::open_with(&dir, None).err().unwrap().code, ErrorCode::SecretStore);
+        fs::write(&key_file, "abcd").unwrap();
+        assert_eq!(SecretStore::open_with(&dir, None).err().unwrap().code, ErrorCode::SecretStore);
+
+        fs::write(&key_file, to_hex(&[7u8; KEY_LEN])).unwrap();
+        let store = SecretStore::open_with(&dir, None).unwrap();
+        assert_eq!(store.get(&id).unwrap_err().code, ErrorCode::SecretStore);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn masks_keys() {
+        assert_eq!(mask(""), "••••••••");
+        assert_eq!(mask("sk-12345678"), "••••••••");
+        assert_eq!(mask("sk-123456789"), "sk-••••••••6789");
+        assert_eq!(mask("密钥密钥密钥密钥密钥密钥"), "密钥密••••••••密钥密钥");
+    }
+
+    #[test]
+    fn decodes_hex() {
+        assert_eq!(from_hex("00ff7A").unwrap(), [0x00, 0xff, 0x7a]);
+        assert_eq!(from_hex("").unwrap(), Vec::<u8>::new());
+        assert_eq!(from_hex(&to_hex(&[1, 2, 254])).unwrap(), [1, 2, 254]);
+        for bad in ["abc", "zz", "+1", "é0"] {
+            assert!(from_hex(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -7,6 +7,9 @@ use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
 use tauri::Manager;
 use tauri_plugin_shell::ShellExt;
 
+use crate::error::{self, ErrorCode, ZopError, ZopResult};
+use crate::sop_json;
+
 /// Default port for the Python agent
 const DEFAULT_AGENT_PORT: u16 = 19820;
 
@@ -40,6 +43,16 @@ pub struct AgentGeneratedSop {
     pub steps: Vec<AgentSopStep>,
 }
 
+/// An event of the agent's SOP stream
+#[derive(Debug, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AgentStreamEvent {
+    Title { title: String },
+    Step { step: AgentSopStep },
+    Done { sop: AgentGeneratedSop },
+    Error { message: String, status: Option<u16> },
+}
+
 /// Health check response
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HealthResponse {
@@ -84,7 +97,7 @@ impl SidecarState {
     }
 
     /// Wait for the agent to become healthy
-    pub async fn wait_for_healthy(&self, max_attempts: u32) -> Result<(), String> {
+    pub async fn wait_for_healthy(&self, max_attempts: u32) -> ZopResult<()> {
         for i in 0..max_attempts {
             if self.check_health().await {
                 return Ok(());
@@ -94,40 +107,83 @@ impl SidecarState {
                 eprintln!("Waiting for agent to start... attempt {}/{}", i + 1, max_attempts);
             }
         }
-        Err("Agent failed to start within timeout".to_string())
+        Err(ZopError::new(ErrorCode::Sidecar, "Agent failed to start within timeout"))
     }
 
-    /// Generate SOP using the Python agent
-    pub async fn generate_sop(
+    /// Generate SOP using the Python agent, calling `on_event` for the title
+    /// and every step as the agent streams them
+    pub async fn generate_sop_stream(
         &self,
         prompt: String,
         config: AgentAiConfig,
-    ) -> Result<AgentGeneratedSop, String> {
-        let url = format!("{}/generate-sop", self.get_base_url());
+        mut on_event: impl FnMut(AgentStreamEvent),
+    ) -> ZopResult<AgentGeneratedSop> {
+        let url = format!("{}/generate-sop/stream", self.get_base_url());
         let request = GenerateSopRequest { prompt, config };
 
-        let response = self
+        let mut response = self
             .http_client
             .post(&url)
             .json(&request)
             .send()
             .await
-            .map_err(|e| format!("Failed to send request to agent: {}", e))?;
+            .map_err(|e| ZopError::new(ErrorCode::Sidecar, format!("Failed to send request to agent: {}", e)))?;
 
-        if !response.status().is_success() {
+        if let Err(e) = response.error_for_status_ref() {
             let error_text = response.text().await.unwrap_or_default();
-            return Err(format!("Agent returned error: {}", error_text));
+            let mut error = error::ai_http_error(&e);
+            error.message = format!("Agent returned error: {}", error_text);
+            return Err(error);
         }
 
-        response
-            .json::<AgentGeneratedSop>()
-            .await
-            .map_err(|e| format!("Failed to parse agent response: {}", e))
+        // One JSON event per line; chunks may end mid-line
+        let mut pending: Vec<u8> = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| error::ai_http_error(&e))? {
+            pending.extend_from_slice(&chunk);
+            while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                let event = match serde_json::from_slice::<AgentStreamEvent>(&line) {
+                    Ok(event) => event,
+                    Err(e) => match sop_json::parse_step_event(&String::from_utf8_lossy(&line)) {
+                        Some(Ok(step)) => AgentStreamEvent::Step {
+                            step: AgentSopStep {
+                                step_type: step.step_type,
+                                label: step.label,
+                                content: step.content,
+                            },
+                        },
+                        // Steps only preview the SOP that `done` delivers, so one beyond repair is dropped
+                        Some(Err(invalid)) => {
+                            eprintln!("Skipping a step the agent sent: {}", invalid);
+                            continue;
+                        }
+                        None => {
+                            return Err(ZopError::new(
+                                ErrorCode::AiBadResponse,
+                                format!("Failed to parse agent response: {}", e),
+                            ))
+                        }
+                    },
+                };
+                match event {
+                    AgentStreamEvent::Done { sop } => return Ok(sop),
+                    AgentStreamEvent::Error { message, status } => {
+                        let code = status.map_or(ErrorCode::AiProvider, error::ai_status_code);
+                        return Err(ZopError::new(code, format!("Agent returned error: {}", message)));
+                    }
+                    event => on_event(event),
+                }
+            }
+        }
+        Err(ZopError::new(ErrorCode::Sidecar, "The agent closed the stream before finishing"))
     }
 }
 
 /// Spawn the Python agent sidecar
-pub async fn spawn_agent_sidecar(app: &tauri::AppHandle) -> Result<(), String> {
+pub async fn spawn_agent_sidecar(app: &tauri::AppHandle) -> ZopResult<()> {
     let sidecar_state = app.state::<SidecarState>();
 
     // Check if already running
@@ -144,13 +200,13 @@ pub async fn spawn_agent_sidecar(app: &tauri::AppHandle) -> Result<(), String> {
     let shell = app.shell();
     let sidecar_command = shell
         .sidecar("zop-agent")
-        .map_err(|e| format!("Failed to create sidecar command: {}", e))?
+        .map_err(|e| ZopError::new(ErrorCode::Sidecar, format!("Failed to create sidecar command: {}", e)))?
         .args([port.to_string()]);
 
     // Spawn the sidecar
     let (mut _rx, _child) = sidecar_command
         .spawn()
-        .map_err(|e| format!("Failed to spawn sidecar: {}", e))?;
+        .map_err(|e| ZopError::new(ErrorCode::Sidecar, format!("Failed to spawn sidecar: {}", e)))?;
 
     // Wait for the agent to become healthy
     sidecar_state.wait_for_healthy(20).await?;
@@ -0,0 +1,455 @@
+//! Reading a generated SOP out of a model's answer.
+//!
+//! Models don't reliably answer with bare JSON: they wrap it in ```json
+//! fences, add a sentence before or after, leave trailing commas, or spell
+//! step types as "Read" or "input". The answer's first JSON object is taken
+//! and repaired where the intent is clear: step types are normalized, and a
+//! missing or misplaced start or end step is put in place. What can't be
+//! repaired is reported as a list of problems the model can be asked to fix.
+
+use serde_json::{Map, Value};
+use std::fmt;
+
+use crate::{GeneratedSop, SopStep};
+
+pub const STEP_TYPES: [&str; 4] = ["start", "read", "form", "end"];
+
+/// Why an answer is not a usable SOP
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidSop {
+    pub problems: Vec<String>,
+}
+
+impl InvalidSop {
+    fn new(problem: impl Into<String>) -> Self {
+        Self {
+            problems: vec![problem.into()],
+        }
+    }
+
+    /// A follow-up message asking the model to correct its answer
+    pub fn reask_prompt(&self) -> String {
+        format!(
+            "Your previous answer could not be used: {}. Answer again with only the corrected JSON object.",
+            self
+        )
+    }
+}
+
+impl fmt::Display for InvalidSop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.problems.join("; "))
+    }
+}
+
+/// The SOP in a model's answer
+pub fn parse(answer: &str) -> Result<GeneratedSop, InvalidSop> {
+    repair(extract_object(answer)?)
+}
+
+/// A parsed SOP checked and repaired like an answer, e.g. one from the agent
+pub fn validate(sop: &GeneratedSop) -> Result<GeneratedSop, InvalidSop> {
+    repair(serde_json::to_value(sop).map_err(|e| InvalidSop::new(e.to_string()))?)
+}
+
+/// A step the agent streamed as `{"event": "step", "step": {...}}` that didn't
+/// deserialize as is, repaired like the steps of an answer. `None` when `line`
+/// is some other event, or not even a JSON object with trailing commas removed.
+pub fn parse_step_event(line: &str) -> Option<Result<SopStep, InvalidSop>> {
+    let event = first_object(line).ok()?;
+    if event.get("event").and_then(Value::as_str) != Some("step") {
+        return None;
+    }
+    Some(match event.get("step") {
+        Some(Value::Object(step)) => repair_step(step).map_err(InvalidSop::new),
+        _ => Err(InvalidSop::new("the step event has no \"step\" object")),
+    })
+}
+
+/// `raw` as one of `STEP_TYPES`, accepting any casing and common synonyms
+pub fn normalize_step_type(raw: &str) -> Option<&'static str> {
+    let key: String = raw
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+    let key = key.strip_suffix("step").filter(|k| !k.is_empty()).unwrap_or(&key);
+    match key {
+        "start" | "begin" | "beginning" | "initial" | "trigger" => Some("start"),
+        "read" | "info" | "information" | "instruction" | "instructions" | "action" | "task" | "process" | "note" => {
+            Some("read")
+        }
+        "form" | "input" | "userinput" | "question" | "dataentry" | "entry" | "field" => Some("form"),
+        "end" | "finish" | "finished" | "stop" | "done" | "complete" | "completed" | "exit" => Some("end"),
+        _ => None,
+    }
+}
+
+/// The first JSON object in `answer`, looking inside a code fence first
+fn extract_object(answer: &str) -> Result<Value, InvalidSop> {
+    match fenced(answer).map(first_object) {
+        Some(Ok(value)) => Ok(value),
+        _ => first_object(answer),
+    }
+}
+
+/// The body of the first ``` fence; an unclosed fence runs to the end, as when the answer was cut off
+fn fenced(answer: &str) -> Option<&str> {
+    let open = answer.find("```")?;
+    let after = &answer[open + 3..];
+    // Skip the language tag
+    let body = &after[after.find('\n').map_or(after.len(), |i| i + 1)..];
+    Some(body.find("```").map_or(body, |close| &body[..close]))
+}
+
+/// The first balanced `{...}` in `text` that parses as a JSON object. A brace
+/// that is never closed means the answer was cut off; the objects inside it
+/// are only parts of the SOP.
+fn first_object(text: &str) -> Result<Value, InvalidSop> {
+    let mut from = 0;
+    while let Some(offset) = text[from..].find('{') {
+        let start = from + offset;
+        let Some(end) = balanced_end(text, start) else {
+            return Err(InvalidSop::new("the answer ends before its JSON object is complete"));
+        };
+        let candidate = &text[start..=end];
+        let parsed = serde_json::from_str::<Value>(candidate)
+            .or_else(|_| serde_json::from_str::<Value>(&without_trailing_commas(candidate)));
+        if let Ok(value @ Value::Object(_)) = parsed {
+            return Ok(value);
+        }
+        from = start + 1;
+    }
+    Err(InvalidSop::new("the answer contains no JSON object"))
+}
+
+/// Index of the brace closing the one at `start`, skipping over strings
+fn balanced_end(text: &str, start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, byte) in text.bytes().enumerate().skip(start) {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `json` without commas right before a closing brace or bracket
+fn without_trailing_commas(json: &str) -> String {
+    let mut out = String::with_capacity(json.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in json.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            let next = json[i + 1..].trim_start().chars().next();
+            if matches!(next, Some('}') | Some(']')) {
+                continue;
+            }
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn repair(value: Value) -> Result<GeneratedSop, InvalidSop> {
+    let Value::Object(mut object) = value else {
+        return Err(InvalidSop::new("the answer must be a JSON object"));
+    };
+    // e.g. {"sop": {"title": ..., "steps": [...]}}
+    if !object.contains_key("steps") && object.len() == 1 {
+        if let Some(Value::Object(inner)) = object.values().next() {
+            object = inner.clone();
+        }
+    }
+
+    let mut problems = Vec::new();
+    let title = text_field(&object, &["title", "name"]);
+    if title.is_none() {
+        problems.push("\"title\" must be a non-empty string".to_string());
+    }
+
+    let mut steps = Vec::new();
+    match object.get("steps") {
+        Some(Value::Array(items)) => {
+            for (i, item) in items.iter().enumerate() {
+                match item {
+                    Value::Object(step) => match repair_step(step) {
+                        Ok(step) => steps.push(step),
+                        Err(problem) => problems.push(format!("steps[{}]: {}", i, problem)),
+                    },
+                    _ => problems.push(format!("steps[{}] must be an object", i)),
+                }
+            }
+        }
+        _ => problems.push("\"steps\" must be an array".to_string()),
+    }
+    if problems.is_empty() {
+        if let Err(problem) = place_start_and_end(&mut steps) {
+            problems.push(problem);
+        }
+    }
+
+    match title {
+        Some(title) if problems.is_empty() => Ok(GeneratedSop { title, steps }),
+        _ => Err(InvalidSop { problems }),
+    }
+}
+
+fn repair_step(step: &Map<String, Value>) -> Result<SopStep, String> {
+    let raw_type = text_field(step, &["step_type", "type", "kind"]).ok_or("\"step_type\" is missing")?;
+    let step_type = normalize_step_type(&raw_type)
+        .ok_or_else(|| format!("\"step_type\" is \"{}\" but must be one of {}", raw_type, STEP_TYPES.join(", ")))?;
+    let label = text_field(step, &["label", "name", "title"]).ok_or("\"label\" must be a non-empty string")?;
+    let content = match step.get("content").or_else(|| step.get("description")) {
+        Some(Value::String(text)) => Some(text.trim().to_string()).filter(|t| !t.is_empty()),
+        Some(Value::Null) | None => None,
+        Some(other) => Some(other.to_string()),
+    };
+    Ok(SopStep {
+        step_type: step_type.to_string(),
+        label,
+        content,
+    })
+}
+
+/// The first of `keys` holding a non-empty string, trimmed
+fn text_field(object: &Map<String, Value>, keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .filter_map(|key| object.get(*key)?.as_str())
+        .map(str::trim)
+        .find(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
+/// Make the steps begin with the only start step and finish with the only end
+/// step, adding them when missing
+fn place_start_and_end(steps: &mut Vec<SopStep>) -> Result<(), String> {
+    for (kind, label) in [("start", "Start"), ("end", "End")] {
+        let positions: Vec<usize> = steps
+            .iter()
+            .enumerate()
+            .filter(|(_, step)| step.step_type == kind)
+            .map(|(i, _)| i)
+            .collect();
+        let step = match positions.as_slice() {
+            [] => SopStep {
+                step_type: kind.to_string(),
+                label: label.to_string(),
+                content: None,
+            },
+            [i] => steps.remove(*i),
+            _ => return Err(format!("there must be exactly one {} step, not {}", kind, positions.len())),
+        };
+        if kind == "start" {
+            steps.insert(0, step);
+        } else {
+            steps.push(step);
+        }
+    }
+    if steps.len() < 3 {
+        return Err("there must be at least one read or form step between start and end".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step_types(sop: &GeneratedSop) -> Vec<&str> {
+        sop.steps.iter().map(|s| s.step_type.as_str()).collect()
+    }
+
+    /// Recorded from a model that fences its JSON and explains it
+    const FENCED_WITH_PROSE: &str = r#"Sure! Here is the SOP you asked for:
+
+```json
+{
+  "title": "Customer Support Ticket Handling",
+  "steps": [
+    {"step_type": "start", "label": "Start", "content": null},
+    {"step_type": "read", "label": "Triage Ticket", "content": "Read the ticket and set its priority."},
+    {"step_type": "form", "label": "Assign Agent", "content": "Pick the agent who handles it."},
+    {"step_type": "end", "label": "End", "content": null}
+  ]
+}
+```
+
+Let me know if you want me to add escalation steps!"#;
+
+    /// Recorded from a model that capitalizes and paraphrases step types
+    const ODD_STEP_TYPES: &str = r#"{
+  "title": "Onboard a New Hire",
+  "steps": [
+    {"step_type": "Start", "label": "Start", "content": null},
+    {"step_type": "Read", "label": "Welcome Pack", "content": "Send the welcome pack."},
+    {"step_type": "USER_INPUT", "label": "Collect Details", "content": "Bank account and address"},
+    {"step_type": "instruction", "label": "Set Up Laptop", "content": "Install the standard image."},
+    {"step_type": "End-Step", "label": "Done", "content": ""}
+  ]
+}"#;
+
+    /// Recorded from a small local model: no fence, trailing commas, no start
+    /// or end step, and a wrapper object
+    const TRAILING_COMMAS_NO_ENDS: &str = r#"Output:
+{"sop": {
+  "title": "Weekly Backup Check",
+  "steps": [
+    {"type": "action", "name": "Open Dashboard", "description": "Log in to the backup console.",},
+    {"type": "form", "name": "Record Result", "description": "Did last night's job succeed?",},
+  ],
+}}"#;
+
+    /// Recorded from a model that put the end step first and started twice
+    const MISPLACED_ENDS: &str = r#"{"title": "Close the Shop", "steps": [
+  {"step_type": "end", "label": "Finish", "content": null},
+  {"step_type": "start", "label": "Begin", "content": null},
+  {"step_type": "read", "label": "Lock Doors", "content": "Lock the front and back doors."}
+]}"#;
+
+    /// Recorded from a model that was cut off mid-answer
+    const TRUNCATED: &str = r#"```json
+{
+  "title": "Incident Response",
+  "steps": [
+    {"step_type": "start", "label": "Start", "content": null},
+    {"step_type": "read", "label": "Assess Impact", "content": "Determine which"#;
+
+    #[test]
+    fn extracts_fenced_json_with_prose() {
+        let sop = parse(FENCED_WITH_PROSE).unwrap();
+        assert_eq!(sop.title, "Customer Support Ticket Handling");
+        assert_eq!(step_types(&sop), vec!["start", "read", "form", "end"]);
+        assert_eq!(sop.steps[0].content, None);
+    }
+
+    #[test]
+    fn normalizes_step_types() {
+        let sop = parse(ODD_STEP_TYPES).unwrap();
+        assert_eq!(step_types(&sop), vec!["start", "read", "form", "read", "end"]);
+        // Empty content means none
+        assert_eq!(sop.steps[4].content, None);
+        assert_eq!(sop.steps[4].label, "Done");
+    }
+
+    #[test]
+    fn repairs_trailing_commas_and_missing_ends() {
+        let sop = parse(TRAILING_COMMAS_NO_ENDS).unwrap();
+        assert_eq!(sop.title, "Weekly Backup Check");
+        assert_eq!(step_types(&sop), vec!["start", "read", "form", "end"]);
+        assert_eq!(sop.steps[1].label, "Open Dashboard");
+        assert_eq!(sop.steps[1].content.as_deref(), Some("Log in to the backup console."));
+        assert_eq!(sop.steps[0].label, "Start");
+    }
+
+    #[test]
+    fn moves_misplaced_ends() {
+        let sop = parse(MISPLACED_ENDS).unwrap();
+        let labels: Vec<&str> = sop.steps.iter().map(|s| s.label.as_str()).collect();
+        assert_eq!(labels, vec!["Begin", "Lock Doors", "Finish"]);
+    }
+
+    #[test]
+    fn reports_what_cannot_be_repaired() {
+        assert_eq!(
+            parse(TRUNCATED).unwrap_err().problems,
+            vec!["the answer ends before its JSON object is complete"]
+        );
+        assert_eq!(
+            parse("I'm sorry, I can't help with that.").unwrap_err().problems,
+            vec!["the answer contains no JSON object"]
+        );
+
+        let invalid = parse(
+            r#"{"steps": [
+                {"step_type": "decision", "label": "Approve?"},
+                {"step_type": "read", "label": "  "},
+                "Call the customer"
+            ]}"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            invalid.problems,
+            vec![
+                "\"title\" must be a non-empty string",
+                "steps[0]: \"step_type\" is \"decision\" but must be one of start, read, form, end",
+                "steps[1]: \"label\" must be a non-empty string",
+                "steps[2] must be an object",
+            ]
+        );
+        assert!(invalid.reask_prompt().contains("steps[2] must be an object"));
+
+        let twice = r#"{"title": "T", "steps": [
+            {"step_type": "start", "label": "A"}, {"step_type": "end", "label": "B"},
+            {"step_type": "read", "label": "C"}, {"step_type": "end", "label": "D"}]}"#;
+        assert_eq!(parse(twice).unwrap_err().problems, vec!["there must be exactly one end step, not 2"]);
+
+        let empty = r#"{"title": "T", "steps": [{"step_type": "start", "label": "A"}]}"#;
+        assert_eq!(
+            parse(empty).unwrap_err().problems,
+            vec!["there must be at least one read or form step between start and end"]
+        );
+    }
+
+    #[test]
+    fn skips_braces_in_prose_and_strings() {
+        let answer = r#"Using {placeholders} as asked: {"title": "Use {curly} \"quotes\"", "steps": [{"step_type": "read", "label": "Say }"}]}"#;
+        let sop = parse(answer).unwrap();
+        assert_eq!(sop.title, "Use {curly} \"quotes\"");
+        assert_eq!(sop.steps[1].label, "Say }");
+        // Commas inside strings stay
+        assert_eq!(without_trailing_commas(r#"{"a": "x,}", "b": [1,],}"#), r#"{"a": "x,}", "b": [1]}"#);
+    }
+
+    #[test]
+    fn repairs_streamed_step_events() {
+        let line = r#"{"event": "step", "step": {"type": "Instruction", "name": " Check logs ", "content": "Last hour",},}"#;
+        let step = parse_step_event(line).unwrap().unwrap();
+        assert_eq!((step.step_type.as_str(), step.label.as_str()), ("read", "Check logs"));
+        assert_eq!(step.content.as_deref(), Some("Last hour"));
+
+        let invalid = parse_step_event(r#"{"event": "step", "step": {"step_type": "wait", "label": "Pause"}}"#);
+        assert!(invalid.unwrap().unwrap_err().to_string().contains("\"wait\""));
+        assert!(parse_step_event(r#"{"event": "step", "step": "read"}"#).unwrap().is_err());
+        // Other events and unreadable lines are not steps
+        assert!(parse_step_event(r#"{"event": "title", "title": "x"}"#).is_none());
+        assert!(parse_step_event(r#"{"event": "step", "step": {"#).is_none());
+    }
+
+    #[test]
+    fn step_type_synonyms() {
+        assert_eq!(normalize_step_type(" READ "), Some("read"));
+        assert_eq!(normalize_step_type("read_step"), Some("read"));
+        assert_eq!(normalize_step_type("Data Entry"), Some("form"));
+        assert_eq!(normalize_step_type("step"), None);
+        assert_eq!(normalize_step_type(""), None);
+    }
+}
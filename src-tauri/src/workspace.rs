@@ -0,0 +1,354 @@
+//! Workspaces: independent SOP libraries, each with its own SQLite file.
+//!
+//! The data directory is taken from `--data-dir <path>`, then `ZOP_DATA_DIR`,
+//! then `~/.zop`. It holds `workspaces.json`, the registry of known workspaces
+//! and which one is active, and `zop.db`, the `default` workspace. A workspace
+//! may live anywhere, e.g. in a folder shared with a team.
+
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{ErrorCode, ZopError, ZopResult};
+
+pub const DEFAULT_WORKSPACE_ID: &str = "default";
+
+const DATA_DIR_ENV: &str = "ZOP_DATA_DIR";
+const DATA_DIR_ARG: &str = "--data-dir";
+const REGISTRY_FILE: &str = "workspaces.json";
+const DB_FILE: &str = "zop.db";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Workspace {
+    pub id: String,
+    pub name: String,
+    /// SQLite file holding the workspace's SOPs
+    pub path: PathBuf,
+    pub created_at: String,
+    pub last_opened_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct WorkspaceList {
+    pub active_id: String,
+    pub workspaces: Vec<Workspace>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct RegistryFile {
+    active: Option<String>,
+    workspaces: Vec<Workspace>,
+}
+
+/// The registry of workspaces, stored in the data directory
+pub struct WorkspaceRegistry {
+    dir: PathBuf,
+    file: RegistryFile,
+}
+
+/// Resolve the data directory and make sure it exists
+pub fn data_dir() -> ZopResult<PathBuf> {
+    let dir = resolve_data_dir(std::env::args(), std::env::var_os(DATA_DIR_ENV), dirs::home_dir())?;
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// The data directory named by the command line, else by `env` (the value of
+/// `ZOP_DATA_DIR`), else `.zop` in `home`
+fn resolve_data_dir(
+    args: impl Iterator<Item = String>,
+    env: Option<OsString>,
+    home: Option<PathBuf>,
+) -> ZopResult<PathBuf> {
+    if let Some(dir) = data_dir_arg(args) {
+        return Ok(dir);
+    }
+    if let Some(dir) = env.filter(|v| !v.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+    let home = home.ok_or_else(|| {
+        ZopError::new(
+            ErrorCode::Io,
+            format!("Could not find home directory; set {} to choose a data directory", DATA_DIR_ENV),
+        )
+    })?;
+    Ok(home.join(".zop"))
+}
+
+/// `--data-dir <path>` or `--data-dir=<path>`
+fn data_dir_arg(args: impl Iterator<Item = String>) -> Option<PathBuf> {
+    let mut args = args.skip(1);
+    while let Some(arg) = args.next() {
+        if arg == DATA_DIR_ARG {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(dir) = arg.strip_prefix(DATA_DIR_ARG).and_then(|rest| rest.strip_prefix('=')) {
+            return Some(PathBuf::from(dir));
+        }
+    }
+    None
+}
+
+impl WorkspaceRegistry {
+    /// Read the registry in `dir`, registering the default workspace if it is missing
+    pub fn load(dir: &Path) -> ZopResult<Self> {
+        let registry_path = dir.join(REGISTRY_FILE);
+        let file = if registry_path.exists() {
+            serde_json::from_str(&fs::read_to_string(&registry_path)?)?
+        } else {
+            RegistryFile::default()
+        };
+
+        let mut registry = Self {
+            dir: dir.to_path_buf(),
+            file,
+        };
+        if registry.get(DEFAULT_WORKSPACE_ID).is_none() {
+            registry.file.workspaces.insert(
+                0,
+                Workspace {
+                    id: DEFAULT_WORKSPACE_ID.to_string(),
+                    name: "Default".to_string(),
+                    path: dir.join(DB_FILE),
+                    created_at: chrono::Utc::now().to_rfc3339(),
+                    last_opened_at: None,
+                },
+            );
+        }
+        Ok(registry)
+    }
+
+    pub fn list(&self) -> WorkspaceList {
+        WorkspaceList {
+            active_id: self.active().id.clone(),
+            workspaces: self.file.workspaces.clone(),
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Workspace> {
+        self.file.workspaces.iter().find(|w| w.id == id)
+    }
+
+    /// The active workspace, or the default one if the registry names none
+    pub fn active(&self) -> &Workspace {
+        self.file
+            .active
+            .as_deref()
+            .and_then(|id| self.get(id))
+            .or_else(|| self.get(DEFAULT_WORKSPACE_ID))
+            .expect("default workspace is always registered")
+    }
+
+    /// Register a new workspace. `location` is a directory (the database is
+    /// `zop.db` inside it) or a `.db` file, which may already hold SOPs;
+    /// without one the workspace is created under the data directory.
+    pub fn create(&mut self, name: &str, location: Option<&Path>) -> ZopResult<Workspace> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ZopError::validation("Workspace name must not be empty"));
+        }
+
+        let id = self.unique_id(name);
+        let path = match location {
+            Some(location) if location.extension().is_some_and(|ext| ext == "db") => location.to_path_buf(),
+            Some(location) => location.join(DB_FILE),
+            None => self.dir.join("workspaces").join(&id).join(DB_FILE),
+        };
+        if let Some(existing) = self.file.workspaces.iter().find(|w| w.path == path) {
+            return Err(ZopError::new(
+                ErrorCode::Conflict,
+                format!("{} is already registered as workspace {}", path.display(), existing.name),
+            ));
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let workspace = Workspace {
+            id,
+            name: name.to_string(),
+            path,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            last_opened_at: None,
+        };
+        self.file.workspaces.push(workspace.clone());
+        self.save()?;
+        Ok(workspace)
+    }
+
+    /// Make `id` the workspace opened on startup
+    pub fn set_active(&mut self, id: &str) -> ZopResult<Workspace> {
+        let workspace = self
+            .file
+            .workspaces
+            .iter_mut()
+            .find(|w| w.id == id)
+            .ok_or_else(|| ZopError::not_found("Workspace", id))?;
+        workspace.last_opened_at = Some(chrono::Utc::now().to_rfc3339());
+        let workspace = workspace.clone();
+
+        self.file.active = Some(workspace.id.clone());
+        self.save()?;
+        Ok(workspace)
+    }
+
+    /// Forget a workspace. Its database file is left in place.
+    pub fn remove(&mut self, id: &str) -> ZopResult<Workspace> {
+        if id == DEFAULT_WORKSPACE_ID {
+            return Err(ZopError::invalid_state("The default workspace cannot be closed"));
+        }
+        let index = self
+            .file
+            .workspaces
+            .iter()
+            .position(|w| w.id == id)
+            .ok_or_else(|| ZopError::not_found("Workspace", id))?;
+
+        let workspace = self.file.workspaces.remove(index);
+        if self.file.active.as_deref() == Some(id) {
+            self.file.active = None;
+        }
+        self.save()?;
+        Ok(workspace)
+    }
+
+    /// A slug of `name` not used by another workspace
+    fn unique_id(&self, name: &str) -> String {
+        let slug = name
+            .to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        let base = if slug.is_empty() { "workspace".to_string() } else { slug };
+
+        let mut id = base.clone();
+        let mut n = 2;
+        while self.get(&id).is_some() {
+            id = format!("{}-{}", base, n);
+            n += 1;
+        }
+        id
+    }
+
+    /// Write the registry through a temporary file so a crash can't leave it half-written
+    fn save(&self) -> ZopResult<()> {
+        let path = self.dir.join(REGISTRY_FILE);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&self.file)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A fresh data directory for one test
+    fn temp_dir() -> PathBuf {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("zop-workspaces-{}-{}", std::process::id(), n));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn args(list: &[&str]) -> impl Iterator<Item = String> {
+        let list: Vec<String> = std::iter::once("zop").chain(list.iter().copied()).map(String::from).collect();
+        list.into_iter()
+    }
+
+    #[test]
+    fn data_dir_prefers_the_argument_then_the_environment_then_home() {
+        let env = || Some(OsString::from("/env"));
+        let home = || Some(PathBuf::from("/home/ada"));
+        let resolve = |list: &[&str], env: Option<OsString>| resolve_data_dir(args(list), env, home()).unwrap();
+
+        assert_eq!(resolve(&["--data-dir", "/arg"], env()), PathBuf::from("/arg"));
+        assert_eq!(resolve(&[], env()), PathBuf::from("/env"));
+        assert_eq!(resolve(&[], Some(OsString::new())), PathBuf::from("/home/ada/.zop"));
+        assert_eq!(resolve(&[], None), PathBuf::from("/home/ada/.zop"));
+        assert!(resolve_data_dir(args(&[]), None, None).is_err());
+    }
+
+    #[test]
+    fn reads_both_forms_of_the_argument() {
+        assert_eq!(data_dir_arg(args(&["--data-dir=/a b"])), Some(PathBuf::from("/a b")));
+        assert_eq!(data_dir_arg(args(&["-v", "--data-dir", "/c"])), Some(PathBuf::from("/c")));
+        // The first one wins, and a flag without a value names nothing
+        assert_eq!(data_dir_arg(args(&["--data-dir=/a", "--data-dir=/b"])), Some(PathBuf::from("/a")));
+        assert_eq!(data_dir_arg(args(&["--data-dir"])), None);
+        assert_eq!(data_dir_arg(args(&["--data-directory=/x", "/y"])), None);
+        // The program name is never taken as an argument
+        assert_eq!(data_dir_arg(["--data-dir=/x".to_string()].into_iter()), None);
+    }
+
+    #[test]
+    fn ids_are_unique_slugs_of_the_name() {
+        let dir = temp_dir();
+        let mut registry = WorkspaceRegistry::load(&dir).unwrap();
+        let ids: Vec<String> = ["Team Ops", "team-ops", "Team  OPS!", "Default", "!!!"]
+            .iter()
+            .map(|name| registry.create(name, None).unwrap().id)
+            .collect();
+        assert_eq!(ids, ["team-ops", "team-ops-2", "team-ops-3", "default-2", "workspace"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_a_path_that_is_already_registered() {
+        let dir = temp_dir();
+        let mut registry = WorkspaceRegistry::load(&dir).unwrap();
+        let shared = dir.join("shared");
+        let first = registry.create("Shared", Some(&shared)).unwrap();
+        assert_eq!(first.path, shared.join(DB_FILE));
+
+        let error = registry.create("Again", Some(&shared.join(DB_FILE))).unwrap_err();
+        assert_eq!(error.code, ErrorCode::Conflict);
+        let error = registry.create("Data dir", Some(&dir)).unwrap_err();
+        assert_eq!(error.code, ErrorCode::Conflict, "the default workspace's folder");
+        assert!(registry.create(" ", None).is_err());
+        assert_eq!(registry.list().workspaces.len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_the_default_workspace() {
+        let dir = temp_dir();
+        let mut registry = WorkspaceRegistry::load(&dir).unwrap();
+        let other = registry.create("Other", None).unwrap();
+        registry.set_active(&other.id).unwrap();
+
+        let error = registry.remove(DEFAULT_WORKSPACE_ID).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidState);
+        assert_eq!(registry.remove("missing").unwrap_err().code, ErrorCode::NotFound);
+
+        // Removing the active workspace falls back to the default one
+        registry.remove(&other.id).unwrap();
+        assert_eq!(registry.active().id, DEFAULT_WORKSPACE_ID);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn saves_the_registry_through_a_temporary_file() {
+        let dir = temp_dir();
+        let registry_path = dir.join(REGISTRY_FILE);
+        // Left over from a save that crashed before its rename
+        fs::write(registry_path.with_extension("json.tmp"), "{ half").unwrap();
+
+        let mut registry = WorkspaceRegistry::load(&dir).unwrap();
+        let other = registry.create("Other", None).unwrap();
+        registry.set_active(&other.id).unwrap();
+
+        assert!(!registry_path.with_extension("json.tmp").exists());
+        let reloaded = WorkspaceRegistry::load(&dir).unwrap();
+        assert_eq!(reloaded.active().id, other.id);
+        let ids: Vec<String> = reloaded.list().workspaces.into_iter().map(|w| w.id).collect();
+        assert_eq!(ids, [DEFAULT_WORKSPACE_ID, "other"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
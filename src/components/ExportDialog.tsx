@@ -0,0 +1,151 @@
+import { useState } from "react";
+import { useTranslation } from "react-i18next";
+import { invoke } from "@tauri-apps/api/core";
+import { revealItemInDir } from "@tauri-apps/plugin-opener";
+import {
+  Dialog,
+  DialogContent,
+  DialogDescription,
+  DialogFooter,
+  DialogHeader,
+  DialogTitle,
+} from "@/components/ui/dialog";
+import { Button } from "@/components/ui/button";
+import { Input } from "@/components/ui/input";
+import { errorMessage } from "@/lib/errors";
+
+interface ExportFormat {
+  value: string;
+  // Command writing the file; each takes `id` and `path` and returns the path written
+  command: string;
+  // SOP types the format can represent
+  itemTypes: string[];
+}
+
+const FORMATS: ExportFormat[] = [
+  { value: "bundle", command: "export_sop", itemTypes: ["todo", "flowchart"] },
+  { value: "markdown", command: "export_sop_markdown", itemTypes: ["todo"] },
+  { value: "mermaid", command: "export_sop_mermaid", itemTypes: ["flowchart"] },
+  { value: "dot", command: "export_sop_dot", itemTypes: ["flowchart"] },
+  { value: "bpmn", command: "export_sop_bpmn", itemTypes: ["flowchart"] },
+  { value: "html", command: "export_sop_html", itemTypes: ["todo", "flowchart"] },
+];
+
+interface ExportDialogProps {
+  sop: { id: number; name: string; item_type: string } | null;
+  onOpenChange: (open: boolean) => void;
+}
+
+export default function ExportDialog({ sop, onOpenChange }: ExportDialogProps) {
+  const { t } = useTranslation();
+  const [format, setFormat] = useState("bundle");
+  const [path, setPath] = useState("");
+  const [includeRevisions, setIncludeRevisions] = useState(false);
+  const [isExporting, setIsExporting] = useState(false);
+  const [exportedTo, setExportedTo] = useState<string | null>(null);
+  const [error, setError] = useState<string | null>(null);
+
+  const formats = FORMATS.filter((f) => sop && f.itemTypes.includes(sop.item_type));
+  const selected = formats.find((f) => f.value === format) ?? formats[0];
+
+  const handleOpenChange = (open: boolean) => {
+    if (!open) {
+      setExportedTo(null);
+      setError(null);
+      setPath("");
+    }
+    onOpenChange(open);
+  };
+
+  const handleExport = async () => {
+    if (!sop || !selected) return;
+    setIsExporting(true);
+    setError(null);
+    try {
+      const written = await invoke<string>(selected.command, {
+        id: sop.id,
+        path: path.trim() || null,
+        includeRevisions,
+      });
+      setExportedTo(written);
+    } catch (e) {
+      setError(errorMessage(e, t));
+    } finally {
+      setIsExporting(false);
+    }
+  };
+
+  return (
+    <Dialog open={sop !== null} onOpenChange={handleOpenChange}>
+      <DialogContent>
+        <DialogHeader>
+          <DialogTitle>{t('export.title', { name: sop?.name })}</DialogTitle>
+          <DialogDescription>{t('export.description')}</DialogDescription>
+        </DialogHeader>
+
+        {exportedTo ? (
+          <div className="space-y-1">
+            <p className="text-sm text-foreground">{t('export.done')}</p>
+            <p className="text-xs text-muted-foreground break-all select-text">{exportedTo}</p>
+          </div>
+        ) : (
+          <div className="space-y-4">
+            <div className="space-y-1">
+              <label className="text-sm font-medium text-foreground">{t('export.format')}</label>
+              <select
+                value={selected?.value}
+                onChange={(e) => setFormat(e.target.value)}
+                className="w-full h-9 px-3 rounded-md bg-background text-foreground border border-input text-sm"
+              >
+                {formats.map((f) => (
+                  <option key={f.value} value={f.value}>
+                    {t(`export.formats.${f.value}`)}
+                  </option>
+                ))}
+              </select>
+            </div>
+            {selected?.value === "bundle" && sop?.item_type === "flowchart" && (
+              <label className="flex items-center gap-2 text-sm text-foreground">
+                <input
+                  type="checkbox"
+                  checked={includeRevisions}
+                  onChange={(e) => setIncludeRevisions(e.target.checked)}
+                />
+                {t('export.includeRevisions')}
+              </label>
+            )}
+            <div className="space-y-1">
+              <label className="text-sm font-medium text-foreground">{t('export.path')}</label>
+              <Input
+                value={path}
+                onChange={(e) => setPath(e.target.value)}
+                placeholder={t('export.pathPlaceholder')}
+              />
+            </div>
+            {error && <p className="text-sm text-destructive">{error}</p>}
+          </div>
+        )}
+
+        <DialogFooter>
+          {exportedTo ? (
+            <>
+              <Button variant="outline" onClick={() => revealItemInDir(exportedTo)}>
+                {t('export.showInFolder')}
+              </Button>
+              <Button onClick={() => handleOpenChange(false)}>{t('export.close')}</Button>
+            </>
+          ) : (
+            <>
+              <Button variant="outline" onClick={() => handleOpenChange(false)}>
+                {t('common.cancel')}
+              </Button>
+              <Button onClick={handleExport} disabled={isExporting || !selected}>
+                {isExporting ? t('export.exporting') : t('export.export')}
+              </Button>
+            </>
+          )}
+        </DialogFooter>
+      </DialogContent>
+    </Dialog>
+  );
+}
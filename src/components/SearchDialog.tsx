@@ -0,0 +1,137 @@
+import { Fragment, useEffect, useState } from "react";
+import { useNavigate } from "react-router-dom";
+import { useTranslation } from "react-i18next";
+import { invoke } from "@tauri-apps/api/core";
+import { CheckSquare, FileText, GitBranch } from "lucide-react";
+import {
+  Dialog,
+  DialogContent,
+  DialogDescription,
+  DialogHeader,
+  DialogTitle,
+} from "@/components/ui/dialog";
+import {
+  Command,
+  CommandEmpty,
+  CommandGroup,
+  CommandInput,
+  CommandItem,
+  CommandList,
+} from "@/components/ui/command";
+
+// Mirrors `SearchHit` in src-tauri/src/lib.rs
+interface SearchHit {
+  sop_id: number;
+  sop_name: string;
+  item_type: string;
+  kind: "sop" | "todo" | "node";
+  todo_id: number | null;
+  node_id: string | null;
+  snippet: string;
+  score: number;
+}
+
+const KIND_ICON = {
+  sop: FileText,
+  todo: CheckSquare,
+  node: GitBranch,
+};
+
+// Render the backend's <mark> markers as elements, never as HTML
+function Snippet({ text }: { text: string }) {
+  const parts = text.split(/<mark>(.*?)<\/mark>/g);
+  return (
+    <>
+      {parts.map((part, index) =>
+        index % 2 === 1 ? (
+          <mark key={index} className="bg-yellow-200 dark:bg-yellow-700 text-inherit rounded-sm">
+            {part}
+          </mark>
+        ) : (
+          <Fragment key={index}>{part}</Fragment>
+        )
+      )}
+    </>
+  );
+}
+
+interface SearchDialogProps {
+  open: boolean;
+  onOpenChange: (open: boolean) => void;
+}
+
+export default function SearchDialog({ open, onOpenChange }: SearchDialogProps) {
+  const { t } = useTranslation();
+  const navigate = useNavigate();
+  const [query, setQuery] = useState("");
+  const [hits, setHits] = useState<SearchHit[]>([]);
+
+  useEffect(() => {
+    if (!query.trim()) {
+      setHits([]);
+      return;
+    }
+    // Debounce so typing doesn't query on every keystroke
+    const timeout = setTimeout(async () => {
+      try {
+        setHits(await invoke<SearchHit[]>("search", { query }));
+      } catch (error) {
+        console.error("Search failed:", error);
+      }
+    }, 150);
+    return () => clearTimeout(timeout);
+  }, [query]);
+
+  const openHit = (hit: SearchHit) => {
+    if (hit.item_type === "todo") {
+      navigate(`/todo/${hit.sop_id}`);
+    } else {
+      navigate(hit.node_id ? `/flow/${hit.sop_id}?node=${encodeURIComponent(hit.node_id)}` : `/flow/${hit.sop_id}`);
+    }
+    onOpenChange(false);
+    setQuery("");
+  };
+
+  return (
+    <Dialog open={open} onOpenChange={onOpenChange}>
+      <DialogHeader className="sr-only">
+        <DialogTitle>{t('search.title')}</DialogTitle>
+        <DialogDescription>{t('search.description')}</DialogDescription>
+      </DialogHeader>
+      <DialogContent className="overflow-hidden p-0">
+        {/* Results come ranked from the backend, so cmdk must not filter or reorder them */}
+        <Command shouldFilter={false}>
+          <CommandInput value={query} onValueChange={setQuery} placeholder={t('search.placeholder')} />
+          <CommandList className="max-h-[400px]">
+            {query.trim() && <CommandEmpty>{t('search.noResults')}</CommandEmpty>}
+            {hits.length > 0 && (
+              <CommandGroup>
+                {hits.map((hit) => {
+                  const Icon = KIND_ICON[hit.kind];
+                  return (
+                    <CommandItem
+                      key={`${hit.kind}-${hit.sop_id}-${hit.todo_id ?? hit.node_id ?? ""}`}
+                      value={`${hit.kind}-${hit.sop_id}-${hit.todo_id ?? hit.node_id ?? ""}`}
+                      onSelect={() => openHit(hit)}
+                      className="items-start"
+                    >
+                      <Icon className="w-4 h-4 mt-0.5" />
+                      <div className="min-w-0">
+                        <div className="text-sm truncate">
+                          <Snippet text={hit.snippet} />
+                        </div>
+                        {hit.kind !== "sop" && (
+                          <div className="text-xs text-muted-foreground truncate">{hit.sop_name}</div>
+                        )}
+                      </div>
+                    </CommandItem>
+                  );
+                })}
+              </CommandGroup>
+            )}
+          </CommandList>
+        </Command>
+      </DialogContent>
+    </Dialog>
+  );
+}
@@ -26,6 +26,11 @@ import {
   Pencil,
   Trash2,
   Sparkles,
+  ChevronRight,
+  FolderPlus,
+  FolderInput,
+  Tag as TagIcon,
+  Download,
   type LucideIcon,
 } from "lucide-react";
 import {
@@ -44,22 +49,43 @@ import {
 } from "@/components/ui/sidebar";
 import {
   DropdownMenu,
+  DropdownMenuCheckboxItem,
   DropdownMenuContent,
   DropdownMenuItem,
+  DropdownMenuSeparator,
+  DropdownMenuSub,
+  DropdownMenuSubContent,
+  DropdownMenuSubTrigger,
   DropdownMenuTrigger,
 } from "@/components/ui/dropdown-menu";
 import { Input } from "@/components/ui/input";
 import UserInfo from "./UserInfo";
+import SearchDialog from "./SearchDialog";
+import ExportDialog from "./ExportDialog";
 
 interface SopItem {
   id: number;
   name: string;
   icon: string;
   item_type: string;
+  folder_id: number | null;
+  tag_ids: number[];
   created_at: string;
   updated_at: string;
 }
 
+interface SopFolder {
+  id: number;
+  name: string;
+  parent_id: number | null;
+}
+
+interface Tag {
+  id: number;
+  name: string;
+  color: string | null;
+}
+
 const ICON_MAP: Record<string, LucideIcon> = {
   folder: Folder,
   "file-text": FileText,
@@ -87,11 +113,25 @@ export default function AppSidebar() {
   const [isLoading, setIsLoading] = useState(true);
   const [editingId, setEditingId] = useState<number | null>(null);
   const [editingName, setEditingName] = useState("");
+  const [isSearchOpen, setIsSearchOpen] = useState(false);
+  const [folders, setFolders] = useState<SopFolder[]>([]);
+  const [tags, setTags] = useState<Tag[]>([]);
+  const [tagFilter, setTagFilter] = useState<number[]>([]);
+  const [collapsedFolders, setCollapsedFolders] = useState<Set<number>>(new Set());
+  const [editingFolderId, setEditingFolderId] = useState<number | null>(null);
+  const [newTagName, setNewTagName] = useState("");
+  const [exportTarget, setExportTarget] = useState<SopItem | null>(null);
 
   const fetchItems = async () => {
     try {
-      const result = await invoke<SopItem[]>("get_all_sop_items");
+      const [result, folderList, tagList] = await Promise.all([
+        invoke<SopItem[]>("get_all_sop_items", { filter: { tag_ids: tagFilter } }),
+        invoke<SopFolder[]>("get_folders"),
+        invoke<Tag[]>("get_tags"),
+      ]);
       setItems(result);
+      setFolders(folderList);
+      setTags(tagList);
     } catch (error) {
       console.error("Failed to fetch items:", error);
     } finally {
@@ -101,7 +141,19 @@ export default function AppSidebar() {
 
   useEffect(() => {
     fetchItems();
-  }, [location.pathname]);
+  }, [location.pathname, tagFilter]);
+
+  // Ctrl/Cmd+K opens search
+  useEffect(() => {
+    const handleShortcut = (e: KeyboardEvent) => {
+      if ((e.ctrlKey || e.metaKey) && e.key.toLowerCase() === "k") {
+        e.preventDefault();
+        setIsSearchOpen(true);
+      }
+    };
+    document.addEventListener("keydown", handleShortcut);
+    return () => document.removeEventListener("keydown", handleShortcut);
+  }, []);
 
   const handleCreateNew = () => {
     navigate("/new");
@@ -154,6 +206,321 @@ export default function AppSidebar() {
     setEditingName(item.name);
   };
 
+  const handleMoveItem = async (id: number, folderId: number | null) => {
+    try {
+      const moved = await invoke<SopItem>("move_sop_item", { id, folderId });
+      setItems((prev) => prev.map((item) => (item.id === id ? moved : item)));
+    } catch (error) {
+      console.error("Failed to move item:", error);
+    }
+  };
+
+  const handleToggleTag = async (item: SopItem, tagId: number) => {
+    const tagIds = item.tag_ids.includes(tagId)
+      ? item.tag_ids.filter((id) => id !== tagId)
+      : [...item.tag_ids, tagId];
+    try {
+      const updated = await invoke<SopItem>("set_sop_item_tags", { id: item.id, tagIds });
+      setItems((prev) => prev.map((i) => (i.id === item.id ? updated : i)));
+    } catch (error) {
+      console.error("Failed to update tags:", error);
+    }
+  };
+
+  const handleCreateTag = async (item: SopItem) => {
+    if (!newTagName.trim()) return;
+    try {
+      const tag = await invoke<Tag>("create_tag", { name: newTagName, color: null });
+      setTags((prev) => [...prev, tag]);
+      setNewTagName("");
+      await handleToggleTag(item, tag.id);
+    } catch (error) {
+      console.error("Failed to create tag:", error);
+    }
+  };
+
+  const handleCreateFolder = async (parentId: number | null) => {
+    try {
+      const folder = await invoke<SopFolder>("create_folder", { name: t('sidebar.newFolder'), parentId });
+      setFolders((prev) => [...prev, folder]);
+      if (parentId !== null) {
+        setCollapsedFolders((prev) => {
+          const next = new Set(prev);
+          next.delete(parentId);
+          return next;
+        });
+      }
+      setEditingFolderId(folder.id);
+      setEditingName(folder.name);
+    } catch (error) {
+      console.error("Failed to create folder:", error);
+    }
+  };
+
+  const handleRenameFolder = async (id: number) => {
+    setEditingFolderId(null);
+    if (!editingName.trim()) return;
+    try {
+      const renamed = await invoke<SopFolder>("rename_folder", { id, name: editingName });
+      setFolders((prev) => prev.map((folder) => (folder.id === id ? renamed : folder)));
+    } catch (error) {
+      console.error("Failed to rename folder:", error);
+    }
+  };
+
+  const handleDeleteFolder = async (id: number) => {
+    try {
+      await invoke("delete_folder", { id });
+      // Its items and subfolders moved up a level
+      fetchItems();
+    } catch (error) {
+      console.error("Failed to delete folder:", error);
+    }
+  };
+
+  const toggleFolder = (id: number) => {
+    setCollapsedFolders((prev) => {
+      const next = new Set(prev);
+      if (next.has(id)) {
+        next.delete(id);
+      } else {
+        next.add(id);
+      }
+      return next;
+    });
+  };
+
+  const toggleTagFilter = (id: number) => {
+    setTagFilter((prev) => (prev.includes(id) ? prev.filter((tagId) => tagId !== id) : [...prev, id]));
+  };
+
+  // Full path of each folder, for the "Move to" menu
+  const folderPath = (folder: SopFolder): string => {
+    const parent = folders.find((f) => f.id === folder.parent_id);
+    return parent ? `${folderPath(parent)} / ${folder.name}` : folder.name;
+  };
+
+  const renderItem = (item: SopItem, depth: number) => {
+    const Icon = getIcon(item.icon);
+    const itemPath =
+      item.item_type === "todo"
+        ? `/todo/${item.id}`
+        : `/flow/${item.id}`;
+    const isActive = location.pathname === itemPath;
+    const isEditing = editingId === item.id;
+    const handleItemClick = () => {
+      if (!isEditing) {
+        navigate(itemPath);
+      }
+    };
+    return (
+      <SidebarMenuItem key={`item-${item.id}`} className="group/item" style={{ paddingLeft: depth * 12 }}>
+        {isEditing ? (
+          <div className="flex items-center gap-2 px-2 py-1">
+            <Icon className="w-4 h-4 shrink-0" />
+            <Input
+              type="text"
+              value={editingName}
+              onChange={(e) => setEditingName(e.target.value)}
+              onBlur={() => handleRename(item.id)}
+              onKeyDown={(e) => {
+                if (e.key === "Enter") {
+                  handleRename(item.id);
+                } else if (e.key === "Escape") {
+                  setEditingId(null);
+                }
+              }}
+              className="h-6 text-sm"
+              autoFocus
+            />
+          </div>
+        ) : (
+          <div className="flex items-center w-full">
+            <SidebarMenuButton
+              tooltip={item.name}
+              onClick={handleItemClick}
+              isActive={isActive}
+              className="flex-1"
+            >
+              <Icon className="w-4 h-4" />
+              <span>{item.name}</span>
+            </SidebarMenuButton>
+            <DropdownMenu>
+              <DropdownMenuTrigger asChild>
+                <button
+                  className="p-1 opacity-0 group-hover/item:opacity-100 hover:bg-sidebar-accent rounded transition-opacity"
+                  onClick={(e) => e.stopPropagation()}
+                >
+                  <MoreHorizontal className="w-4 h-4 text-muted-foreground" />
+                </button>
+              </DropdownMenuTrigger>
+              <DropdownMenuContent align="end" side="right">
+                <DropdownMenuItem onClick={() => startEditing(item)}>
+                  <Pencil className="w-4 h-4" />
+                  <span>{t('sidebar.rename')}</span>
+                </DropdownMenuItem>
+                <DropdownMenuSub>
+                  <DropdownMenuSubTrigger>
+                    <FolderInput className="w-4 h-4" />
+                    <span>{t('sidebar.moveTo')}</span>
+                  </DropdownMenuSubTrigger>
+                  <DropdownMenuSubContent>
+                    <DropdownMenuCheckboxItem
+                      checked={item.folder_id === null}
+                      onCheckedChange={() => handleMoveItem(item.id, null)}
+                    >
+                      {t('sidebar.topLevel')}
+                    </DropdownMenuCheckboxItem>
+                    {folders.map((folder) => (
+                      <DropdownMenuCheckboxItem
+                        key={folder.id}
+                        checked={item.folder_id === folder.id}
+                        onCheckedChange={() => handleMoveItem(item.id, folder.id)}
+                      >
+                        {folderPath(folder)}
+                      </DropdownMenuCheckboxItem>
+                    ))}
+                  </DropdownMenuSubContent>
+                </DropdownMenuSub>
+                <DropdownMenuSub>
+                  <DropdownMenuSubTrigger>
+                    <TagIcon className="w-4 h-4" />
+                    <span>{t('sidebar.tags')}</span>
+                  </DropdownMenuSubTrigger>
+                  <DropdownMenuSubContent>
+                    {tags.map((tag) => (
+                      <DropdownMenuCheckboxItem
+                        key={tag.id}
+                        checked={item.tag_ids.includes(tag.id)}
+                        onCheckedChange={() => handleToggleTag(item, tag.id)}
+                        onSelect={(e) => e.preventDefault()}
+                      >
+                        {tag.name}
+                      </DropdownMenuCheckboxItem>
+                    ))}
+                    {tags.length > 0 && <DropdownMenuSeparator />}
+                    <div className="p-1">
+                      <Input
+                        value={newTagName}
+                        onChange={(e) => setNewTagName(e.target.value)}
+                        onKeyDown={(e) => {
+                          // Keep typing from driving the menu's keyboard navigation
+                          e.stopPropagation();
+                          if (e.key === "Enter") {
+                            handleCreateTag(item);
+                          }
+                        }}
+                        placeholder={t('sidebar.newTag')}
+                        className="h-7 text-sm"
+                      />
+                    </div>
+                  </DropdownMenuSubContent>
+                </DropdownMenuSub>
+                <DropdownMenuItem onClick={() => setExportTarget(item)}>
+                  <Download className="w-4 h-4" />
+                  <span>{t('sidebar.export')}</span>
+                </DropdownMenuItem>
+                <DropdownMenuItem
+                  variant="destructive"
+                  onClick={() => handleDelete(item.id)}
+                >
+                  <Trash2 className="w-4 h-4" />
+                  <span>{t('sidebar.delete')}</span>
+                </DropdownMenuItem>
+              </DropdownMenuContent>
+            </DropdownMenu>
+          </div>
+        )}
+      </SidebarMenuItem>
+    );
+  };
+
+  const renderFolder = (folder: SopFolder, depth: number): React.ReactNode => {
+    const isCollapsed = collapsedFolders.has(folder.id);
+    const subfolders = folders.filter((f) => f.parent_id === folder.id);
+    const folderItems = items.filter((item) => item.folder_id === folder.id);
+    // While filtering by tag, hide folders without a matching item
+    if (tagFilter.length > 0 && !hasItems(folder)) return null;
+
+    return (
+      <div key={`folder-${folder.id}`}>
+        <SidebarMenuItem className="group/item" style={{ paddingLeft: depth * 12 }}>
+          {editingFolderId === folder.id ? (
+            <div className="flex items-center gap-2 px-2 py-1">
+              <Folder className="w-4 h-4 shrink-0" />
+              <Input
+                type="text"
+                value={editingName}
+                onChange={(e) => setEditingName(e.target.value)}
+                onBlur={() => handleRenameFolder(folder.id)}
+                onKeyDown={(e) => {
+                  if (e.key === "Enter") {
+                    handleRenameFolder(folder.id);
+                  } else if (e.key === "Escape") {
+                    setEditingFolderId(null);
+                  }
+                }}
+                className="h-6 text-sm"
+                autoFocus
+              />
+            </div>
+          ) : (
+            <div className="flex items-center w-full">
+              <SidebarMenuButton onClick={() => toggleFolder(folder.id)} className="flex-1">
+                <ChevronRight className={`w-4 h-4 transition-transform ${isCollapsed ? "" : "rotate-90"}`} />
+                <span className="font-medium">{folder.name}</span>
+              </SidebarMenuButton>
+              <DropdownMenu>
+                <DropdownMenuTrigger asChild>
+                  <button
+                    className="p-1 opacity-0 group-hover/item:opacity-100 hover:bg-sidebar-accent rounded transition-opacity"
+                    onClick={(e) => e.stopPropagation()}
+                  >
+                    <MoreHorizontal className="w-4 h-4 text-muted-foreground" />
+                  </button>
+                </DropdownMenuTrigger>
+                <DropdownMenuContent align="end" side="right">
+                  <DropdownMenuItem onClick={() => handleCreateFolder(folder.id)}>
+                    <FolderPlus className="w-4 h-4" />
+                    <span>{t('sidebar.newSubfolder')}</span>
+                  </DropdownMenuItem>
+                  <DropdownMenuItem
+                    onClick={() => {
+                      setEditingFolderId(folder.id);
+                      setEditingName(folder.name);
+                    }}
+                  >
+                    <Pencil className="w-4 h-4" />
+                    <span>{t('sidebar.rename')}</span>
+                  </DropdownMenuItem>
+                  <DropdownMenuItem variant="destructive" onClick={() => handleDeleteFolder(folder.id)}>
+                    <Trash2 className="w-4 h-4" />
+                    <span>{t('sidebar.deleteFolder')}</span>
+                  </DropdownMenuItem>
+                </DropdownMenuContent>
+              </DropdownMenu>
+            </div>
+          )}
+        </SidebarMenuItem>
+        {!isCollapsed && (
+          <>
+            {subfolders.map((subfolder) => renderFolder(subfolder, depth + 1))}
+            {folderItems.map((item) => renderItem(item, depth + 1))}
+          </>
+        )}
+      </div>
+    );
+  };
+
+  const hasItems = (folder: SopFolder): boolean =>
+    items.some((item) => item.folder_id === folder.id) ||
+    folders.some((f) => f.parent_id === folder.id && hasItems(f));
+
+  const topLevelFolders = folders.filter((folder) => folder.parent_id === null);
+  const unfiledItems = items.filter(
+    (item) => item.folder_id === null || !folders.some((folder) => folder.id === item.folder_id)
+  );
+
   return (
     <Sidebar collapsible="icon">
       {/* Header */}
@@ -173,9 +540,17 @@ export default function AppSidebar() {
               <button
                 className="p-1.5 hover:bg-sidebar-accent rounded-md transition-colors"
                 title={t('sidebar.searchProject')}
+                onClick={() => setIsSearchOpen(true)}
               >
                 <Search className="w-4 h-4 text-muted-foreground" />
               </button>
+              <button
+                className="p-1.5 hover:bg-sidebar-accent rounded-md transition-colors"
+                title={t('sidebar.newFolder')}
+                onClick={() => handleCreateFolder(null)}
+              >
+                <FolderPlus className="w-4 h-4 text-muted-foreground" />
+              </button>
               <button
                 className="p-1.5 hover:bg-sidebar-accent rounded-md transition-colors"
                 title={t('common.newItem')}
@@ -213,90 +588,39 @@ export default function AppSidebar() {
         {/* Project List */}
         <SidebarGroup className="group-data-[collapsible=icon]:hidden">
           <SidebarGroupLabel>{t('sidebar.itemList')}</SidebarGroupLabel>
+          {tags.length > 0 && (
+            <div className="flex flex-wrap gap-1 px-2 pb-2">
+              {tags.map((tag) => (
+                <button
+                  key={tag.id}
+                  onClick={() => toggleTagFilter(tag.id)}
+                  className={`px-2 py-0.5 rounded-full text-xs border transition-colors ${
+                    tagFilter.includes(tag.id)
+                      ? "bg-primary text-primary-foreground border-primary"
+                      : "text-muted-foreground border-border hover:bg-sidebar-accent"
+                  }`}
+                  style={tag.color && !tagFilter.includes(tag.id) ? { borderColor: tag.color } : undefined}
+                >
+                  {tag.name}
+                </button>
+              ))}
+            </div>
+          )}
           <SidebarGroupContent>
             <SidebarMenu>
               {isLoading ? (
                 <div className="px-2 py-4 text-sm text-muted-foreground">
                   {t('common.loading')}
                 </div>
-              ) : items.length === 0 ? (
+              ) : items.length === 0 && folders.length === 0 ? (
                 <div className="px-2 py-4 text-sm text-muted-foreground text-center">
                   {t('sidebar.emptyMessage')}
                 </div>
               ) : (
-              items.map((item) => {
-                  const Icon = getIcon(item.icon);
-                  const itemPath =
-                    item.item_type === "todo"
-                      ? `/todo/${item.id}`
-                      : `/flow/${item.id}`;
-                  const isActive = location.pathname === itemPath;
-                  const isEditing = editingId === item.id;
-                  const handleItemClick = () => {
-                    if (!isEditing) {
-                      navigate(itemPath);
-                    }
-                  };
-                  return (
-                    <SidebarMenuItem key={item.id} className="group/item">
-                      {isEditing ? (
-                        <div className="flex items-center gap-2 px-2 py-1">
-                          <Icon className="w-4 h-4 shrink-0" />
-                          <Input
-                            type="text"
-                            value={editingName}
-                            onChange={(e) => setEditingName(e.target.value)}
-                            onBlur={() => handleRename(item.id)}
-                            onKeyDown={(e) => {
-                              if (e.key === "Enter") {
-                                handleRename(item.id);
-                              } else if (e.key === "Escape") {
-                                setEditingId(null);
-                              }
-                            }}
-                            className="h-6 text-sm"
-                            autoFocus
-                          />
-                        </div>
-                      ) : (
-                        <div className="flex items-center w-full">
-                          <SidebarMenuButton
-                            tooltip={item.name}
-                            onClick={handleItemClick}
-                            isActive={isActive}
-                            className="flex-1"
-                          >
-                            <Icon className="w-4 h-4" />
-                            <span>{item.name}</span>
-                          </SidebarMenuButton>
-                          <DropdownMenu>
-                            <DropdownMenuTrigger asChild>
-                              <button
-                                className="p-1 opacity-0 group-hover/item:opacity-100 hover:bg-sidebar-accent rounded transition-opacity"
-                                onClick={(e) => e.stopPropagation()}
-                              >
-                                <MoreHorizontal className="w-4 h-4 text-muted-foreground" />
-                              </button>
-                            </DropdownMenuTrigger>
-                            <DropdownMenuContent align="end" side="right">
-                              <DropdownMenuItem onClick={() => startEditing(item)}>
-                                <Pencil className="w-4 h-4" />
-                                <span>{t('sidebar.rename')}</span>
-                              </DropdownMenuItem>
-                              <DropdownMenuItem
-                                variant="destructive"
-                                onClick={() => handleDelete(item.id)}
-                              >
-                                <Trash2 className="w-4 h-4" />
-                                <span>{t('sidebar.delete')}</span>
-                              </DropdownMenuItem>
-                            </DropdownMenuContent>
-                          </DropdownMenu>
-                        </div>
-                      )}
-                    </SidebarMenuItem>
-                  );
-                })
+                <>
+                  {topLevelFolders.map((folder) => renderFolder(folder, 0))}
+                  {unfiledItems.map((item) => renderItem(item, 0))}
+                </>
               )}
             </SidebarMenu>
           </SidebarGroupContent>
@@ -310,6 +634,9 @@ export default function AppSidebar() {
 
       {/* Rail for resizing */}
       <SidebarRail />
+
+      <SearchDialog open={isSearchOpen} onOpenChange={setIsSearchOpen} />
+      <ExportDialog sop={exportTarget} onOpenChange={(open) => !open && setExportTarget(null)} />
     </Sidebar>
   );
 }
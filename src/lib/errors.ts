@@ -0,0 +1,18 @@
+import type { TFunction } from "i18next"
+
+// Mirrors `ZopError` in src-tauri/src/error.rs
+export interface ZopError {
+  code: string
+  message: string
+  details?: unknown
+}
+
+export function isZopError(error: unknown): error is ZopError {
+  return typeof error === "object" && error !== null && "code" in error && "message" in error
+}
+
+// Localized text for a command error, falling back to the backend's English message
+export function errorMessage(error: unknown, t: TFunction): string {
+  if (!isZopError(error)) return String(error)
+  return t(`errors.${error.code}`, { defaultValue: error.message })
+}
@@ -2,76 +2,178 @@ import { useState, useEffect } from "react";
 import { useNavigate } from "react-router-dom";
 import { useTranslation } from "react-i18next";
 import { invoke } from "@tauri-apps/api/core";
-import { ArrowLeft, Save, Eye, EyeOff } from "lucide-react";
+import { errorMessage } from "@/lib/errors";
+import {
+  ArrowLeft,
+  Save,
+  Eye,
+  EyeOff,
+  Plus,
+  Star,
+  Trash2,
+  PlugZap,
+  RefreshCw,
+  Loader2,
+} from "lucide-react";
 import { Button } from "@/components/ui/button";
 import { Input } from "@/components/ui/input";
 import { Label } from "@/components/ui/label";
 
-interface AiConfig {
+interface AiProfile {
   id: number;
+  name: string;
   base_url: string;
-  api_key: string;
+  api_key_masked: string | null;
+  // Set when the stored key can't be decrypted
+  api_key_error?: { code: string; message: string };
   model_name: string;
+  is_default: boolean;
   created_at: string;
   updated_at: string;
 }
 
+interface ConnectionCheck {
+  model_count: number;
+  latency_ms: number;
+}
+
+const DEFAULT_BASE_URL = "https://api.openai.com/v1";
+const DEFAULT_MODEL = "gpt-4o";
+
 export default function AiConfig() {
   const { t } = useTranslation();
   const navigate = useNavigate();
 
-  const [baseUrl, setBaseUrl] = useState("https://api.openai.com/v1");
+  const [profiles, setProfiles] = useState<AiProfile[]>([]);
+  // The profile being edited, or null for a new one
+  const [selectedId, setSelectedId] = useState<number | null>(null);
+  const [name, setName] = useState("");
+  const [baseUrl, setBaseUrl] = useState(DEFAULT_BASE_URL);
   const [apiKey, setApiKey] = useState("");
-  const [modelName, setModelName] = useState("gpt-4o");
+  const [storedKey, setStoredKey] = useState<string | null>(null);
+  const [isKeyUnavailable, setIsKeyUnavailable] = useState(false);
+  const [modelName, setModelName] = useState(DEFAULT_MODEL);
   const [showApiKey, setShowApiKey] = useState(false);
   const [isLoading, setIsLoading] = useState(true);
   const [isSaving, setIsSaving] = useState(false);
   const [error, setError] = useState<string | null>(null);
+  const [models, setModels] = useState<string[]>([]);
+  const [isLoadingModels, setIsLoadingModels] = useState(false);
+  const [isTesting, setIsTesting] = useState(false);
+  const [testResult, setTestResult] = useState<
+    { ok: true; check: ConnectionCheck } | { ok: false; message: string } | null
+  >(null);
+
+  const edit = (profile: AiProfile | null) => {
+    setSelectedId(profile?.id ?? null);
+    setName(profile?.name ?? "");
+    setBaseUrl(profile?.base_url ?? DEFAULT_BASE_URL);
+    setApiKey("");
+    setStoredKey(profile?.api_key_masked ?? null);
+    setIsKeyUnavailable(!!profile?.api_key_error);
+    setModelName(profile?.model_name ?? DEFAULT_MODEL);
+    setShowApiKey(false);
+    setError(null);
+    setModels([]);
+    setTestResult(null);
+  };
+
+  const loadProfiles = async (select?: number) => {
+    const list = await invoke<AiProfile[]>("list_ai_profiles");
+    setProfiles(list);
+    const current =
+      list.find((p) => p.id === select) ?? list.find((p) => p.is_default) ?? null;
+    edit(current);
+  };
 
   useEffect(() => {
-    const loadConfig = async () => {
-      try {
-        const config = await invoke<AiConfig | null>("get_ai_config");
-        if (config) {
-          setBaseUrl(config.base_url);
-          setApiKey(config.api_key);
-          setModelName(config.model_name);
-        }
-      } catch (error) {
-        console.error("Failed to load AI config:", error);
-      } finally {
-        setIsLoading(false);
-      }
-    };
-
-    loadConfig();
+    loadProfiles()
+      .catch((error) => console.error("Failed to load AI profiles:", error))
+      .finally(() => setIsLoading(false));
   }, []);
 
+  const canConnect = !!baseUrl.trim() && (!!apiKey.trim() || !!storedKey);
+  const canSave = canConnect && !!name.trim() && !!modelName.trim();
+
+  // The settings as typed; an empty key means the stored one
+  const formConfig = () => ({
+    base_url: baseUrl,
+    api_key: apiKey.trim() || null,
+    model_name: modelName,
+  });
+
+  const handleLoadModels = async () => {
+    setIsLoadingModels(true);
+    setError(null);
+    try {
+      setModels(
+        await invoke<string[]>("list_models", { config: formConfig(), profileId: selectedId })
+      );
+    } catch (error) {
+      setError(errorMessage(error, t));
+    } finally {
+      setIsLoadingModels(false);
+    }
+  };
+
+  const handleTest = async () => {
+    setIsTesting(true);
+    setTestResult(null);
+    try {
+      const check = await invoke<ConnectionCheck>("test_ai_config", {
+        config: formConfig(),
+        profileId: selectedId,
+      });
+      setTestResult({ ok: true, check });
+    } catch (error) {
+      setTestResult({ ok: false, message: errorMessage(error, t) });
+    } finally {
+      setIsTesting(false);
+    }
+  };
+
   const handleSave = async () => {
-    if (!apiKey.trim() || !modelName.trim() || !baseUrl.trim()) {
+    if (!canSave) {
       return;
     }
 
     setIsSaving(true);
     setError(null);
     try {
-      const result = await invoke("save_ai_config", {
-        config: {
-          base_url: baseUrl,
-          api_key: apiKey,
-          model_name: modelName,
-        },
-      });
-      console.log("AI config saved successfully:", result);
-      navigate(-1);
+      const config = formConfig();
+      const saved =
+        selectedId === null
+          ? await invoke<AiProfile>("create_ai_profile", { name, config })
+          : await invoke<AiProfile>("update_ai_profile", { id: selectedId, name, config });
+      await loadProfiles(saved.id);
     } catch (error) {
-      console.error("Failed to save AI config:", error);
-      setError(String(error));
+      console.error("Failed to save AI profile:", error);
+      setError(errorMessage(error, t));
     } finally {
       setIsSaving(false);
     }
   };
 
+  const handleSetDefault = async () => {
+    if (selectedId === null) return;
+    try {
+      await invoke("set_default_ai_profile", { id: selectedId });
+      await loadProfiles(selectedId);
+    } catch (error) {
+      setError(errorMessage(error, t));
+    }
+  };
+
+  const handleDelete = async () => {
+    if (selectedId === null) return;
+    try {
+      await invoke("delete_ai_profile", { id: selectedId });
+      await loadProfiles();
+    } catch (error) {
+      setError(errorMessage(error, t));
+    }
+  };
+
   if (isLoading) {
     return (
       <div className="h-full flex items-center justify-center bg-background">
@@ -80,6 +182,8 @@ export default function AiConfig() {
     );
   }
 
+  const selected = profiles.find((p) => p.id === selectedId);
+
   return (
     <div className="h-full bg-background overflow-y-auto">
       <div className="max-w-2xl mx-auto p-6">
@@ -93,8 +197,43 @@ export default function AiConfig() {
           </h1>
         </div>
 
+        {/* Profiles */}
+        <div className="flex flex-wrap items-center gap-2 mb-6">
+          {profiles.map((profile) => (
+            <Button
+              key={profile.id}
+              variant={profile.id === selectedId ? "secondary" : "ghost"}
+              size="sm"
+              onClick={() => edit(profile)}
+            >
+              {profile.is_default && <Star className="w-3 h-3 mr-1 fill-current" />}
+              {profile.name}
+            </Button>
+          ))}
+          <Button
+            variant={selectedId === null ? "secondary" : "outline"}
+            size="sm"
+            onClick={() => edit(null)}
+          >
+            <Plus className="w-4 h-4 mr-1" />
+            {t("aiConfig.newProfile")}
+          </Button>
+        </div>
+
         {/* Form */}
         <div className="space-y-6">
+          {/* Name */}
+          <div className="space-y-2">
+            <Label htmlFor="profileName">{t("aiConfig.profileName")}</Label>
+            <Input
+              id="profileName"
+              type="text"
+              value={name}
+              onChange={(e) => setName(e.target.value)}
+              placeholder={t("aiConfig.profileNamePlaceholder")}
+            />
+          </div>
+
           {/* Base URL */}
           <div className="space-y-2">
             <Label htmlFor="baseUrl">{t("aiConfig.baseUrl")}</Label>
@@ -119,7 +258,7 @@ export default function AiConfig() {
                 type={showApiKey ? "text" : "password"}
                 value={apiKey}
                 onChange={(e) => setApiKey(e.target.value)}
-                placeholder={t("aiConfig.apiKeyPlaceholder")}
+                placeholder={storedKey ?? t("aiConfig.apiKeyPlaceholder")}
                 className="pr-10"
               />
               <button
@@ -134,23 +273,74 @@ export default function AiConfig() {
                 )}
               </button>
             </div>
+            {storedKey && (
+              <p className="text-xs text-muted-foreground">
+                {t("aiConfig.apiKeyStored")}
+              </p>
+            )}
+            {isKeyUnavailable && (
+              <p className="text-xs text-destructive">
+                {t("aiConfig.apiKeyUnavailable")}
+              </p>
+            )}
           </div>
 
           {/* Model Name */}
           <div className="space-y-2">
             <Label htmlFor="modelName">{t("aiConfig.modelName")}</Label>
-            <Input
-              id="modelName"
-              type="text"
-              value={modelName}
-              onChange={(e) => setModelName(e.target.value)}
-              placeholder={t("aiConfig.modelPlaceholder")}
-            />
+            <div className="flex gap-2">
+              <Input
+                id="modelName"
+                type="text"
+                list="modelOptions"
+                value={modelName}
+                onChange={(e) => setModelName(e.target.value)}
+                placeholder={t("aiConfig.modelPlaceholder")}
+              />
+              <Button
+                variant="outline"
+                size="icon"
+                onClick={handleLoadModels}
+                disabled={!canConnect || isLoadingModels}
+                title={t("aiConfig.loadModels")}
+              >
+                <RefreshCw className={`w-4 h-4 ${isLoadingModels ? "animate-spin" : ""}`} />
+              </Button>
+            </div>
+            <datalist id="modelOptions">
+              {models.map((model) => (
+                <option key={model} value={model} />
+              ))}
+            </datalist>
             <p className="text-xs text-muted-foreground">
               {t("aiConfig.modelHint")}
             </p>
           </div>
 
+          {/* Connection test */}
+          <div className="flex items-center gap-3">
+            <Button variant="outline" onClick={handleTest} disabled={!canConnect || isTesting}>
+              {isTesting ? (
+                <Loader2 className="w-4 h-4 mr-2 animate-spin" />
+              ) : (
+                <PlugZap className="w-4 h-4 mr-2" />
+              )}
+              {isTesting ? t("aiConfig.testing") : t("aiConfig.testConnection")}
+            </Button>
+            {testResult && (
+              <p
+                className={`text-sm ${testResult.ok ? "text-green-600" : "text-destructive"}`}
+              >
+                {testResult.ok
+                  ? t("aiConfig.testSuccess", {
+                      count: testResult.check.model_count,
+                      ms: testResult.check.latency_ms,
+                    })
+                  : testResult.message}
+              </p>
+            )}
+          </div>
+
           {/* Error Message */}
           {error && (
             <div className="p-3 rounded-md bg-destructive/10 text-destructive text-sm">
@@ -158,13 +348,25 @@ export default function AiConfig() {
             </div>
           )}
 
-          {/* Save Button */}
-          <div className="pt-4">
-            <Button
-              onClick={handleSave}
-              disabled={isSaving || !apiKey.trim() || !modelName.trim() || !baseUrl.trim()}
-              className="w-full"
-            >
+          {/* Actions */}
+          <div className="pt-4 flex items-center gap-2">
+            {selected && !selected.is_default && (
+              <Button variant="outline" onClick={handleSetDefault}>
+                <Star className="w-4 h-4 mr-2" />
+                {t("aiConfig.setDefault")}
+              </Button>
+            )}
+            {selected && (
+              <Button
+                variant="ghost"
+                className="text-destructive hover:text-destructive"
+                onClick={handleDelete}
+              >
+                <Trash2 className="w-4 h-4 mr-2" />
+                {t("common.delete")}
+              </Button>
+            )}
+            <Button onClick={handleSave} disabled={isSaving || !canSave} className="flex-1">
               <Save className="w-4 h-4 mr-2" />
               {isSaving ? t("common.saving") : t("common.save")}
             </Button>
@@ -1,7 +1,9 @@
-import { useState } from "react";
+import { useState, useEffect, useRef } from "react";
 import { useNavigate } from "react-router-dom";
 import { useTranslation } from "react-i18next";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { errorMessage, isZopError } from "@/lib/errors";
 import {
   RotateCcw,
   Send,
@@ -14,6 +16,7 @@ import {
   FileText,
   FormInput,
   CircleStop,
+  X,
 } from "lucide-react";
 import { Button } from "@/components/ui/button";
 import { Textarea } from "@/components/ui/textarea";
@@ -37,6 +40,27 @@ interface GeneratedSop {
   steps: SopStep[];
 }
 
+interface StepEvent {
+  generation_id: string;
+  index: number;
+  step: SopStep;
+}
+
+interface ProgressEvent {
+  generation_id: string;
+  stage: "started" | "streaming" | "restarted";
+  title: string | null;
+  steps: number;
+  received: number;
+}
+
+interface AiProfile {
+  id: number;
+  name: string;
+  model_name: string;
+  is_default: boolean;
+}
+
 const STEP_TYPES = [
   { value: "start", label: "Start", icon: Play, color: "text-green-500" },
   { value: "read", label: "Read", icon: FileText, color: "text-blue-500" },
@@ -52,6 +76,22 @@ export default function AiHome() {
   const [error, setError] = useState<string | null>(null);
   const [generatedSop, setGeneratedSop] = useState<GeneratedSop | null>(null);
   const [isCreating, setIsCreating] = useState(false);
+  const [profiles, setProfiles] = useState<AiProfile[]>([]);
+  const [profileId, setProfileId] = useState<string>("");
+  // Steps streamed in by the running generation
+  const [streamedSteps, setStreamedSteps] = useState<SopStep[]>([]);
+  const [streamedTitle, setStreamedTitle] = useState<string | null>(null);
+  const generationId = useRef<string | null>(null);
+
+  useEffect(() => {
+    invoke<AiProfile[]>("list_ai_profiles")
+      .then((list) => {
+        setProfiles(list);
+        const fallback = list.find((p) => p.is_default) ?? list[0];
+        if (fallback) setProfileId(String(fallback.id));
+      })
+      .catch((error) => console.error("Failed to load AI profiles:", error));
+  }, []);
 
   const handleReset = () => {
     setInput("");
@@ -62,21 +102,53 @@ export default function AiHome() {
   const handleGenerate = async () => {
     if (!input.trim()) return;
 
+    const id = crypto.randomUUID();
+    generationId.current = id;
     setIsGenerating(true);
     setError(null);
+    setStreamedSteps([]);
+    setStreamedTitle(null);
+
+    const unlisten = await Promise.all([
+      listen<StepEvent>("sop-generation://step", ({ payload }) => {
+        if (payload.generation_id !== id) return;
+        setStreamedSteps((steps) => [...steps.slice(0, payload.index), payload.step]);
+      }),
+      listen<ProgressEvent>("sop-generation://progress", ({ payload }) => {
+        if (payload.generation_id !== id) return;
+        setStreamedTitle(payload.title);
+        // The direct path starts over after the agent failed
+        if (payload.stage === "restarted") setStreamedSteps([]);
+      }),
+    ]);
 
     try {
       const result = await invoke<GeneratedSop>("generate_sop", {
         prompt: input,
+        profileId: profileId ? Number(profileId) : null,
+        generationId: id,
       });
       setGeneratedSop(result);
     } catch (err) {
-      setError(String(err));
+      if (!(isZopError(err) && err.code === "cancelled")) {
+        setError(errorMessage(err, t));
+      }
     } finally {
+      unlisten.forEach((stop) => stop());
+      generationId.current = null;
       setIsGenerating(false);
     }
   };
 
+  const handleCancel = async () => {
+    if (!generationId.current) return;
+    try {
+      await invoke("cancel_generation", { generationId: generationId.current });
+    } catch (err) {
+      console.error("Failed to cancel generation:", err);
+    }
+  };
+
   const updateStep = (index: number, field: keyof SopStep, value: string) => {
     if (!generatedSop) return;
 
@@ -112,15 +184,6 @@ export default function AiHome() {
     setIsCreating(true);
 
     try {
-      // Create SOP item
-      const sopItem = await invoke<{ id: number }>("create_sop_item", {
-        item: {
-          name: generatedSop.title,
-          icon: "zap",
-          item_type: "flowchart",
-        },
-      });
-
       // Convert steps to flow nodes and edges
       const nodes = generatedSop.steps.map((step, index) => ({
         id: `node-${Date.now()}-${index}`,
@@ -140,17 +203,21 @@ export default function AiHome() {
         type: "straight",
       }));
 
-      // Save flow data
-      await invoke("save_flow_data", {
-        sopId: sopItem.id,
-        nodes: JSON.stringify(nodes),
-        edges: JSON.stringify(edges),
+      // Create the SOP item and its flow together
+      const sopItem = await invoke<{ id: number }>("create_sop_with_flow", {
+        item: {
+          name: generatedSop.title,
+          icon: "zap",
+          item_type: "flowchart",
+        },
+        nodes,
+        edges,
       });
 
       // Navigate to flow detail
       navigate(`/flow/${sopItem.id}`);
     } catch (err) {
-      setError(String(err));
+      setError(errorMessage(err, t));
     } finally {
       setIsCreating(false);
     }
@@ -184,25 +251,65 @@ export default function AiHome() {
                 </div>
               )}
 
+              {/* Steps as they are generated */}
+              {isGenerating && (streamedTitle || streamedSteps.length > 0) && (
+                <div className="space-y-2 p-3 rounded-lg border bg-card">
+                  {streamedTitle && (
+                    <p className="font-semibold text-foreground">{streamedTitle}</p>
+                  )}
+                  <ol className="space-y-1">
+                    {streamedSteps.map((step, index) => (
+                      <li key={index} className="flex items-center gap-2 text-sm">
+                        {getStepIcon(step.step_type)}
+                        <span>{step.label}</span>
+                      </li>
+                    ))}
+                    <li className="flex items-center gap-2 text-sm text-muted-foreground">
+                      <Loader2 className="w-4 h-4 animate-spin" />
+                      <span>{t("aiHome.nextStep")}</span>
+                    </li>
+                  </ol>
+                </div>
+              )}
+
               {/* Actions */}
               <div className="flex items-center justify-between">
-                <Button
-                  variant="outline"
-                  onClick={handleReset}
-                  disabled={!input.trim() || isGenerating}
-                >
-                  <RotateCcw className="w-4 h-4 mr-2" />
-                  {t("aiHome.reset")}
-                </Button>
-
-                <Button onClick={handleGenerate} disabled={!input.trim() || isGenerating}>
-                  {isGenerating ? (
-                    <Loader2 className="w-4 h-4 mr-2 animate-spin" />
-                  ) : (
-                    <Send className="w-4 h-4 mr-2" />
+                {isGenerating ? (
+                  <Button variant="outline" onClick={handleCancel}>
+                    <X className="w-4 h-4 mr-2" />
+                    {t("common.cancel")}
+                  </Button>
+                ) : (
+                  <Button variant="outline" onClick={handleReset} disabled={!input.trim()}>
+                    <RotateCcw className="w-4 h-4 mr-2" />
+                    {t("aiHome.reset")}
+                  </Button>
+                )}
+
+                <div className="flex items-center gap-2">
+                  {profiles.length > 1 && (
+                    <Select value={profileId} onValueChange={setProfileId} disabled={isGenerating}>
+                      <SelectTrigger className="w-56" title={t("aiHome.profile")}>
+                        <SelectValue />
+                      </SelectTrigger>
+                      <SelectContent>
+                        {profiles.map((profile) => (
+                          <SelectItem key={profile.id} value={String(profile.id)}>
+                            {profile.name} · {profile.model_name}
+                          </SelectItem>
+                        ))}
+                      </SelectContent>
+                    </Select>
                   )}
-                  {isGenerating ? t("aiHome.generating") : t("aiHome.generate")}
-                </Button>
+                  <Button onClick={handleGenerate} disabled={!input.trim() || isGenerating}>
+                    {isGenerating ? (
+                      <Loader2 className="w-4 h-4 mr-2 animate-spin" />
+                    ) : (
+                      <Send className="w-4 h-4 mr-2" />
+                    )}
+                    {isGenerating ? t("aiHome.generating") : t("aiHome.generate")}
+                  </Button>
+                </div>
               </div>
             </div>
           )}
@@ -1,5 +1,5 @@
 import { useState, useEffect, useCallback, useRef } from "react";
-import { useParams, useNavigate } from "react-router-dom";
+import { useParams, useNavigate, useSearchParams } from "react-router-dom";
 import { useTranslation } from "react-i18next";
 import { invoke } from "@tauri-apps/api/core";
 import {
@@ -19,11 +19,18 @@ import {
   useReactFlow,
   ReactFlowProvider,
 } from "@xyflow/react";
-import { Play, FileText, FormInput, CircleStop, Hammer, PlayCircle, Eye, Edit3, X, ChevronLeft, ChevronRight } from "lucide-react";
+import { Play, FileText, FormInput, CircleStop, Hammer, PlayCircle, Eye, Edit3, X, ChevronLeft, ChevronRight, AlertTriangle, Plus, GitBranch, History, RotateCcw } from "lucide-react";
 import { Button } from "@/components/ui/button";
 import { Input } from "@/components/ui/input";
 import { Textarea } from "@/components/ui/textarea";
 import { useSidebar } from "@/components/ui/sidebar";
+import {
+  Select,
+  SelectContent,
+  SelectItem,
+  SelectTrigger,
+  SelectValue,
+} from "@/components/ui/select";
 import {
   Drawer,
   DrawerContent,
@@ -37,17 +44,90 @@ import remarkGfm from "remark-gfm";
 interface FlowData {
   id: number;
   sop_id: number;
-  nodes: string;
-  edges: string;
+  nodes: EditableNode[];
+  edges: ConditionalEdge[];
   created_at: string;
   updated_at: string;
 }
 
+interface FlowDiagnostic {
+  code: string;
+  severity: "error" | "warning";
+  message: string;
+  node_id?: string;
+  edge_id?: string;
+}
+
+interface FlowRevisionSummary {
+  id: number;
+  sop_id: number;
+  revision: number;
+  node_count: number;
+  edge_count: number;
+  restored_from: number | null;
+  created_at: string;
+}
+
+interface ItemChange<T> {
+  id: string;
+  before: T;
+  after: T;
+  changed: string[];
+}
+
+interface FlowDiff {
+  nodes_added: EditableNode[];
+  nodes_removed: EditableNode[];
+  nodes_changed: ItemChange<EditableNode>[];
+  edges_added: ConditionalEdge[];
+  edges_removed: ConditionalEdge[];
+  edges_changed: ItemChange<ConditionalEdge>[];
+}
+
+const FIELD_TYPES = ["text", "number", "checkbox", "select", "date", "file_path"] as const;
+
+type FormFieldType = (typeof FIELD_TYPES)[number];
+
+interface FormField {
+  id: string;
+  label: string;
+  type: FormFieldType;
+  required?: boolean;
+  options?: string[];
+}
+
+const CONDITION_OPS = ["eq", "ne", "gt", "gte", "lt", "lte", "contains", "is_empty", "is_not_empty"] as const;
+
+type ConditionOp = (typeof CONDITION_OPS)[number];
+
+const CONDITION_OP_SYMBOLS: Record<ConditionOp, string> = {
+  eq: "=",
+  ne: "≠",
+  gt: ">",
+  gte: "≥",
+  lt: "<",
+  lte: "≤",
+  contains: "∋",
+  is_empty: "= ∅",
+  is_not_empty: "≠ ∅",
+};
+
+interface EdgeCondition {
+  field: string;
+  op: ConditionOp;
+  value?: unknown;
+}
+
+type ConditionalEdge = Edge & { condition?: EdgeCondition };
+
+type NodeShape = "start" | "read" | "form" | "decision" | "end";
+
 interface EditableNodeData extends Record<string, unknown> {
   label: string;
-  shape: "start" | "read" | "form" | "end";
+  shape: NodeShape;
   config?: {
     content?: string;
+    fields?: FormField[];
   };
 }
 
@@ -59,6 +139,7 @@ function EditableNode({ data, selected }: NodeProps<EditableNode>) {
     start: "rounded-full bg-green-500/10 border-green-500",
     read: "rounded-lg bg-blue-500/10 border-blue-500",
     form: "rounded-lg bg-orange-500/10 border-orange-500",
+    decision: "rounded-lg border-dashed bg-yellow-500/10 border-yellow-500",
     end: "rounded-full bg-red-500/10 border-red-500",
   };
 
@@ -66,6 +147,7 @@ function EditableNode({ data, selected }: NodeProps<EditableNode>) {
     start: "w-[80px] h-[80px]",
     read: "min-w-[120px] min-h-[50px] px-4 py-2",
     form: "min-w-[120px] min-h-[50px] px-4 py-2",
+    decision: "min-w-[120px] min-h-[50px] px-4 py-2",
     end: "w-[80px] h-[80px]",
   };
 
@@ -103,15 +185,23 @@ function FlowDetailInner() {
   const { id } = useParams();
   const navigate = useNavigate();
   const sopId = Number(id);
+  // Node to select on load, e.g. when opened from a search result
+  const [searchParams] = useSearchParams();
+  const focusNodeId = searchParams.get("node");
   const { screenToFlowPosition, getViewport } = useReactFlow();
   const { setOpen: setSidebarOpen } = useSidebar();
 
   const [nodes, setNodes, onNodesChange] = useNodesState<EditableNode>([]);
-  const [edges, setEdges, onEdgesChange] = useEdgesState<Edge>([]);
+  const [edges, setEdges, onEdgesChange] = useEdgesState<ConditionalEdge>([]);
   const [isLoading, setIsLoading] = useState(true);
   const [isToolbarExpanded, setIsToolbarExpanded] = useState(false);
   const [selectedNode, setSelectedNode] = useState<EditableNode | null>(null);
+  const [selectedEdgeId, setSelectedEdgeId] = useState<string | null>(null);
   const [isContentEditing, setIsContentEditing] = useState(false);
+  const [diagnostics, setDiagnostics] = useState<FlowDiagnostic[]>([]);
+  const [isHistoryOpen, setIsHistoryOpen] = useState(false);
+  const [revisions, setRevisions] = useState<FlowRevisionSummary[]>([]);
+  const [revisionDiff, setRevisionDiff] = useState<{ revisionId: number; diff: FlowDiff } | null>(null);
   const saveTimeoutRef = useRef<NodeJS.Timeout | null>(null);
   const toolbarRef = useRef<HTMLDivElement>(null);
   const containerRef = useRef<HTMLDivElement>(null);
@@ -139,10 +229,12 @@ function FlowDetailInner() {
       try {
         const data = await invoke<FlowData | null>("get_flow_data", { sopId });
         if (data) {
-          const parsedNodes = JSON.parse(data.nodes) as EditableNode[];
-          const parsedEdges = JSON.parse(data.edges) as Edge[];
-          setNodes(parsedNodes);
-          setEdges(parsedEdges);
+          setNodes(data.nodes.map((node) => (focusNodeId ? { ...node, selected: node.id === focusNodeId } : node)));
+          setEdges(data.edges);
+          const focused = data.nodes.find((node) => node.id === focusNodeId);
+          if (focused && (focused.data.shape === "read" || focused.data.shape === "form")) {
+            setSelectedNode(focused);
+          }
         }
       } catch (error) {
         console.error("Failed to load flow data:", error);
@@ -152,16 +244,17 @@ function FlowDetailInner() {
     };
 
     loadFlowData();
-  }, [sopId]);
+  }, [sopId, focusNodeId]);
 
   // Auto-save with debounce
   const saveFlowData = useCallback(async () => {
     try {
-      await invoke("save_flow_data", {
+      const result = await invoke<{ diagnostics: FlowDiagnostic[] }>("save_flow_data", {
         sopId,
-        nodes: JSON.stringify(nodes),
-        edges: JSON.stringify(edges),
+        nodes,
+        edges,
       });
+      setDiagnostics(result.diagnostics);
     } catch (error) {
       console.error("Failed to save flow data:", error);
     }
@@ -185,6 +278,46 @@ function FlowDetailInner() {
     };
   }, [nodes, edges, saveFlowData, isLoading]);
 
+  const loadRevisions = useCallback(async () => {
+    try {
+      setRevisions(await invoke<FlowRevisionSummary[]>("list_flow_revisions", { sopId }));
+    } catch (error) {
+      console.error("Failed to load flow revisions:", error);
+    }
+  }, [sopId]);
+
+  const toggleHistory = () => {
+    if (!isHistoryOpen) {
+      loadRevisions();
+    }
+    setRevisionDiff(null);
+    setIsHistoryOpen(!isHistoryOpen);
+  };
+
+  // Compare a revision with the flow as it is now
+  const compareRevision = async (revisionId: number) => {
+    try {
+      const diff = await invoke<FlowDiff>("diff_flow_revisions", { fromRevisionId: revisionId });
+      setRevisionDiff({ revisionId, diff });
+    } catch (error) {
+      console.error("Failed to diff flow revision:", error);
+    }
+  };
+
+  const restoreRevision = async (revisionId: number) => {
+    try {
+      const data = await invoke<FlowData>("restore_flow_revision", { revisionId });
+      setNodes(data.nodes);
+      setEdges(data.edges);
+      setSelectedNode(null);
+      setSelectedEdgeId(null);
+      setRevisionDiff(null);
+      await loadRevisions();
+    } catch (error) {
+      console.error("Failed to restore flow revision:", error);
+    }
+  };
+
   // Get node center Y position based on shape
   const getNodeCenterY = (node: EditableNode) => {
     const heights: Record<string, number> = {
@@ -192,6 +325,7 @@ function FlowDetailInner() {
       end: 80,
       read: 50,
       form: 50,
+      decision: 50,
     };
     const height = heights[node.data.shape] || 50;
     return node.position.y + height / 2;
@@ -204,6 +338,7 @@ function FlowDetailInner() {
       end: 80,
       read: 50,
       form: 50,
+      decision: 50,
     };
     return heights[shape] || 50;
   };
@@ -297,7 +432,7 @@ function FlowDetailInner() {
     }
   }, [nodes]);
 
-  const addNode = (shape: "start" | "read" | "form" | "end") => {
+  const addNode = (shape: NodeShape) => {
     // Calculate center position in flow coordinates using viewport
     const { x, y, zoom } = getViewport();
     const containerWidth = containerRef.current?.clientWidth || 800;
@@ -311,6 +446,7 @@ function FlowDetailInner() {
       start: t('flowDetail.startNode'),
       read: t('flowDetail.readNode'),
       form: t('flowDetail.formNode'),
+      decision: t('flowDetail.decisionNode'),
       end: t('flowDetail.endNode'),
     };
 
@@ -333,7 +469,7 @@ function FlowDetailInner() {
     (event: React.DragEvent) => {
       event.preventDefault();
 
-      const shape = event.dataTransfer.getData("application/reactflow-shape") as "start" | "read" | "form" | "end";
+      const shape = event.dataTransfer.getData("application/reactflow-shape") as NodeShape;
       if (!shape) return;
 
       const position = screenToFlowPosition({
@@ -345,6 +481,7 @@ function FlowDetailInner() {
         start: t('flowDetail.startNode'),
         read: t('flowDetail.readNode'),
         form: t('flowDetail.formNode'),
+        decision: t('flowDetail.decisionNode'),
         end: t('flowDetail.endNode'),
       };
 
@@ -361,7 +498,7 @@ function FlowDetailInner() {
     [screenToFlowPosition, t, setNodes]
   );
 
-  const onDragStart = (event: React.DragEvent, shape: "start" | "read" | "form" | "end") => {
+  const onDragStart = (event: React.DragEvent, shape: NodeShape) => {
     event.dataTransfer.setData("application/reactflow-shape", shape);
     event.dataTransfer.effectAllowed = "move";
   };
@@ -374,10 +511,41 @@ function FlowDetailInner() {
     }
   }, [setSidebarOpen]);
 
+  const onEdgeClick = useCallback((_: React.MouseEvent, edge: ConditionalEdge) => {
+    setSelectedEdgeId(edge.id);
+  }, []);
+
   const onPaneClick = useCallback(() => {
     setSelectedNode(null);
+    setSelectedEdgeId(null);
   }, []);
 
+  // Fields any form node collects; edge conditions can test each of them
+  const conditionFields = nodes.flatMap((node) => node.data.config?.fields || []);
+  const selectedEdge = edges.find((edge) => edge.id === selectedEdgeId) || null;
+
+  // Store the condition on the edge and show it as the edge label
+  const updateEdgeCondition = (edgeId: string, condition: EdgeCondition | undefined) => {
+    let label: string | undefined;
+    if (condition) {
+      const field = conditionFields.find((f) => f.id === condition.field);
+      const name = field?.label || condition.field;
+      const hasValue = condition.op !== "is_empty" && condition.op !== "is_not_empty";
+      label = `${name} ${CONDITION_OP_SYMBOLS[condition.op]}${hasValue ? ` ${String(condition.value ?? "")}` : ""}`;
+    }
+    setEdges((eds) =>
+      eds.map((edge) => (edge.id === edgeId ? { ...edge, condition, label } : edge))
+    );
+  };
+
+  // Keep the condition value typed like the field it tests
+  const parseConditionValue = (fieldId: string, raw: string): unknown => {
+    const field = conditionFields.find((f) => f.id === fieldId);
+    if (field?.type === "number") return raw === "" ? null : Number(raw);
+    if (field?.type === "checkbox") return raw === "true";
+    return raw;
+  };
+
   const updateNodeData = (nodeId: string, newLabel: string) => {
     setNodes((nds) =>
       nds.map((node) =>
@@ -410,6 +578,44 @@ function FlowDetailInner() {
     );
   };
 
+  const updateNodeFields = (nodeId: string, fields: FormField[]) => {
+    setNodes((nds) =>
+      nds.map((node) =>
+        node.id === nodeId
+          ? { ...node, data: { ...node.data, config: { ...node.data.config, fields } } }
+          : node
+      )
+    );
+    setSelectedNode((prev) =>
+      prev && prev.id === nodeId
+        ? { ...prev, data: { ...prev.data, config: { ...prev.data.config, fields } } }
+        : prev
+    );
+  };
+
+  const selectedFields = selectedNode?.data.config?.fields || [];
+
+  const addField = () => {
+    if (!selectedNode) return;
+    updateNodeFields(selectedNode.id, [
+      ...selectedFields,
+      { id: `field-${Date.now()}`, label: t('flowDetail.newField'), type: "text" },
+    ]);
+  };
+
+  const updateField = (fieldId: string, changes: Partial<FormField>) => {
+    if (!selectedNode) return;
+    updateNodeFields(
+      selectedNode.id,
+      selectedFields.map((field) => (field.id === fieldId ? { ...field, ...changes } : field))
+    );
+  };
+
+  const removeField = (fieldId: string) => {
+    if (!selectedNode) return;
+    updateNodeFields(selectedNode.id, selectedFields.filter((field) => field.id !== fieldId));
+  };
+
   // Get editable nodes (read and form only)
   const editableNodes = nodes.filter(
     (n) => n.data.shape === "read" || n.data.shape === "form"
@@ -467,6 +673,7 @@ function FlowDetailInner() {
         onDragOver={onDragOver}
         onDrop={onDrop}
         onNodeClick={onNodeClick}
+        onEdgeClick={onEdgeClick}
         onPaneClick={onPaneClick}
         nodeTypes={nodeTypes}
         fitView
@@ -478,6 +685,104 @@ function FlowDetailInner() {
         <Controls className="!bg-background !border-border !shadow-md" />
       </ReactFlow>
 
+      {/* Validation issues */}
+      {diagnostics.length > 0 && (
+        <div
+          className="absolute top-4 left-4 flex items-center gap-2 px-3 py-1.5 rounded-full border border-border bg-background/95 shadow-md text-sm"
+          title={diagnostics.map((d) => d.message).join("\n")}
+        >
+          <AlertTriangle
+            className={`w-4 h-4 ${diagnostics.some((d) => d.severity === "error") ? "text-red-500" : "text-yellow-500"}`}
+          />
+          <span>{t('flowDetail.issues', { count: diagnostics.length })}</span>
+        </div>
+      )}
+
+      {/* Edge condition editor */}
+      {selectedEdge && (
+        <div className="absolute top-4 right-4 w-72 flex flex-col gap-2 bg-background/95 backdrop-blur-sm border border-border rounded-lg p-3 shadow-lg">
+          <div className="flex items-center justify-between">
+            <span className="text-xs font-medium text-muted-foreground">{t('flowDetail.edgeCondition')}</span>
+            <button
+              onClick={() => setSelectedEdgeId(null)}
+              className="text-muted-foreground hover:text-foreground transition-colors"
+            >
+              <X className="w-4 h-4" />
+            </button>
+          </div>
+          {conditionFields.length === 0 ? (
+            <p className="text-xs text-muted-foreground">{t('flowDetail.noConditionFields')}</p>
+          ) : (
+            <>
+              <Select
+                value={selectedEdge.condition?.field ?? ""}
+                onValueChange={(field) =>
+                  updateEdgeCondition(selectedEdge.id, {
+                    field,
+                    op: selectedEdge.condition?.op ?? "eq",
+                    value: selectedEdge.condition?.value,
+                  })
+                }
+              >
+                <SelectTrigger className="w-full">
+                  <SelectValue placeholder={t('flowDetail.conditionField')} />
+                </SelectTrigger>
+                <SelectContent>
+                  {conditionFields.map((field) => (
+                    <SelectItem key={field.id} value={field.id}>
+                      {field.label || field.id}
+                    </SelectItem>
+                  ))}
+                </SelectContent>
+              </Select>
+              {selectedEdge.condition && (
+                <div className="flex gap-2">
+                  <Select
+                    value={selectedEdge.condition.op}
+                    onValueChange={(op) =>
+                      selectedEdge.condition &&
+                      updateEdgeCondition(selectedEdge.id, { ...selectedEdge.condition, op: op as ConditionOp })
+                    }
+                  >
+                    <SelectTrigger className="w-28">
+                      <SelectValue />
+                    </SelectTrigger>
+                    <SelectContent>
+                      {CONDITION_OPS.map((op) => (
+                        <SelectItem key={op} value={op}>
+                          {t(`flowDetail.conditionOps.${op}`)}
+                        </SelectItem>
+                      ))}
+                    </SelectContent>
+                  </Select>
+                  {selectedEdge.condition.op !== "is_empty" && selectedEdge.condition.op !== "is_not_empty" && (
+                    <Input
+                      value={selectedEdge.condition.value === undefined || selectedEdge.condition.value === null ? "" : String(selectedEdge.condition.value)}
+                      onChange={(e) =>
+                        selectedEdge.condition &&
+                        updateEdgeCondition(selectedEdge.id, {
+                          ...selectedEdge.condition,
+                          value: parseConditionValue(selectedEdge.condition.field, e.target.value),
+                        })
+                      }
+                      placeholder={t('flowDetail.conditionValue')}
+                      className="flex-1"
+                    />
+                  )}
+                </div>
+              )}
+            </>
+          )}
+          {selectedEdge.condition ? (
+            <Button variant="outline" size="sm" onClick={() => updateEdgeCondition(selectedEdge.id, undefined)}>
+              {t('flowDetail.clearCondition')}
+            </Button>
+          ) : (
+            <p className="text-xs text-muted-foreground">{t('flowDetail.defaultBranch')}</p>
+          )}
+        </div>
+      )}
+
       {/* Toolbar */}
       <div className="absolute bottom-4 right-4 flex flex-col items-end gap-2">
         {isToolbarExpanded && (
@@ -518,6 +823,15 @@ function FlowDetailInner() {
               <FormInput className="w-4 h-4 text-orange-500" />
               <span className="text-sm">{t('flowDetail.formNode')}</span>
             </div>
+            <div
+              draggable
+              onDragStart={(e) => onDragStart(e, "decision")}
+              onClick={() => addNode("decision")}
+              className="flex items-center gap-3 h-9 px-3 rounded-md cursor-grab hover:bg-accent transition-colors"
+            >
+              <GitBranch className="w-4 h-4 text-yellow-500" />
+              <span className="text-sm">{t('flowDetail.decisionNode')}</span>
+            </div>
             <div
               draggable
               onDragStart={(e) => onDragStart(e, "end")}
@@ -529,7 +843,75 @@ function FlowDetailInner() {
             </div>
           </div>
         )}
+        {isHistoryOpen && (
+          <div className="w-80 flex flex-col gap-1 bg-background/95 backdrop-blur-sm border border-border rounded-lg p-3 shadow-lg animate-in fade-in slide-in-from-bottom-2 duration-200">
+            <div className="flex items-center justify-between mb-1">
+              <span className="text-xs font-medium text-muted-foreground">{t('flowDetail.history')}</span>
+              <button
+                onClick={() => setIsHistoryOpen(false)}
+                className="text-muted-foreground hover:text-foreground transition-colors"
+              >
+                <X className="w-4 h-4" />
+              </button>
+            </div>
+            <div className="max-h-80 overflow-y-auto flex flex-col gap-1">
+              {revisions.length === 0 && (
+                <p className="text-xs text-muted-foreground">{t('flowDetail.noRevisions')}</p>
+              )}
+              {revisions.map((revision, index) => (
+                <div key={revision.id} className="flex flex-col gap-1 px-2 py-1.5 rounded-md hover:bg-accent/50">
+                  <div className="flex items-center gap-2">
+                    <span className="text-sm font-medium">#{revision.revision}</span>
+                    <span className="text-xs text-muted-foreground flex-1 truncate">
+                      {new Date(revision.created_at).toLocaleString()}
+                    </span>
+                    {index === 0 ? (
+                      <span className="text-xs text-muted-foreground">{t('flowDetail.currentRevision')}</span>
+                    ) : (
+                      <>
+                        <Button variant="ghost" size="sm" className="h-6 px-2 text-xs" onClick={() => compareRevision(revision.id)}>
+                          {t('flowDetail.compare')}
+                        </Button>
+                        <Button
+                          variant="ghost"
+                          size="icon"
+                          className="h-6 w-6"
+                          title={t('flowDetail.restoreRevision')}
+                          onClick={() => restoreRevision(revision.id)}
+                        >
+                          <RotateCcw className="w-3.5 h-3.5" />
+                        </Button>
+                      </>
+                    )}
+                  </div>
+                  <span className="text-xs text-muted-foreground">
+                    {t('flowDetail.revisionSize', { nodes: revision.node_count, edges: revision.edge_count })}
+                    {revision.restored_from !== null && ` · ${t('flowDetail.restoredFrom', { revision: revision.restored_from })}`}
+                  </span>
+                  {revisionDiff?.revisionId === revision.id && (
+                    <span className="text-xs">
+                      {t('flowDetail.diffSummary', {
+                        added: revisionDiff.diff.nodes_added.length + revisionDiff.diff.edges_added.length,
+                        removed: revisionDiff.diff.nodes_removed.length + revisionDiff.diff.edges_removed.length,
+                        changed: revisionDiff.diff.nodes_changed.length + revisionDiff.diff.edges_changed.length,
+                      })}
+                    </span>
+                  )}
+                </div>
+              ))}
+            </div>
+          </div>
+        )}
         <div className="flex gap-2">
+          <Button
+            variant="outline"
+            size="icon"
+            className="h-10 w-10 rounded-full shadow-lg"
+            onClick={toggleHistory}
+            title={t('flowDetail.history')}
+          >
+            <History className="w-5 h-5" />
+          </Button>
           <Button
             variant="outline"
             size="icon"
@@ -616,6 +998,73 @@ function FlowDetailInner() {
                 </div>
               )}
             </div>
+            {selectedNode?.data.shape === "form" && (
+              <div className="space-y-2">
+                <div className="flex items-center justify-between">
+                  <label className="text-sm font-medium text-foreground">
+                    {t('flowDetail.fields')}
+                  </label>
+                  <Button variant="outline" size="sm" onClick={addField}>
+                    <Plus className="w-4 h-4 mr-1" />
+                    {t('flowDetail.addField')}
+                  </Button>
+                </div>
+                {selectedFields.map((field) => (
+                  <div key={field.id} className="space-y-2 p-2 rounded-md border border-border">
+                    <div className="flex items-center gap-2">
+                      <Input
+                        value={field.label}
+                        onChange={(e) => updateField(field.id, { label: e.target.value })}
+                        placeholder={t('flowDetail.fieldLabel')}
+                        className="flex-1"
+                      />
+                      <Select
+                        value={field.type}
+                        onValueChange={(value) => updateField(field.id, { type: value as FormFieldType })}
+                      >
+                        <SelectTrigger className="w-32">
+                          <SelectValue />
+                        </SelectTrigger>
+                        <SelectContent>
+                          {FIELD_TYPES.map((type) => (
+                            <SelectItem key={type} value={type}>
+                              {t(`flowDetail.fieldTypes.${type}`)}
+                            </SelectItem>
+                          ))}
+                        </SelectContent>
+                      </Select>
+                      <label className="flex items-center gap-1 text-xs text-muted-foreground whitespace-nowrap">
+                        <input
+                          type="checkbox"
+                          checked={!!field.required}
+                          onChange={(e) => updateField(field.id, { required: e.target.checked })}
+                        />
+                        {t('flowDetail.fieldRequired')}
+                      </label>
+                      <Button
+                        variant="ghost"
+                        size="icon"
+                        className="h-7 w-7"
+                        onClick={() => removeField(field.id)}
+                      >
+                        <X className="w-4 h-4" />
+                      </Button>
+                    </div>
+                    {field.type === "select" && (
+                      <Input
+                        value={(field.options || []).join(", ")}
+                        onChange={(e) =>
+                          updateField(field.id, {
+                            options: e.target.value.split(",").map((option) => option.trimStart()),
+                          })
+                        }
+                        placeholder={t('flowDetail.fieldOptions')}
+                      />
+                    )}
+                  </div>
+                ))}
+              </div>
+            )}
           </div>
           <DrawerFooter className="border-t border-border">
             <div className="flex justify-end gap-2">
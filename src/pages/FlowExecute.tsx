@@ -1,9 +1,18 @@
-import { useState, useEffect, useCallback, useMemo } from "react";
+import { useState, useEffect, useCallback, useMemo, useRef } from "react";
 import { useParams, useNavigate } from "react-router-dom";
 import { useTranslation } from "react-i18next";
 import { invoke } from "@tauri-apps/api/core";
-import { X, ChevronRight, ChevronLeft, Check, Play, FileText, FormInput, CircleStop, List, CheckCircle2, Pin, PinOff } from "lucide-react";
+import { errorMessage, isZopError } from "@/lib/errors";
+import { X, ChevronRight, ChevronLeft, Check, Play, FileText, FormInput, CircleStop, List, CheckCircle2, Pin, PinOff, RotateCcw } from "lucide-react";
 import { Button } from "@/components/ui/button";
+import { Input } from "@/components/ui/input";
+import {
+  Select,
+  SelectContent,
+  SelectItem,
+  SelectTrigger,
+  SelectValue,
+} from "@/components/ui/select";
 import {
   DropdownMenu,
   DropdownMenuContent,
@@ -15,17 +24,80 @@ import { Node, Edge } from "@xyflow/react";
 interface FlowData {
   id: number;
   sop_id: number;
-  nodes: string;
-  edges: string;
+  nodes: EditableNode[];
+  edges: Edge[];
   created_at: string;
   updated_at: string;
 }
 
+interface FormField {
+  id: string;
+  label: string;
+  type: "text" | "number" | "checkbox" | "select" | "date" | "file_path";
+  required?: boolean;
+  options?: string[];
+}
+
+interface FieldError {
+  field_id: string;
+  message: string;
+}
+
+interface FlowRun {
+  id: number;
+  sop_id: number;
+  revision_id: number | null;
+  operator: string | null;
+  status: "active" | "completed" | "abandoned";
+  current_node_id: string | null;
+  draft_values: Record<string, unknown> | null;
+  started_at: string;
+  updated_at: string;
+  finished_at: string | null;
+}
+
+interface FlowRevision {
+  id: number;
+  nodes: EditableNode[];
+  edges: Edge[];
+}
+
+interface FlowRunStep {
+  id: number;
+  run_id: number;
+  seq: number;
+  node_id: string;
+  node_label: string;
+  node_shape: string;
+  entered_at: string;
+  reverted_at: string | null;
+}
+
+interface FlowRunPath {
+  visited: FlowRunStep[];
+  upcoming: EditableNode[];
+}
+
+interface ActiveFlowRun {
+  run: FlowRun;
+  sop_name: string;
+  current_node_label: string | null;
+}
+
+interface FlowDiagnostic {
+  code: string;
+  severity: "error" | "warning";
+  message: string;
+  node_id?: string;
+  edge_id?: string;
+}
+
 interface EditableNodeData extends Record<string, unknown> {
   label: string;
-  shape: "start" | "read" | "form" | "end";
+  shape: "start" | "read" | "form" | "decision" | "end";
   config?: {
     content?: string;
+    fields?: FormField[];
   };
 }
 
@@ -40,10 +112,24 @@ export default function FlowExecute() {
   const [nodes, setNodes] = useState<EditableNode[]>([]);
   const [edges, setEdges] = useState<Edge[]>([]);
   const [isLoading, setIsLoading] = useState(true);
-  const [currentNodeIndex, setCurrentNodeIndex] = useState(0);
   const [isCompleted, setIsCompleted] = useState(false);
   const [isTocOpen, setIsTocOpen] = useState(true); // Default open
   const [isAlwaysOnTop, setIsAlwaysOnTop] = useState(false);
+  const [blockingIssues, setBlockingIssues] = useState<FlowDiagnostic[]>([]);
+  const [run, setRun] = useState<FlowRun | null>(null);
+  const [formValues, setFormValues] = useState<Record<string, unknown>>({});
+  const [fieldErrors, setFieldErrors] = useState<FieldError[]>([]);
+  const [path, setPath] = useState<FlowRunPath | null>(null);
+  const [advanceError, setAdvanceError] = useState<string | null>(null);
+  const draftTimeoutRef = useRef<NodeJS.Timeout | null>(null);
+
+  // Show the revision a run was started on, even if the flow was edited since
+  const loadRunGraph = useCallback(async (flowRun: FlowRun) => {
+    if (flowRun.revision_id === null) return;
+    const revision = await invoke<FlowRevision>("get_flow_revision", { revisionId: flowRun.revision_id });
+    setNodes(revision.nodes);
+    setEdges(revision.edges);
+  }, []);
 
   // Load flow data
   useEffect(() => {
@@ -51,10 +137,22 @@ export default function FlowExecute() {
       try {
         const data = await invoke<FlowData | null>("get_flow_data", { sopId });
         if (data) {
-          const parsedNodes = JSON.parse(data.nodes) as EditableNode[];
-          const parsedEdges = JSON.parse(data.edges) as Edge[];
-          setNodes(parsedNodes);
-          setEdges(parsedEdges);
+          setNodes(data.nodes);
+          setEdges(data.edges);
+        }
+        const diagnostics = await invoke<FlowDiagnostic[]>("validate_flow", { sopId });
+        const errors = diagnostics.filter((d) => d.severity === "error");
+        setBlockingIssues(errors);
+        if (data && errors.length === 0) {
+          // Pick up an unfinished run of this flow instead of starting over
+          const activeRuns = await invoke<ActiveFlowRun[]>("get_active_runs");
+          const active = activeRuns.find((a) => a.run.sop_id === sopId);
+          const flowRun = active ? active.run : await invoke<FlowRun>("start_flow_run", { sopId });
+          await loadRunGraph(flowRun);
+          setRun(flowRun);
+          if (active) {
+            setFormValues(active.run.draft_values ?? {});
+          }
         }
       } catch (error) {
         console.error("Failed to load flow data:", error);
@@ -64,81 +162,142 @@ export default function FlowExecute() {
     };
 
     loadFlowData();
-  }, [sopId]);
+  }, [sopId, loadRunGraph]);
 
-  // Compute execution order from start node
-  const executionOrder = useMemo(() => {
-    if (nodes.length === 0) return [];
-
-    // Find start node
-    const startNode = nodes.find(n => n.data.shape === "start");
-    if (!startNode) return nodes; // Fallback to all nodes if no start
+  // Steps taken so far and the ones the engine expects next; re-read whenever the run moves
+  useEffect(() => {
+    if (!run) return;
+    invoke<FlowRunPath>("get_flow_run_path", { runId: run.id })
+      .then(setPath)
+      .catch((error) => console.error("Failed to load run path:", error));
+  }, [run]);
 
-    const order: EditableNode[] = [];
-    const visited = new Set<string>();
+  const executionOrder = useMemo(() => {
+    if (!path) return [];
+    const visited = path.visited
+      .map((step) => nodes.find((n) => n.id === step.node_id))
+      .filter((node): node is EditableNode => node !== undefined);
+    return [...visited, ...path.upcoming];
+  }, [path, nodes]);
+
+  const currentNodeIndex = path ? path.visited.length - 1 : 0;
+  const currentNode = executionOrder[currentNodeIndex];
 
-    const traverse = (nodeId: string) => {
-      if (visited.has(nodeId)) return;
-      visited.add(nodeId);
+  // Keep partial form inputs so the run can be resumed after a restart
+  useEffect(() => {
+    if (!run || run.status !== "active" || Object.keys(formValues).length === 0) return;
 
-      const node = nodes.find(n => n.id === nodeId);
-      if (!node) return;
+    if (draftTimeoutRef.current) {
+      clearTimeout(draftTimeoutRef.current);
+    }
 
-      order.push(node);
+    draftTimeoutRef.current = setTimeout(async () => {
+      try {
+        await invoke("save_form_draft", { runId: run.id, values: formValues });
+      } catch (error) {
+        console.error("Failed to save form draft:", error);
+      }
+    }, 500);
 
-      // Find next nodes through edges
-      const outgoingEdges = edges.filter(e => e.source === nodeId);
-      for (const edge of outgoingEdges) {
-        traverse(edge.target);
+    return () => {
+      if (draftTimeoutRef.current) {
+        clearTimeout(draftTimeoutRef.current);
       }
     };
+  }, [formValues, run]);
 
-    traverse(startNode.id);
+  // Record progress in the run history; failures only affect the audit trail
+  const recordRunStep = useCallback(async (command: string, args: Record<string, unknown> = {}) => {
+    if (!run || run.status !== "active") return;
+    try {
+      setRun(await invoke<FlowRun>(command, { runId: run.id, ...args }));
+    } catch (error) {
+      console.error(`Failed to ${command}:`, error);
+    }
+  }, [run]);
 
-    // Add any unvisited nodes at the end
-    nodes.forEach(node => {
-      if (!visited.has(node.id)) {
-        order.push(node);
+  // Store the current form step's values; returns false if they were rejected
+  const submitFormValues = useCallback(async () => {
+    const fields = currentNode?.data.config?.fields || [];
+    if (!run || run.status !== "active" || currentNode?.data.shape !== "form" || fields.length === 0) {
+      return true;
+    }
+    try {
+      await invoke("submit_form_input", { runId: run.id, values: formValues });
+      setFieldErrors([]);
+      return true;
+    } catch (error) {
+      console.error("Failed to submit form input:", error);
+      // Rejected values come back with the per-field problems as details
+      if (isZopError(error) && error.code === "validation" && Array.isArray(error.details)) {
+        setFieldErrors(error.details as FieldError[]);
       }
-    });
-
-    return order;
-  }, [nodes, edges]);
-
-  const currentNode = executionOrder[currentNodeIndex];
+      return false;
+    }
+  }, [run, currentNode, formValues]);
 
-  const handleNext = useCallback(() => {
-    if (currentNodeIndex < executionOrder.length - 1) {
-      const nextIndex = currentNodeIndex + 1;
-      setCurrentNodeIndex(nextIndex);
+  const handleNext = useCallback(async () => {
+    if (!run || run.status !== "active") return;
+    if (!(await submitFormValues())) return;
+    try {
+      const next = await invoke<FlowRun>("advance_flow_run", { runId: run.id });
+      setFormValues({});
+      setAdvanceError(null);
+      setRun(next);
 
       // Check if we reached the end
-      if (executionOrder[nextIndex]?.data.shape === "end") {
+      if (nodes.find((n) => n.id === next.current_node_id)?.data.shape === "end") {
         setIsCompleted(true);
+        setRun(await invoke<FlowRun>("complete_flow_run", { runId: run.id }));
       }
+    } catch (error) {
+      console.error("Failed to advance flow run:", error);
+      const noBranch = isZopError(error) && (error.details as { kind?: string } | undefined)?.kind === "no_matching_branch";
+      setAdvanceError(noBranch ? t('flowExecute.noMatchingBranch') : errorMessage(error, t));
     }
-  }, [currentNodeIndex, executionOrder]);
+  }, [run, nodes, submitFormValues, t]);
 
-  const handlePrevious = useCallback(() => {
+  const handlePrevious = useCallback(async () => {
     if (currentNodeIndex > 0) {
-      setCurrentNodeIndex(currentNodeIndex - 1);
       setIsCompleted(false);
+      setFormValues({});
+      setFieldErrors([]);
+      setAdvanceError(null);
+      await recordRunStep("go_back_flow_run");
     }
-  }, [currentNodeIndex]);
+  }, [currentNodeIndex, recordRunStep]);
 
+  // Unfinished runs stay active so they can be resumed later
   const handleExit = useCallback(() => {
     navigate(`/flow/${sopId}`);
   }, [navigate, sopId]);
 
-  const handleJumpToStep = useCallback((index: number) => {
-    setCurrentNodeIndex(index);
-    // Check if we reached the end
-    if (executionOrder[index]?.data.shape === "end") {
-      setIsCompleted(true);
-    } else {
-      setIsCompleted(false);
+  const handleRestart = useCallback(async () => {
+    await recordRunStep("abandon_flow_run");
+    try {
+      const flowRun = await invoke<FlowRun>("start_flow_run", { sopId });
+      await loadRunGraph(flowRun);
+      setRun(flowRun);
+    } catch (error) {
+      console.error("Failed to start flow run:", error);
     }
-  }, [executionOrder]);
+    setIsCompleted(false);
+    setFormValues({});
+    setFieldErrors([]);
+    setAdvanceError(null);
+  }, [recordRunStep, sopId, loadRunGraph]);
+
+  const handleJumpToStep = useCallback(async (index: number) => {
+    if (!run || run.status !== "active") return;
+    let current = run;
+    for (let step = currentNodeIndex; step > index; step--) {
+      current = await invoke<FlowRun>("go_back_flow_run", { runId: run.id });
+    }
+    setRun(current);
+    setFormValues({});
+    setFieldErrors([]);
+    setAdvanceError(null);
+  }, [run, currentNodeIndex]);
 
   const handleToggleAlwaysOnTop = async () => {
     try {
@@ -149,6 +308,57 @@ export default function FlowExecute() {
     }
   };
 
+  const setFieldValue = (fieldId: string, value: unknown) => {
+    setFormValues((prev) => ({ ...prev, [fieldId]: value }));
+  };
+
+  const renderField = (field: FormField) => {
+    const value = formValues[field.id];
+    switch (field.type) {
+      case "checkbox":
+        return (
+          <input
+            type="checkbox"
+            checked={value === true}
+            onChange={(e) => setFieldValue(field.id, e.target.checked)}
+            className="w-4 h-4"
+          />
+        );
+      case "select":
+        return (
+          <Select value={(value as string) ?? undefined} onValueChange={(v) => setFieldValue(field.id, v)}>
+            <SelectTrigger className="w-full">
+              <SelectValue />
+            </SelectTrigger>
+            <SelectContent>
+              {(field.options || []).filter((option) => option.trim()).map((option) => (
+                <SelectItem key={option} value={option}>
+                  {option}
+                </SelectItem>
+              ))}
+            </SelectContent>
+          </Select>
+        );
+      case "number":
+        return (
+          <Input
+            type="number"
+            value={value === undefined || value === null ? "" : String(value)}
+            onChange={(e) => setFieldValue(field.id, e.target.value === "" ? null : Number(e.target.value))}
+          />
+        );
+      default:
+        return (
+          <Input
+            type={field.type === "date" ? "date" : "text"}
+            value={(value as string) ?? ""}
+            onChange={(e) => setFieldValue(field.id, e.target.value)}
+            placeholder={field.type === "file_path" ? t('flowExecute.filePathPlaceholder') : undefined}
+          />
+        );
+    }
+  };
+
   const getNodeIcon = (shape: string) => {
     switch (shape) {
       case "start":
@@ -202,6 +412,23 @@ export default function FlowExecute() {
     );
   }
 
+  if (executionOrder.length > 0 && blockingIssues.length > 0) {
+    return (
+      <div className="h-full flex flex-col items-center justify-center bg-background gap-4 p-8">
+        <p className="text-foreground font-medium">{t('flowExecute.invalidFlow')}</p>
+        <ul className="text-sm text-muted-foreground list-disc space-y-1 max-w-lg">
+          {blockingIssues.map((issue, index) => (
+            <li key={index}>{issue.message}</li>
+          ))}
+        </ul>
+        <Button variant="outline" onClick={handleExit}>
+          <X className="w-4 h-4 mr-2" />
+          {t('flowExecute.exit')}
+        </Button>
+      </div>
+    );
+  }
+
   if (executionOrder.length === 0) {
     return (
       <div className="h-full flex flex-col items-center justify-center bg-background gap-4">
@@ -245,7 +472,7 @@ export default function FlowExecute() {
 
                 return (
                   <DropdownMenuItem
-                    key={node.id}
+                    key={`${node.id}-${index}`}
                     onClick={() => !isFuture && handleJumpToStep(index)}
                     className={`flex items-center gap-2 ${
                       isCurrent ? "bg-accent" : ""
@@ -279,6 +506,14 @@ export default function FlowExecute() {
           </div>
         </div>
         <div className="flex items-center gap-1">
+          <Button
+            variant="ghost"
+            size="icon"
+            onClick={handleRestart}
+            title={t('flowExecute.restart')}
+          >
+            <RotateCcw className="w-5 h-5" />
+          </Button>
           <Button
             variant="ghost"
             size="icon"
@@ -331,8 +566,29 @@ export default function FlowExecute() {
                   {currentNode.data.config.content}
                 </p>
               )}
+              {currentNode.data.shape === "form" && (currentNode.data.config?.fields || []).length > 0 && (
+                <div className="w-full space-y-4">
+                  {(currentNode.data.config?.fields || []).map((field) => {
+                    const error = fieldErrors.find((e) => e.field_id === field.id);
+                    return (
+                      <div key={field.id} className="space-y-1">
+                        <label className="text-sm font-medium text-foreground">
+                          {field.label}
+                          {field.required && <span className="text-red-500"> *</span>}
+                        </label>
+                        {renderField(field)}
+                        {error && <p className="text-xs text-red-500">{error.message}</p>}
+                      </div>
+                    );
+                  })}
+                </div>
+              )}
             </div>
 
+            {advanceError && (
+              <p className="text-sm text-red-500 text-center">{advanceError}</p>
+            )}
+
             {/* Node type hint */}
             <p className="text-sm text-muted-foreground">
               {currentNode.data.shape === "start" && t('flowExecute.startHint')}
@@ -349,7 +605,7 @@ export default function FlowExecute() {
         <Button
           variant="outline"
           onClick={handlePrevious}
-          disabled={currentNodeIndex === 0}
+          disabled={currentNodeIndex === 0 || run?.status !== "active"}
         >
           <ChevronLeft className="w-4 h-4 mr-2" />
           {t('flowExecute.previous')}
@@ -358,7 +614,7 @@ export default function FlowExecute() {
         {!isCompleted && (
           <Button
             onClick={handleNext}
-            disabled={currentNodeIndex === executionOrder.length - 1}
+            disabled={currentNode?.data.shape === "end"}
           >
             {t('flowExecute.next')}
             <ChevronRight className="w-4 h-4 ml-2" />
@@ -2,7 +2,7 @@ import { useState, useEffect } from "react";
 import { useNavigate } from "react-router-dom";
 import { useTranslation } from "react-i18next";
 import { invoke } from "@tauri-apps/api/core";
-import { Plus, Layers } from "lucide-react";
+import { Plus, Layers, PlayCircle, X } from "lucide-react";
 import { Button } from "@/components/ui/button";
 
 interface SopItem {
@@ -14,10 +14,21 @@ interface SopItem {
   updated_at: string;
 }
 
+interface ActiveFlowRun {
+  run: {
+    id: number;
+    sop_id: number;
+    updated_at: string;
+  };
+  sop_name: string;
+  current_node_label: string | null;
+}
+
 export default function Home() {
   const { t } = useTranslation();
   const navigate = useNavigate();
   const [items, setItems] = useState<SopItem[]>([]);
+  const [activeRuns, setActiveRuns] = useState<ActiveFlowRun[]>([]);
   const [isLoading, setIsLoading] = useState(true);
 
   useEffect(() => {
@@ -25,6 +36,7 @@ export default function Home() {
       try {
         const result = await invoke<SopItem[]>("get_all_sop_items");
         setItems(result);
+        setActiveRuns(await invoke<ActiveFlowRun[]>("get_active_runs"));
       } catch (error) {
         console.error("Failed to fetch items:", error);
       } finally {
@@ -39,6 +51,15 @@ export default function Home() {
     navigate("/new");
   };
 
+  const handleDiscardRun = async (runId: number) => {
+    try {
+      await invoke("abandon_flow_run", { runId });
+      setActiveRuns((runs) => runs.filter((r) => r.run.id !== runId));
+    } catch (error) {
+      console.error("Failed to abandon run:", error);
+    }
+  };
+
   if (isLoading) {
     return (
       <div className="h-full w-full flex items-center justify-center">
@@ -70,10 +91,43 @@ export default function Home() {
   }
 
   return (
-    <div className="h-full w-full flex items-center justify-center">
+    <div className="h-full w-full flex flex-col items-center justify-center gap-6 px-4">
       <div className="text-muted-foreground">
         {t('home.selectItem')}
       </div>
+      {activeRuns.length > 0 && (
+        <div className="w-full max-w-md space-y-2">
+          <h3 className="text-sm font-medium text-foreground">{t('home.resumeTitle')}</h3>
+          {activeRuns.map((active) => (
+            <div
+              key={active.run.id}
+              className="flex items-center gap-3 p-3 rounded-lg border border-border"
+            >
+              <div className="flex-1 min-w-0">
+                <p className="text-sm font-medium truncate">{active.sop_name}</p>
+                {active.current_node_label && (
+                  <p className="text-xs text-muted-foreground truncate">
+                    {t('home.resumeAt', { step: active.current_node_label })}
+                  </p>
+                )}
+              </div>
+              <Button size="sm" onClick={() => navigate(`/flow/${active.run.sop_id}/execute`)}>
+                <PlayCircle className="w-4 h-4 mr-1" />
+                {t('home.resume')}
+              </Button>
+              <Button
+                variant="ghost"
+                size="icon"
+                className="h-8 w-8"
+                onClick={() => handleDiscardRun(active.run.id)}
+                title={t('home.discardRun')}
+              >
+                <X className="w-4 h-4" />
+              </Button>
+            </div>
+          ))}
+        </div>
+      )}
     </div>
   );
 }
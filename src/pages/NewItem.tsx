@@ -1,7 +1,8 @@
-import { useState } from "react";
+import { useEffect, useState } from "react";
 import { useNavigate } from "react-router-dom";
 import { useTranslation } from "react-i18next";
 import { invoke } from "@tauri-apps/api/core";
+import { getCurrentWebview } from "@tauri-apps/api/webview";
 import {
   Folder,
   FileText,
@@ -20,6 +21,7 @@ import {
   Settings,
   Users,
   ChevronDown,
+  Upload,
   type LucideIcon,
 } from "lucide-react";
 import { Button } from "@/components/ui/button";
@@ -29,6 +31,7 @@ import {
   DropdownMenuContent,
   DropdownMenuTrigger,
 } from "@/components/ui/dropdown-menu";
+import { errorMessage } from "@/lib/errors";
 
 const ICONS: { name: string; Icon: LucideIcon }[] = [
   { name: "folder", Icon: Folder },
@@ -56,6 +59,36 @@ export default function NewItem() {
   const [selectedIcon, setSelectedIcon] = useState("folder");
   const [selectedType, setSelectedType] = useState("todo");
   const [isSubmitting, setIsSubmitting] = useState(false);
+  const [importPath, setImportPath] = useState("");
+  const [isImporting, setIsImporting] = useState(false);
+  const [importError, setImportError] = useState<string | null>(null);
+
+  // Dropping a file on the window fills in the import path
+  useEffect(() => {
+    const unlisten = getCurrentWebview().onDragDropEvent((event) => {
+      if (event.payload.type === "drop" && event.payload.paths.length > 0) {
+        setImportPath(event.payload.paths[0]);
+        setImportError(null);
+      }
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  const handleImport = async () => {
+    if (!importPath.trim()) return;
+    setIsImporting(true);
+    setImportError(null);
+    try {
+      const item = await invoke<{ id: number; item_type: string }>("import_sop", { path: importPath.trim() });
+      navigate(item.item_type === "todo" ? `/todo/${item.id}` : `/flow/${item.id}`);
+    } catch (error) {
+      setImportError(errorMessage(error, t));
+    } finally {
+      setIsImporting(false);
+    }
+  };
 
   const handleSubmit = async (e: React.FormEvent) => {
     e.preventDefault();
@@ -187,6 +220,31 @@ export default function NewItem() {
             </Button>
           </div>
         </form>
+
+        {/* Import */}
+        <div className="mt-10 pt-6 border-t border-border space-y-2">
+          <label className="text-sm font-medium text-foreground">{t('newItem.importTitle')}</label>
+          <p className="text-sm text-muted-foreground">{t('newItem.importDescription')}</p>
+          <div className="flex gap-2">
+            <Input
+              type="text"
+              placeholder={t('newItem.importPlaceholder')}
+              value={importPath}
+              onChange={(e) => setImportPath(e.target.value)}
+              className="flex-1"
+            />
+            <Button
+              type="button"
+              variant="outline"
+              onClick={handleImport}
+              disabled={!importPath.trim() || isImporting}
+            >
+              <Upload className="w-4 h-4" />
+              {isImporting ? t('newItem.importing') : t('newItem.import')}
+            </Button>
+          </div>
+          {importError && <p className="text-sm text-destructive">{importError}</p>}
+        </div>
       </div>
     </div>
   );
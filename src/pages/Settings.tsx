@@ -1,11 +1,80 @@
-import { useState } from "react";
+import { useEffect, useState } from "react";
+import { useNavigate } from "react-router-dom";
 import { useTranslation } from "react-i18next";
 import { invoke } from "@tauri-apps/api/core";
+import { Check, X } from "lucide-react";
+import { Button } from "@/components/ui/button";
+import { Input } from "@/components/ui/input";
+import { errorMessage } from "@/lib/errors";
+
+interface Workspace {
+  id: string;
+  name: string;
+  path: string;
+  created_at: string;
+  last_opened_at: string | null;
+}
+
+interface WorkspaceList {
+  active_id: string;
+  workspaces: Workspace[];
+}
 
 export default function Settings() {
   const { t } = useTranslation();
+  const navigate = useNavigate();
   const [isAlwaysOnTop, setIsAlwaysOnTop] = useState(false);
   const [theme, setTheme] = useState<"light" | "dark">("light");
+  const [workspaces, setWorkspaces] = useState<WorkspaceList | null>(null);
+  const [newWorkspaceName, setNewWorkspaceName] = useState("");
+  const [newWorkspaceLocation, setNewWorkspaceLocation] = useState("");
+  const [workspaceError, setWorkspaceError] = useState<string | null>(null);
+
+  useEffect(() => {
+    invoke<WorkspaceList>("list_workspaces")
+      .then(setWorkspaces)
+      .catch((error) => console.error("Failed to load workspaces:", error));
+  }, []);
+
+  async function createWorkspace() {
+    if (!newWorkspaceName.trim()) return;
+    try {
+      await invoke<Workspace>("create_workspace", {
+        name: newWorkspaceName,
+        location: newWorkspaceLocation.trim() || null,
+      });
+      setWorkspaces(await invoke<WorkspaceList>("list_workspaces"));
+      setNewWorkspaceName("");
+      setNewWorkspaceLocation("");
+      setWorkspaceError(null);
+    } catch (error) {
+      setWorkspaceError(errorMessage(error, t));
+    }
+  }
+
+  async function switchWorkspace(id: string) {
+    try {
+      await invoke<Workspace>("switch_workspace", { id });
+      // The item list and any open SOP belong to the previous workspace
+      navigate("/");
+    } catch (error) {
+      setWorkspaceError(errorMessage(error, t));
+    }
+  }
+
+  async function closeWorkspace(id: string) {
+    try {
+      const list = await invoke<WorkspaceList>("close_workspace", { id });
+      if (list.active_id !== workspaces?.active_id) {
+        navigate("/");
+        return;
+      }
+      setWorkspaces(list);
+      setWorkspaceError(null);
+    } catch (error) {
+      setWorkspaceError(errorMessage(error, t));
+    }
+  }
 
   async function toggleAlwaysOnTop() {
     try {
@@ -40,6 +109,64 @@ export default function Settings() {
         </div>
       </div>
 
+      <div className="bg-card rounded-xl p-6 shadow-sm border border-border">
+        <h2 className="text-lg font-semibold text-foreground mb-1">{t('settings.workspaces.title')}</h2>
+        <p className="text-sm text-muted-foreground mb-4">{t('settings.workspaces.description')}</p>
+        <div className="divide-y divide-border">
+          {workspaces?.workspaces.map((workspace) => {
+            const isActive = workspace.id === workspaces.active_id;
+            return (
+              <div key={workspace.id} className="flex items-center justify-between gap-4 py-3">
+                <div className="min-w-0">
+                  <h3 className="font-medium text-foreground flex items-center gap-2">
+                    {workspace.name}
+                    {isActive && <Check className="w-4 h-4 text-primary" />}
+                  </h3>
+                  <p className="text-xs text-muted-foreground truncate" title={workspace.path}>
+                    {workspace.path}
+                  </p>
+                </div>
+                <div className="flex items-center gap-2 shrink-0">
+                  {!isActive && (
+                    <Button variant="outline" size="sm" onClick={() => switchWorkspace(workspace.id)}>
+                      {t('settings.workspaces.switch')}
+                    </Button>
+                  )}
+                  {workspace.id !== "default" && (
+                    <Button
+                      variant="ghost"
+                      size="sm"
+                      title={t('settings.workspaces.close')}
+                      onClick={() => closeWorkspace(workspace.id)}
+                    >
+                      <X className="w-4 h-4" />
+                    </Button>
+                  )}
+                </div>
+              </div>
+            );
+          })}
+        </div>
+        <div className="flex items-center gap-2 pt-4">
+          <Input
+            value={newWorkspaceName}
+            onChange={(e) => setNewWorkspaceName(e.target.value)}
+            placeholder={t('settings.workspaces.namePlaceholder')}
+            className="w-48"
+          />
+          <Input
+            value={newWorkspaceLocation}
+            onChange={(e) => setNewWorkspaceLocation(e.target.value)}
+            placeholder={t('settings.workspaces.locationPlaceholder')}
+            className="flex-1"
+          />
+          <Button onClick={createWorkspace} disabled={!newWorkspaceName.trim()}>
+            {t('common.create')}
+          </Button>
+        </div>
+        {workspaceError && <p className="text-sm text-destructive pt-2">{workspaceError}</p>}
+      </div>
+
       <div className="bg-card rounded-xl p-6 shadow-sm border border-border">
         <h2 className="text-lg font-semibold text-foreground mb-4">{t('settings.appearance.title')}</h2>
         <div className="flex items-center justify-between py-3">
@@ -2,7 +2,7 @@ import { useState, useEffect } from "react";
 import { useParams } from "react-router-dom";
 import { useTranslation } from "react-i18next";
 import { invoke } from "@tauri-apps/api/core";
-import { Plus, ListTodo, Square, CheckSquare, Trash2, GripVertical, Pencil, Check, X } from "lucide-react";
+import { Plus, ListTodo, Square, CheckSquare, Trash2, GripVertical, Pencil, Check, X, Undo2, Redo2 } from "lucide-react";
 import { Button } from "@/components/ui/button";
 import { Input } from "@/components/ui/input";
 import {
@@ -33,6 +33,12 @@ interface TodoItem {
   updated_at: string;
 }
 
+interface TodoListState {
+  items: TodoItem[];
+  can_undo: boolean;
+  can_redo: boolean;
+}
+
 interface SopItem {
   id: number;
   name: string;
@@ -179,6 +185,8 @@ export default function TodoDetail() {
   const [isLoading, setIsLoading] = useState(true);
   const [isAdding, setIsAdding] = useState(false);
   const [newContent, setNewContent] = useState("");
+  const [canUndo, setCanUndo] = useState(false);
+  const [canRedo, setCanRedo] = useState(false);
 
   const sensors = useSensors(
     useSensor(PointerSensor, {
@@ -191,10 +199,37 @@ export default function TodoDetail() {
     })
   );
 
+  const applyListState = (state: TodoListState) => {
+    setItems(state.items);
+    setCanUndo(state.can_undo);
+    setCanRedo(state.can_redo);
+  };
+
+  // Every change is recorded in the backend history, and a new one drops what could be redone
+  const markChanged = () => {
+    setCanUndo(true);
+    setCanRedo(false);
+  };
+
+  const handleUndo = async () => {
+    try {
+      applyListState(await invoke<TodoListState>("undo_todo", { sopId }));
+    } catch (error) {
+      console.error("Failed to undo todo change:", error);
+    }
+  };
+
+  const handleRedo = async () => {
+    try {
+      applyListState(await invoke<TodoListState>("redo_todo", { sopId }));
+    } catch (error) {
+      console.error("Failed to redo todo change:", error);
+    }
+  };
+
   const fetchItems = async () => {
     try {
-      const result = await invoke<TodoItem[]>("get_todo_items", { sopId });
-      setItems(result);
+      applyListState(await invoke<TodoListState>("get_todo_list_state", { sopId }));
     } catch (error) {
       console.error("Failed to fetch todo items:", error);
     } finally {
@@ -219,6 +254,25 @@ export default function TodoDetail() {
     fetchSopItem();
   }, [sopId]);
 
+  // Ctrl/Cmd+Z undoes, Ctrl/Cmd+Shift+Z or Ctrl+Y redoes, unless a text field has focus
+  useEffect(() => {
+    const handleShortcut = (e: KeyboardEvent) => {
+      const target = e.target as HTMLElement;
+      if (target.tagName === "INPUT" || target.tagName === "TEXTAREA" || !(e.ctrlKey || e.metaKey)) return;
+      const key = e.key.toLowerCase();
+      if (key === "z" && !e.shiftKey) {
+        e.preventDefault();
+        handleUndo();
+      } else if ((key === "z" && e.shiftKey) || key === "y") {
+        e.preventDefault();
+        handleRedo();
+      }
+    };
+
+    document.addEventListener("keydown", handleShortcut);
+    return () => document.removeEventListener("keydown", handleShortcut);
+  }, [sopId]);
+
   const handleAdd = async () => {
     if (!newContent.trim()) return;
 
@@ -230,6 +284,7 @@ export default function TodoDetail() {
         },
       });
       setItems([...items, newItem]);
+      markChanged();
       setNewContent("");
       setIsAdding(false);
     } catch (error) {
@@ -246,6 +301,7 @@ export default function TodoDetail() {
     try {
       const updatedItem = await invoke<TodoItem>("toggle_todo_item", { id: itemId });
       setItems(items.map((item) => (item.id === itemId ? updatedItem : item)));
+      markChanged();
     } catch (error) {
       console.error("Failed to toggle todo item:", error);
     }
@@ -255,6 +311,7 @@ export default function TodoDetail() {
     try {
       await invoke("delete_todo_item", { id: itemId });
       setItems(items.filter((item) => item.id !== itemId));
+      markChanged();
     } catch (error) {
       console.error("Failed to delete todo item:", error);
     }
@@ -264,6 +321,7 @@ export default function TodoDetail() {
     try {
       const updatedItem = await invoke<TodoItem>("update_todo_item", { id: itemId, content });
       setItems(items.map((item) => (item.id === itemId ? updatedItem : item)));
+      markChanged();
     } catch (error) {
       console.error("Failed to update todo item:", error);
     }
@@ -291,6 +349,7 @@ export default function TodoDetail() {
       try {
         const itemIds = newItems.map((item) => item.id);
         await invoke("reorder_todo_items", { itemIds });
+        markChanged();
       } catch (error) {
         console.error("Failed to reorder todo items:", error);
         // Revert on error
@@ -347,10 +406,18 @@ export default function TodoDetail() {
                 {t('todoDetail.emptyMessage')}
               </p>
             </div>
-            <Button onClick={() => setIsAdding(true)}>
-              <Plus className="w-4 h-4 mr-2" />
-              {t('todoDetail.newTask')}
-            </Button>
+            <div className="flex justify-center gap-2">
+              <Button onClick={() => setIsAdding(true)}>
+                <Plus className="w-4 h-4 mr-2" />
+                {t('todoDetail.newTask')}
+              </Button>
+              {canUndo && (
+                <Button variant="outline" onClick={handleUndo}>
+                  <Undo2 className="w-4 h-4 mr-2" />
+                  {t('todoDetail.undo')}
+                </Button>
+              )}
+            </div>
           </div>
         </div>
       )}
@@ -363,14 +430,36 @@ export default function TodoDetail() {
               <h2 className="text-lg font-semibold text-foreground">
                 {sopItem?.name || ""}
               </h2>
-              <Button
-                variant="outline"
-                size="sm"
-                onClick={() => setIsAdding(true)}
-              >
-                <Plus className="w-4 h-4 mr-2" />
-                {t('todoDetail.newTask')}
-              </Button>
+              <div className="flex items-center gap-1">
+                <Button
+                  variant="ghost"
+                  size="icon"
+                  className="h-8 w-8"
+                  onClick={handleUndo}
+                  disabled={!canUndo}
+                  title={t('todoDetail.undo')}
+                >
+                  <Undo2 className="w-4 h-4" />
+                </Button>
+                <Button
+                  variant="ghost"
+                  size="icon"
+                  className="h-8 w-8"
+                  onClick={handleRedo}
+                  disabled={!canRedo}
+                  title={t('todoDetail.redo')}
+                >
+                  <Redo2 className="w-4 h-4" />
+                </Button>
+                <Button
+                  variant="outline"
+                  size="sm"
+                  onClick={() => setIsAdding(true)}
+                >
+                  <Plus className="w-4 h-4 mr-2" />
+                  {t('todoDetail.newTask')}
+                </Button>
+              </div>
             </div>
           )}
           <DndContext